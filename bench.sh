@@ -26,7 +26,16 @@ echo '}}'
 
 echo -n '{"name": "defs", "n": 100000, "time": {'
 for j in $@; do
-  t=$($TIME $j -n -f <(for i in `seq 100000`; do echo "def a: 0;"; done; echo empty) 2>&1 > /dev/null)
+  t=$($TIME $j -n -f <(for i in `seq 100000`; do echo "def a$i: 0;"; done; echo empty) 2>&1 > /dev/null)
+  [ $? != 0 ] && t="N/A" # on error
+  [ $j != $1 ] && echo -n ', '
+  echo -n '"'$j'": ['$t']'
+done
+echo '}}'
+
+echo -n '{"name": "pretty", "n": 1048576, "time": {'
+for j in $@; do
+  t=$(echo 1048576 | $TIME $j '[range(.)]' 2>&1 > /dev/null)
   [ $? != 0 ] && t="N/A" # on error
   [ $j != $1 ] && echo -n ', '
   echo -n '"'$j'": ['$t']'
@@ -2,7 +2,7 @@
 
 use crate::load::{self, lex, parse};
 use crate::{ops, Bind, Filter};
-use alloc::{boxed::Box, string::String, vec::Vec};
+use alloc::{boxed::Box, collections::BTreeSet, string::String, vec::Vec};
 
 type NativeId = usize;
 type ModId = usize;
@@ -72,12 +72,32 @@ fn bind<T>(s: &str, x: T) -> Bind<T> {
     }
 }
 
-#[derive(Clone, Debug)]
+/// Convert a numeric literal such as `.5`, `1.`, or `1.e3` to a valid JSON number.
+///
+/// An empty integer or fractional part becomes `0`, so that
+/// `1.` stays a float like `1.0`.
+fn json_num(n: &str) -> String {
+    let (int, frac) = match n.split_once('.') {
+        Some(parts) => parts,
+        None => return n.into(),
+    };
+    let int = if int.is_empty() { "0" } else { int };
+    if frac.starts_with(|c: char| c.is_ascii_digit()) {
+        alloc::format!("{int}.{frac}")
+    } else {
+        alloc::format!("{int}.0{frac}")
+    }
+}
+
+#[derive(Clone, Debug, Default)]
 pub(crate) enum Term<T = TermId> {
     /// Identity (`.`)
+    #[default]
     Id,
     ToString,
 
+    Null,
+    Bool(bool),
     Int(isize),
     Num(String),
     Str(String),
@@ -93,6 +113,8 @@ pub(crate) enum Term<T = TermId> {
     /// Call to a filter (`filter`, `filter(…)`)
     CallDef(TermId, Box<[Bind<T>]>, VarSkip, Option<Tailrec>),
     Native(NativeId, Box<[Bind<T>]>),
+    /// Set the origin of errors yielded by `f` that have no origin yet
+    Origin(crate::exn::Origin, T),
 
     Label(T),
     Break(usize),
@@ -147,21 +169,19 @@ pub(crate) enum Term<T = TermId> {
     /// | ...
     /// | ., (xn as $x | f)...)
     /// ~~~
-    Fold(FoldType, T, T, T),
+    ///
+    /// `foreach xs as $x (init; f)` is like `for`, but omits `init`, and
+    /// `foreach xs as $x (init; f; g)` additionally runs `g` (with `$x` bound) on every output.
+    Fold(FoldType<T>, T, T, T),
 
     Path(T, crate::path::Path<T>),
 }
 
-impl<T> Default for Term<T> {
-    fn default() -> Self {
-        Self::Id
-    }
-}
-
 #[derive(Clone, Debug)]
-pub(crate) enum FoldType {
+pub(crate) enum FoldType<T = TermId> {
     Reduce,
-    Foreach,
+    /// `foreach`, with an optional extractor
+    Foreach(Option<T>),
     For,
 }
 
@@ -172,7 +192,7 @@ pub type Error<S> = (S, Undefined);
 pub type Errors<S> = load::Errors<S, Vec<Error<S>>>;
 
 /// Type of an undefined symbol.
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 #[non_exhaustive]
 pub enum Undefined {
     /// module
@@ -183,6 +203,15 @@ pub enum Undefined {
     Label,
     /// filter
     Filter(Arity),
+    /// path expression, such as on the left-hand side of `.a |= 1`
+    ///
+    /// This is yielded for terms that can never be paths, such as `1 = 2`.
+    Path,
+    /// filter that is defined more than once on the same level
+    ///
+    /// This is yielded for the second `f` in `def f: 1; def f: 2; f`,
+    /// but not for `def f: 1; def f(g): g; f`, nor for `def f: def f: 1; f; f`.
+    Duplicate(Arity),
 }
 
 impl Undefined {
@@ -193,11 +222,14 @@ impl Undefined {
             Self::Mod => "module",
             Self::Label => "label",
             Self::Filter(_arity) => "filter",
+            Self::Path => "path expression",
+            Self::Duplicate(_arity) => "definition",
         }
     }
 }
 
 /// jq program compiler.
+#[derive(Clone)]
 pub struct Compiler<S, F> {
     lut: Lut<(Sig<S>, F)>,
 
@@ -211,10 +243,13 @@ pub struct Compiler<S, F> {
     imported_vars: Vec<(S, ModId)>,
     local: Vec<Local<S>>,
 
+    /// file of the module that is currently compiled, used for `$__loc__`
+    file: load::File<S, String>,
+
     errs: Vec<Error<S>>,
 }
 
-impl<S, F> Default for Compiler<S, F> {
+impl<S: Default, F> Default for Compiler<S, F> {
     fn default() -> Self {
         Self {
             lut: Lut::default(),
@@ -224,6 +259,7 @@ impl<S, F> Default for Compiler<S, F> {
             global_vars: Vec::new(),
             imported_vars: Vec::new(),
             local: Vec::new(),
+            file: load::File::default(),
             errs: Vec::new(),
         }
     }
@@ -273,6 +309,9 @@ impl Def {
     }
 }
 
+/// Continuation of the compilation of a pattern, compiling the term in which its variables are bound.
+type Cont<'a, 's, F> = Box<dyn FnOnce(&mut Compiler<&'s str, F>) -> TermId + 'a>;
+
 #[derive(Clone, Debug)]
 enum Local<S> {
     Var(S),
@@ -282,6 +321,17 @@ enum Local<S> {
     TailrecObstacle,
 }
 
+impl<S> Local<S> {
+    /// Number of variables that are bound by this local.
+    fn vars(&self) -> usize {
+        match self {
+            Self::Var(_) => 1,
+            Self::Label(_) | Self::Sibling(..) | Self::TailrecObstacle => 0,
+            Self::Parent(sig, _def) => sig.args.len(),
+        }
+    }
+}
+
 impl<'s, F> Compiler<&'s str, F> {
     /// Supply functions with given signatures.
     pub fn with_funs(mut self, funs: impl IntoIterator<Item = (&'s str, Box<[Bind]>, F)>) -> Self {
@@ -303,8 +353,37 @@ impl<'s, F> Compiler<&'s str, F> {
         }
     }
 
-    /// Compile the given modules.
-    pub fn compile(mut self, mods: load::Modules<&'s str>) -> Result<Filter<F>, Errors<&'s str>> {
+    /// Compile the given modules, returning a compiler that can compile further modules.
+    ///
+    /// Subsequent calls to [`Self::compile`] (or this function)
+    /// skip all modules that have been compiled before, by position.
+    /// This allows compiling a set of definitions, such as the standard library, only once,
+    /// then compiling many filters that use these definitions, as done by a REPL.
+    ///
+    /// Example:
+    ///
+    /// ~~~
+    /// use jaq_core::load::{Arena, File, Loader};
+    /// use jaq_core::{Compiler, Native};
+    /// use jaq_json::Val;
+    ///
+    /// let defs = jaq_core::load::parse("def inc: . + 1;", |p| p.defs()).unwrap();
+    /// let arena = Arena::default();
+    /// let main = File { code: ".", path: "".into() };
+    /// let mut mods = Loader::new(defs).load(&arena, main).unwrap();
+    /// // compile only the prelude module, not the main module
+    /// mods.pop();
+    /// let prelude = Compiler::<_, Native<Val>>::default().with_mods(mods).unwrap();
+    ///
+    /// for (code, y) in [("inc", 1), ("inc | inc", 2)] {
+    ///     // the loaded prelude is empty here, because it has been compiled already
+    ///     let path = "".into();
+    ///     let mods = Loader::new([]).load(&arena, File { code, path }).unwrap();
+    ///     let filter = prelude.clone().compile(mods).unwrap();
+    ///     filter.yields(Val::from(0), core::iter::once(Ok(Val::from(y))));
+    /// }
+    /// ~~~
+    pub fn with_mods(mut self, mods: load::Modules<&'s str>) -> Result<Self, Errors<&'s str>> {
         self.imported_vars = mods
             .iter()
             .enumerate()
@@ -312,7 +391,8 @@ impl<'s, F> Compiler<&'s str, F> {
             .collect();
 
         let mut errs = Vec::new();
-        for (file, m) in mods {
+        for (file, m) in mods.into_iter().skip(self.mod_map.len()) {
+            self.file = file.clone();
             self.module(m);
             if !self.errs.is_empty() {
                 errs.push((file, core::mem::take(&mut self.errs)));
@@ -320,16 +400,22 @@ impl<'s, F> Compiler<&'s str, F> {
         }
 
         if errs.is_empty() {
-            // the main filter corresponds to the last definition of the last module
-            let (main_sig, main_def) = self.mod_map.last().unwrap().last().unwrap();
-            assert!(main_sig.matches("main", &[]));
-            assert!(!main_def.tailrec);
-            Ok(Filter(main_def.id, self.lut.map_funs(|(_sig, f)| f)))
+            Ok(self)
         } else {
             Err(errs)
         }
     }
 
+    /// Compile the given modules.
+    pub fn compile(self, mods: load::Modules<&'s str>) -> Result<Filter<F>, Errors<&'s str>> {
+        let c = self.with_mods(mods)?;
+        // the main filter corresponds to the last definition of the last module
+        let (main_sig, main_def) = c.mod_map.last().unwrap().last().unwrap();
+        assert!(main_sig.matches("main", &[]));
+        assert!(!main_def.tailrec);
+        Ok(Filter(main_def.id, c.lut.map_funs(|(_sig, f)| f)))
+    }
+
     fn with<T>(&mut self, local: Local<&'s str>, f: impl FnOnce(&mut Self) -> T) -> T {
         self.local.push(local.clone());
         let y = f(self);
@@ -347,7 +433,7 @@ impl<'s, F> Compiler<&'s str, F> {
             }
         }
 
-        m.body.into_iter().for_each(|def| self.def(def));
+        self.defs(m.body);
         let defs = self.local.drain(..).map(|l| match l {
             Local::Sibling(sig, def) => (sig, def),
             _ => panic!(),
@@ -355,6 +441,17 @@ impl<'s, F> Compiler<&'s str, F> {
         self.mod_map.push(defs.collect());
     }
 
+    /// Compile sibling definitions, failing on definitions with the same name and arity.
+    fn defs(&mut self, defs: Vec<parse::Def<&'s str, parse::Term<&'s str>>>) {
+        let mut sigs = BTreeSet::new();
+        for d in &defs {
+            if !sigs.insert((d.name, d.args.len())) {
+                self.errs.push((d.name, Undefined::Duplicate(d.args.len())));
+            }
+        }
+        defs.into_iter().for_each(|d| self.def(d));
+    }
+
     fn def(&mut self, d: parse::Def<&'s str, parse::Term<&'s str>>) {
         let tid = self.lut.insert_term(Term::Id);
 
@@ -403,13 +500,17 @@ impl<'s, F> Compiler<&'s str, F> {
             Id => Term::Id,
             Recurse => self.term(Call("!recurse", Vec::new())),
             Arr(t) => Term::Arr(self.iterm(t.map_or_else(|| Call("!empty", Vec::new()), |t| *t))),
-            Neg(t) => Term::Neg(self.iterm(*t)),
+            Neg(t) => match self.with(Local::TailrecObstacle, |c| c.term(*t)) {
+                Term::Int(i) if i.checked_neg().is_some() => Term::Int(-i),
+                t => Term::Neg(self.lut.insert_term(t)),
+            },
             Pipe(l, Some(x), r) => Term::Pipe(
                 self.iterm(*l),
                 true,
                 self.with(Local::Var(x), |c| c.iterm_tr(*r)),
             ),
             Pipe(l, None, r) => Term::Pipe(self.iterm(*l), false, self.iterm_tr(*r)),
+            Destruct(l, pats, r) => self.destruct(*l, pats, *r),
             Label(x, t) => Term::Label(self.with(Local::Label(x), |c| c.iterm(*t))),
             Break(x) => self.break_(x),
             IfThenElse(if_thens, else_) => {
@@ -433,37 +534,54 @@ impl<'s, F> Compiler<&'s str, F> {
             }
             Def(defs, t) => {
                 let defs_len = defs.len();
-                defs.into_iter().for_each(|d| self.def(d));
+                self.defs(defs);
                 let t = self.term(*t);
                 assert!((0..defs_len).all(|_| matches!(self.local.pop(), Some(Local::Sibling(..)))));
                 t
             }
-            Num(n) => n.parse().map_or_else(|_| Term::Num(n.into()), Term::Int),
+            Bool(b) => Term::Bool(b),
+            Null => Term::Null,
+            Num(n) => {
+                let n = json_num(n);
+                n.parse().map_or_else(|_| Term::Num(n), Term::Int)
+            }
             TryCatch(t, c) => Term::TryCatch(
                 self.iterm(*t),
                 self.iterm_tr(c.map_or_else(|| Call("!empty", Vec::new()), |c| *c)),
             ),
             Fold(name, xs, x, args) => {
                 let arity = args.len();
-                let fold = match name {
-                    "reduce" => FoldType::Reduce,
-                    "foreach" => FoldType::Foreach,
-                    "for" => FoldType::For,
-                    name => return self.fail(name, Undefined::Filter(arity)),
-                };
                 let mut args = args.into_iter();
-                let (init, update) = match (args.next(), args.next(), args.next()) {
-                    (Some(init), Some(update), None) => (init, update),
+                let (init, update, project) = match (name, args.next(), args.next(), args.next()) {
+                    ("reduce" | "for", Some(init), Some(update), None) => (init, update, None),
+                    ("foreach", Some(init), Some(update), project) if arity <= 3 => {
+                        (init, update, project)
+                    }
                     _ => return self.fail(name, Undefined::Filter(arity)),
                 };
                 let xs = self.iterm(*xs);
                 let init = self.iterm(init);
-                let update = self.with(Local::Var(x), |c| c.iterm(update));
+                let (update, project) = self.with(Local::Var(x), |c| {
+                    (c.iterm(update), project.map(|p| c.iterm(p)))
+                });
+                let fold = match name {
+                    "reduce" => FoldType::Reduce,
+                    "for" => FoldType::For,
+                    _ => FoldType::Foreach(project),
+                };
 
                 Term::Fold(fold, xs, init, update)
             }
             BinOp(l, op, r) => {
                 use parse::BinaryOp::*;
+                if matches!(op, Assign | Update | UpdateMath(_) | UpdateAlt) {
+                    if let Some(t) = non_path(&l) {
+                        let code = self.file.code;
+                        let span = span(code, [t]).or_else(|| span(code, [&*l, &*r]));
+                        self.errs
+                            .push((span.unwrap_or(&code[..0]), Undefined::Path));
+                    }
+                }
                 let (l, r) = match op {
                     Comma => (self.iterm_tr(*l), self.iterm_tr(*r)),
                     Alt => (self.iterm(*l), self.iterm_tr(*r)),
@@ -471,7 +589,7 @@ impl<'s, F> Compiler<&'s str, F> {
                 };
                 match op {
                     Comma => Term::Comma(l, r),
-                    Math(op) => Term::Math(l, op, r),
+                    Math(op) => self.math(l, op, r),
                     Assign => Term::Assign(l, r),
                     Update => Term::Update(l, r),
                     UpdateMath(op) => Term::UpdateMath(l, op, r),
@@ -499,8 +617,14 @@ impl<'s, F> Compiler<&'s str, F> {
                     StrPart::Char(c) => Term::Str(c.into()),
                     StrPart::Term(f) => Term::Pipe(self.iterm(f), false, fmt),
                 });
-                let parts = parts.collect();
-                self.sum_or(|| Term::Str(String::new()), parts)
+                let mut parts = parts.collect::<Vec<_>>().into_iter();
+                let first = parts.next().unwrap_or_else(|| Term::Str(String::new()));
+                // we associate to the left, so that every part is appended to
+                // the string built so far, instead of copying that string
+                parts.fold(first, |acc, x| {
+                    let (acc, x) = (self.lut.insert_term(acc), self.lut.insert_term(x));
+                    self.math(acc, ops::Math::Add, x)
+                })
             }
             Obj(o) => {
                 let kvs = o.into_iter().map(|(k, v)| self.obj_entry(k, v)).collect();
@@ -509,6 +633,99 @@ impl<'s, F> Compiler<&'s str, F> {
         }
     }
 
+    /// Compile `l as p1 ?// ... ?// pn | r`.
+    ///
+    /// This is compiled like
+    /// `. as $i | l as $v | try ($v as p1 | r) catch ($i | ... ($v as pn | r))`,
+    /// where `$i` and `$v` are hidden variables,
+    /// so errors in `r` also make us try the next pattern.
+    fn destruct(
+        &mut self,
+        l: parse::Term<&'s str>,
+        mut pats: Vec<parse::Pattern<&'s str>>,
+        r: parse::Term<&'s str>,
+    ) -> Term {
+        let alternatives = pats.len() > 1;
+        let bind = |c: &mut Self, input: Option<usize>| {
+            let l = c.iterm(l);
+            let alts = c.with(Local::Var(""), |c| {
+                let v = c.local.len() - 1;
+                let last = pats.pop().unwrap();
+                let last = c.pattern(v, last, Box::new(|c| c.iterm(r.clone())));
+                pats.into_iter().rev().fold(last, |catch, p| {
+                    let try_ = c.pattern(v, p, Box::new(|c| c.iterm(r.clone())));
+                    let input = c.var_at(input.unwrap());
+                    let catch = Term::Pipe(c.lut.insert_term(input), false, catch);
+                    let catch = c.lut.insert_term(catch);
+                    c.lut.insert_term(Term::TryCatch(try_, catch))
+                })
+            });
+            Term::Pipe(l, true, alts)
+        };
+        if alternatives {
+            let id = self.lut.insert_term(Term::Id);
+            let t = self.with(Local::Var(""), |c| {
+                let input = c.local.len() - 1;
+                let t = bind(c, Some(input));
+                c.lut.insert_term(t)
+            });
+            Term::Pipe(id, true, t)
+        } else {
+            bind(self, None)
+        }
+    }
+
+    /// Compile `$v as p | k`, where `$v` is the variable at position `v` of the local bindings.
+    fn pattern(&mut self, v: usize, p: parse::Pattern<&'s str>, k: Cont<'_, 's, F>) -> TermId {
+        use parse::Pattern;
+        match p {
+            Pattern::Var(x) => {
+                let v = self.var_at(v);
+                let v = self.lut.insert_term(v);
+                let t = Term::Pipe(v, true, self.with(Local::Var(x), k));
+                self.lut.insert_term(t)
+            }
+            Pattern::Arr(pats) => {
+                let pats = pats.into_iter().enumerate().rev();
+                let k = pats.fold(k, |k, (i, p)| {
+                    Box::new(move |c: &mut Self| {
+                        let i = c.lut.insert_term(Term::Int(i as isize));
+                        c.index(v, i, p, k)
+                    })
+                });
+                k(self)
+            }
+            Pattern::Obj(entries) => {
+                let k = entries.into_iter().rev().fold(k, |k, (key, p)| {
+                    Box::new(move |c: &mut Self| {
+                        let key = c.iterm(key);
+                        c.index(v, key, p, k)
+                    })
+                });
+                k(self)
+            }
+        }
+    }
+
+    /// Compile `$v[key] as p | k`, where `key` is evaluated with `$v` as input.
+    fn index(
+        &mut self,
+        v: usize,
+        key: TermId,
+        p: parse::Pattern<&'s str>,
+        k: Cont<'_, 's, F>,
+    ) -> TermId {
+        use crate::path::{Opt, Part, Path};
+        let id = self.lut.insert_term(Term::Id);
+        let path = Path(Vec::from([(Part::Index(key), Opt::Essential)]));
+        let path = self.lut.insert_term(Term::Path(id, path));
+        let v = self.var_at(v);
+        let l = Term::Pipe(self.lut.insert_term(v), false, path);
+        let l = self.lut.insert_term(l);
+        let r = self.with(Local::Var(""), |c| c.pattern(c.local.len() - 1, p, k));
+        self.lut.insert_term(Term::Pipe(l, true, r))
+    }
+
     fn iterm(&mut self, t: parse::Term<&'s str>) -> TermId {
         self.with(Local::TailrecObstacle, |c| c.iterm_tr(t))
     }
@@ -523,14 +740,14 @@ impl<'s, F> Compiler<&'s str, F> {
         Term::default()
     }
 
+    /// Return the variable at position `pos` of the local bindings.
+    fn var_at(&self, pos: usize) -> Term {
+        Term::Var(self.local[pos + 1..].iter().map(Local::vars).sum(), 0)
+    }
+
     /// Resolve call to `mod::filter(a1, ..., an)`.
     fn call_mod(&mut self, module: &'s str, name: &'s str, args: &[TermId]) -> Term {
-        let vars = self.local.iter().map(|l| match l {
-            Local::Var(_) => 1,
-            Local::Label(_) | Local::Sibling(..) | Local::TailrecObstacle => 0,
-            Local::Parent(sig, _def) => sig.args.len(),
-        });
-        let vars = vars.sum();
+        let vars = self.local.iter().map(Local::vars).sum();
         let mut imported_mods = self.imported_mods.iter().rev();
         let mid = match imported_mods.find(|(_mid, module_)| module == *module_) {
             Some((mid, _module)) => mid,
@@ -578,10 +795,13 @@ impl<'s, F> Compiler<&'s str, F> {
             }
         }
         for mid in self.included_mods.iter().rev() {
-            for (sig, def) in self.mod_map[*mid].iter().rev() {
-                if sig.matches(name, &args) {
+            let mut defs = self.mod_map[*mid].iter().rev();
+            if let Some((sig, def)) = defs.find(|(sig, _def)| sig.matches(name, &args)) {
+                if *mid != 0 {
                     return def.call(sig.bind(&args), i);
                 }
+                let (sig, def) = (sig.clone(), def.clone());
+                return self.call_prelude(name, (sig, def), &args, i);
             }
         }
 
@@ -594,6 +814,29 @@ impl<'s, F> Compiler<&'s str, F> {
         self.fail(name, Undefined::Filter(args.len()))
     }
 
+    /// Call a definition of the prelude module from outside the prelude.
+    ///
+    /// Errors that occur in the definition are marked as originating from it,
+    /// except for errors that occur in its arguments.
+    fn call_prelude(
+        &mut self,
+        name: &str,
+        (sig, def): (Sig<&'s str>, Def),
+        args: &[TermId],
+        vars: usize,
+    ) -> Term {
+        use crate::exn::Origin;
+        let args: Vec<_> = args
+            .iter()
+            .map(|a| self.lut.insert_term(Term::Origin(Origin::User, *a)))
+            .collect();
+        let call = self.lut.insert_term(def.call(sig.bind(&args), vars));
+        Term::Origin(
+            Origin::Prelude(alloc::format!("{name}/{}", args.len())),
+            call,
+        )
+    }
+
     fn var(&mut self, x: &'s str) -> Term {
         let mut i = 0;
         for l in self.local.iter().rev() {
@@ -626,9 +869,27 @@ impl<'s, F> Compiler<&'s str, F> {
                 i += 1;
             }
         }
+        if x == "$__loc__" {
+            return self.loc(x);
+        }
         self.fail(x, Undefined::Var)
     }
 
+    /// Return an object with the file and line number where `x` occurs.
+    fn loc(&mut self, x: &'s str) -> Term {
+        let code = self.file.code;
+        let offset = (x.as_ptr() as usize).checked_sub(code.as_ptr() as usize);
+        let before = offset.and_then(|o| code.get(..o));
+        let line = before.map_or(0, |s| s.matches('\n').count() + 1);
+        let file = Term::Str(self.file.path.clone());
+        let kvs = [("file", file), ("line", Term::Int(line as isize))];
+        let kvs = kvs.map(|(k, v)| {
+            let k = self.lut.insert_term(Term::Str(k.into()));
+            Term::ObjSingle(k, self.lut.insert_term(v))
+        });
+        self.sum_or(|| Term::ObjEmpty, kvs.into())
+    }
+
     fn break_(&mut self, x: &'s str) -> Term {
         let mut labels = 0;
         for l in self.local.iter().rev() {
@@ -664,7 +925,91 @@ impl<'s, F> Compiler<&'s str, F> {
         let mut iter = terms.into_iter().rev();
         let last = iter.next().unwrap_or_else(f);
         iter.fold(last, |acc, x| {
-            Term::Math(self.lut.insert_term(x), Add, self.lut.insert_term(acc))
+            let (x, acc) = (self.lut.insert_term(x), self.lut.insert_term(acc));
+            self.math(x, Add, acc)
         })
     }
+
+    /// Compile an arithmetic operation, evaluating it if both operands are constants.
+    ///
+    /// This is only done for operations whose result does not depend on the value type,
+    /// namely for addition, subtraction, and multiplication of integers
+    /// (unless it overflows) as well as concatenation of strings.
+    fn math(&mut self, l: TermId, op: ops::Math, r: TermId) -> Term {
+        use ops::Math::{Add, Mul, Sub};
+        let folded = match (&self.lut.terms[l.0], op, &self.lut.terms[r.0]) {
+            (Term::Int(x), Add, Term::Int(y)) => x.checked_add(*y).map(Term::Int),
+            (Term::Int(x), Sub, Term::Int(y)) => x.checked_sub(*y).map(Term::Int),
+            (Term::Int(x), Mul, Term::Int(y)) => x.checked_mul(*y).map(Term::Int),
+            (Term::Str(x), Add, Term::Str(y)) => Some(Term::Str(x.clone() + y)),
+            _ => None,
+        };
+        folded.unwrap_or(Term::Math(l, op, r))
+    }
+}
+
+/// Return a subterm of `t` that prevents `t` from being a path, such as `1 + 2` in `.a, 1 + 2`.
+///
+/// Calls are always assumed to be paths, because
+/// whether they are paths depends on their definition.
+fn non_path<S>(t: &parse::Term<S>) -> Option<&parse::Term<S>> {
+    use parse::{BinaryOp, Term::*};
+    match t {
+        Id | Recurse | Break(_) | Call(..) | Label(..) | Fold(..) | TryCatch(..) => None,
+        Bool(_) | Null | Num(_) | Str(..) | Arr(_) | Obj(_) | Neg(_) | Var(_) => Some(t),
+        Pipe(l, None, r) | BinOp(l, BinaryOp::Comma | BinaryOp::Alt, r) => {
+            non_path(l).or_else(|| non_path(r))
+        }
+        Pipe(_, Some(_), t) | Destruct(_, _, t) | Def(_, t) | Path(t, _) => non_path(t),
+        BinOp(..) => Some(t),
+        IfThenElse(if_thens, else_) => {
+            let mut thens = if_thens.iter().map(|(_if, then)| then);
+            let else_ = else_.as_deref().and_then(non_path);
+            thens.find_map(non_path).or(else_)
+        }
+    }
+}
+
+/// Return the smallest part of `code` that contains all strings in the given terms.
+fn span<'s, 'a>(
+    code: &'s str,
+    ts: impl IntoIterator<Item = &'a parse::Term<&'s str>>,
+) -> Option<&'s str>
+where
+    's: 'a,
+{
+    use load::visit::{walk_term, Visit};
+    /// Collector of strings that are part of `code`.
+    struct Strs<'s>(&'s str, Option<(usize, usize)>);
+    impl<'s> Strs<'s> {
+        fn add(&mut self, s: &'s str) {
+            let start = (s.as_ptr() as usize).checked_sub(self.0.as_ptr() as usize);
+            let range = start.filter(|start| start + s.len() <= self.0.len());
+            if let Some(start) = range {
+                let (l, r) = self.1.unwrap_or((start, start + s.len()));
+                self.1 = Some((l.min(start), r.max(start + s.len())));
+            }
+        }
+    }
+    impl<'s> Visit<&'s str> for Strs<'s> {
+        fn visit_term(&mut self, t: &parse::Term<&'s str>) {
+            use parse::Term::*;
+            match t {
+                Num(s) | Var(s) | Break(s) | Call(s, _) | Label(s, _) => self.add(s),
+                Str(fmt, parts) => {
+                    fmt.iter().for_each(|fmt| self.add(fmt));
+                    for part in parts {
+                        if let lex::StrPart::Str(s) = part {
+                            self.add(s)
+                        }
+                    }
+                }
+                _ => (),
+            }
+            walk_term(self, t)
+        }
+    }
+    let mut strs = Strs(code, None);
+    ts.into_iter().for_each(|t| strs.visit_term(t));
+    strs.1.map(|(l, r)| &code[l..r])
 }
@@ -31,6 +31,16 @@ impl<V> Exn<'_, V> {
     }
 }
 
+impl<V> Exn<'_, V> {
+    /// If the exception is an error without origin, set its origin.
+    pub(crate) fn or_origin(self, origin: impl FnOnce() -> Origin) -> Self {
+        match self.0 {
+            Inner::Err(e) => Exn(Inner::Err(e.or_origin(origin))),
+            _ => self,
+        }
+    }
+}
+
 impl<V> From<Error<V>> for Exn<'_, V> {
     fn from(e: Error<V>) -> Self {
         Exn(Inner::Err(e))
@@ -44,18 +54,102 @@ enum Part<V, S = &'static str> {
 }
 
 /// Error that occurred during filter execution.
+#[derive(Clone, Debug)]
+pub struct Error<V>(Kind<V>, Origin);
+
 #[derive(Clone, Debug, PartialEq, Eq)]
-pub struct Error<V>(Part<V, Vec<Part<V>>>);
+enum Kind<V> {
+    Part(Part<V, Vec<Part<V>>>),
+    /// request to halt with an exit code and an optional message, made by `halt`
+    Halt(isize, Option<V>),
+    /// path expression error, possibly caused by a value that has no path
+    PathExpr(Option<V>),
+}
+
+/// Part of the program where an error occurred.
+#[derive(Clone, Debug, Default)]
+pub(crate) enum Origin {
+    /// not determined yet
+    #[default]
+    Unknown,
+    /// code outside the prelude, such as the main filter
+    User,
+    /// definition in the prelude, such as `map/1`
+    Prelude(String),
+}
+
+// the origin of an error is just metadata, so it does not affect equality;
+// the same holds for the value that caused a path expression error
+impl<V: PartialEq> PartialEq for Error<V> {
+    fn eq(&self, other: &Self) -> bool {
+        match (&self.0, &other.0) {
+            (Kind::PathExpr(_), Kind::PathExpr(_)) => true,
+            (l, r) => l == r,
+        }
+    }
+}
+
+impl<V: Eq> Eq for Error<V> {}
 
 impl<V> Error<V> {
     /// Create a new error from a value.
     pub fn new(v: V) -> Self {
-        Self(Part::Val(v))
+        Self(Kind::Part(Part::Val(v)), Origin::Unknown)
     }
 
     /// Create a path expression error.
     pub fn path_expr() -> Self {
-        Self(Part::Str(Vec::from([Part::Str("invalid path expression")])))
+        Self(Kind::PathExpr(None), Origin::Unknown)
+    }
+
+    /// Create a path expression error caused by a value that has no path.
+    pub(crate) fn path_expr_with(v: V) -> Self {
+        Self(Kind::PathExpr(Some(v)), Origin::Unknown)
+    }
+
+    /// If the error was caused by a value that has no path, yield the value, else the error.
+    pub(crate) fn path_expr_val(self) -> Result<V, Self> {
+        match self.0 {
+            Kind::PathExpr(Some(v)) => Ok(v),
+            _ => Err(self),
+        }
+    }
+
+    /// Create an error that requests to halt with the given exit code.
+    ///
+    /// Unlike other errors, this error cannot be caught by `try`.
+    /// Embedders should stop running the filter when they encounter it,
+    /// print its message (if any) to the standard error, and
+    /// exit with its exit code (see [`Self::as_halt`]).
+    pub fn halt(exit_code: isize, message: Option<V>) -> Self {
+        Self(Kind::Halt(exit_code, message), Origin::Unknown)
+    }
+
+    /// If the error requests to halt, return its exit code and message.
+    pub fn as_halt(&self) -> Option<(isize, Option<&V>)> {
+        match &self.0 {
+            Kind::Halt(code, msg) => Some((*code, msg.as_ref())),
+            Kind::Part(_) | Kind::PathExpr(_) => None,
+        }
+    }
+
+    /// Return the prelude definition in which the error occurred, such as `map/1`.
+    ///
+    /// This yields `None` if the error occurred outside the prelude,
+    /// for example in the main filter or in a filter argument passed to a prelude definition.
+    pub fn prelude_def(&self) -> Option<&str> {
+        match &self.1 {
+            Origin::Prelude(def) => Some(def),
+            Origin::Unknown | Origin::User => None,
+        }
+    }
+
+    /// Set the origin of the error if it has not been set before.
+    pub(crate) fn or_origin(mut self, origin: impl FnOnce() -> Origin) -> Self {
+        if let Origin::Unknown = self.1 {
+            self.1 = origin();
+        }
+        self
     }
 
     /// Create a type error.
@@ -93,20 +187,23 @@ impl<V> Error<V> {
 impl<V: From<String>> Error<V> {
     /// Build an error from something that can be converted to a string.
     pub fn str(s: impl ToString) -> Self {
-        Self(Part::Val(V::from(s.to_string())))
+        Self::new(V::from(s.to_string()))
     }
 }
 
 impl<V> FromIterator<Part<V>> for Error<V> {
     fn from_iter<T: IntoIterator<Item = Part<V>>>(iter: T) -> Self {
-        Self(Part::Str(iter.into_iter().collect()))
+        Self(
+            Kind::Part(Part::Str(iter.into_iter().collect())),
+            Origin::Unknown,
+        )
     }
 }
 
 impl<V: From<String> + Display> Error<V> {
     /// Convert the error into a value to be used by `catch` filters.
     pub fn into_val(self) -> V {
-        if let Part::Val(v) = self.0 {
+        if let Kind::Part(Part::Val(v)) = self.0 {
             v
         } else {
             V::from(self.to_string())
@@ -117,11 +214,13 @@ impl<V: From<String> + Display> Error<V> {
 impl<V: Display> Display for Error<V> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match &self.0 {
-            Part::Val(v) => v.fmt(f),
-            Part::Str(parts) => parts.iter().try_for_each(|part| match part {
+            Kind::Part(Part::Val(v)) => v.fmt(f),
+            Kind::Part(Part::Str(parts)) => parts.iter().try_for_each(|part| match part {
                 Part::Val(v) => v.fmt(f),
                 Part::Str(s) => s.fmt(f),
             }),
+            Kind::Halt(code, _) => write!(f, "halt with exit code {code}"),
+            Kind::PathExpr(_) => "invalid path expression".fmt(f),
         }
     }
 }
@@ -1,9 +1,10 @@
 use crate::box_iter::{box_once, flat_map_with, map_with, BoxIter};
 use crate::compile::{FoldType, Lut, Tailrec, Term as Ast};
+use crate::path::ValPath;
 use crate::results::{fold, then, Fold, Results};
 use crate::val::{ValT, ValX, ValXs};
 use crate::{exn, rc_lazy_list, Bind, Ctx, Error, Exn};
-use alloc::boxed::Box;
+use alloc::{boxed::Box, rc::Rc, vec::Vec};
 use dyn_clone::DynClone;
 
 pub(crate) use crate::compile::TermId as Id;
@@ -47,7 +48,7 @@ fn run_cvs<'a, F: FilterT>(
     Box::new(cvs.flat_map(move |cv| then(cv, |cv| f.run(lut, cv))))
 }
 
-fn reduce<'a, T, V, F>(xs: Results<'a, T, Exn<'a, V>>, init: V, f: F) -> ValXs<V>
+fn reduce<'a, T, V, F>(xs: Results<'a, T, Exn<'a, V>>, init: V, f: F) -> ValXs<'a, V>
 where
     T: Clone + 'a,
     V: Clone + 'a,
@@ -57,7 +58,81 @@ where
     Box::new(fold(false, xs, Fold::Input(init), f))
 }
 
-fn label_skip<'a, V: 'a>(ys: ValXs<'a, V>, skip: usize) -> ValXs<'a, V> {
+/// Update the values at the given paths with `f`, one path after the other.
+fn update_paths<'a, V: ValT + 'a>(
+    paths: impl Iterator<Item = Result<Vec<V>, Exn<'a, V>>>,
+    v: V,
+    f: BoxUpdate<'a, V>,
+) -> ValXs<'a, V> {
+    match paths.collect::<Result<Vec<_>, _>>() {
+        Ok(paths) => {
+            let len = paths.len();
+            update_group(paths.into(), 0..len, 0, v, f)
+        }
+        Err(e) => box_once(Err(e)),
+    }
+}
+
+/// Update `v` at `paths[range]`, where `v` is the value at
+/// the first `depth` components of each of these paths.
+///
+/// Instead of traversing `v` once for every path,
+/// we update consecutive paths that go through the same child of `v` together.
+/// This yields the same as updating the paths one after the other,
+/// but allows updating all paths yielded by `..` in linear time.
+fn update_group<'a, V: ValT + 'a>(
+    paths: Rc<[Vec<V>]>,
+    range: core::ops::Range<usize>,
+    depth: usize,
+    v: V,
+    f: BoxUpdate<'a, V>,
+) -> ValXs<'a, V> {
+    // `None` stands for a path that ends at `v`, and
+    // `Some(r)` for paths `r` that go through the same child of `v`
+    let mut groups = Vec::new();
+    let mut i = range.start;
+    while i < range.end {
+        let start = i;
+        match paths[i].get(depth) {
+            None => i += 1,
+            Some(k) => {
+                while i < range.end && paths[i].get(depth) == Some(k) {
+                    i += 1;
+                    // a path that ends at the child may delete the child,
+                    // so subsequent paths would refer to another child
+                    if paths[i - 1].len() == depth + 1 {
+                        break;
+                    }
+                }
+            }
+        }
+        groups.push(Ok((paths[start].len() > depth).then_some(start..i)));
+    }
+    let update = move |group: Option<core::ops::Range<usize>>, v| match group {
+        None => f(v),
+        Some(r) => {
+            use crate::path::{Opt, Part};
+            let part = Part::from_key(paths[r.start][depth].clone());
+            let (paths, f) = (paths.clone(), f.clone());
+            let update = move |v| update_group(paths.clone(), r.clone(), depth + 1, v, f.clone());
+            box_once(part.update(v, Opt::Essential, update))
+        }
+    };
+    Box::new(fold(false, groups.into_iter(), Fold::Input(v), update))
+}
+
+/// Set the origin of all errors in `ys` that have no origin yet.
+fn or_origin<'a, T: 'a, V: 'a>(
+    ys: Results<'a, T, Exn<'a, V>>,
+    origin: &'a exn::Origin,
+) -> Results<'a, T, Exn<'a, V>> {
+    Box::new(ys.map(move |y| y.map_err(|e| e.or_origin(|| origin.clone()))))
+}
+
+fn label_skip<'a, T: 'a, V: 'a>(
+    ys: Results<'a, T, Exn<'a, V>>,
+    skip: usize,
+) -> Results<'a, T, Exn<'a, V>> {
     if skip == 0 {
         return ys;
     }
@@ -67,14 +142,37 @@ fn label_skip<'a, V: 'a>(ys: ValXs<'a, V>, skip: usize) -> ValXs<'a, V> {
     }))
 }
 
+/// Return true if the output of `l` in `l // r` should be yielded.
+///
+/// This is the case for values other than `null` and `false`, and
+/// for exceptions other than errors, such as breaks and halts.
+/// Errors are suppressed.
+fn alt_truthy<V: ValT>(y: Result<&V, &Exn<V>>) -> bool {
+    match y {
+        Ok(y) => y.as_bool(),
+        Err(Exn(exn::Inner::Err(e))) => e.as_halt().is_some(),
+        Err(_) => true,
+    }
+}
+
+/// Return an error for every output of a filter that is not a path expression.
+fn not_paths<'a, V: 'a>(ys: ValXs<'a, V>) -> ValPathXs<'a, V> {
+    Box::new(ys.map(|y| y.and_then(|y| Err(Exn::from(Error::path_expr_with(y))))))
+}
+
 /// Combination of context and input value.
 pub type Cv<'c, V> = (Ctx<'c, V>, V);
+/// Combination of context and input value with its path.
+pub type Cvp<'c, V> = (Ctx<'c, V>, ValPath<V>);
+/// Stream of values with their paths and eXceptions.
+pub type ValPathXs<'a, V> = Results<'a, ValPath<V>, Exn<'a, V>>;
 
 /// A filter which is implemented using function pointers.
 #[derive(Clone)]
 pub struct Native<V> {
     run: RunPtr<V>,
     update: UpdatePtr<V>,
+    paths: PathsPtr<V>,
 }
 
 /// Run function pointer.
@@ -86,13 +184,16 @@ pub type RunPtr<V, F = Native<V>> = for<'a> fn(&'a Lut<F>, Cv<'a, V>) -> ValXs<'
 /// Update function pointer.
 pub type UpdatePtr<V, F = Native<V>> =
     for<'a> fn(&'a Lut<F>, Cv<'a, V>, BoxUpdate<'a, V>) -> ValXs<'a, V>;
+/// Paths function pointer.
+pub type PathsPtr<V, F = Native<V>> = for<'a> fn(&'a Lut<F>, Cvp<'a, V>) -> ValPathXs<'a, V>;
 
 impl<V> Native<V> {
-    /// Create a native filter from a run function, without support for updates.
+    /// Create a native filter from a run function, without support for updates and paths.
     pub const fn new(run: RunPtr<V, Self>) -> Self {
         Self {
             run,
             update: |_, _, _| box_once(Err(Exn::from(Error::path_expr()))),
+            paths: |_, _| box_once(Err(Exn::from(Error::path_expr()))),
         }
     }
 
@@ -100,6 +201,11 @@ impl<V> Native<V> {
     pub const fn with_update(self, update: UpdatePtr<V, Self>) -> Self {
         Self { update, ..self }
     }
+
+    /// Specify a paths function (used for `path(filter)`).
+    pub const fn with_paths(self, paths: PathsPtr<V, Self>) -> Self {
+        Self { paths, ..self }
+    }
 }
 
 impl<V: ValT> FilterT for Native<V> {
@@ -117,6 +223,10 @@ impl<V: ValT> FilterT for Native<V> {
     ) -> ValXs<'a, V> {
         (self.update)(lut, cv, f)
     }
+
+    fn paths<'a>(&'a self, lut: &'a Lut<Self>, cv: Cvp<'a, V>) -> ValPathXs<'a, V> {
+        (self.paths)(lut, cv)
+    }
 }
 
 impl<F: FilterT<F>> FilterT<F> for Id {
@@ -131,6 +241,8 @@ impl<F: FilterT<F>> FilterT<F> for Id {
                 Some(_) => Ok(cv.1),
                 None => Ok(Self::V::from(cv.1.to_string())),
             })),
+            Ast::Null => box_once(Ok(Self::V::null())),
+            Ast::Bool(b) => box_once(Ok(Self::V::from(*b))),
             Ast::Int(n) => box_once(Ok(Self::V::from(*n))),
             Ast::Num(x) => box_once(Self::V::from_num(x).map_err(Exn::from)),
             Ast::Str(s) => Box::new(once_with(move || Ok(Self::V::from(s.clone())))),
@@ -140,9 +252,12 @@ impl<F: FilterT<F>> FilterT<F> for Id {
                 Self::cartesian(k, v, lut, cv).map(|(k, v)| Ok(Self::V::from_map([(k?, v?)])?)),
             ),
             // TODO: write test for `try (break $x)`
+            // halts cannot be caught
             Ast::TryCatch(f, c) => {
                 Box::new(f.run(lut, (cv.0.clone(), cv.1)).flat_map(move |y| match y {
-                    Err(Exn(exn::Inner::Err(e))) => c.run(lut, (cv.0.clone(), e.into_val())),
+                    Err(Exn(exn::Inner::Err(e))) if e.as_halt().is_none() => {
+                        c.run(lut, (cv.0.clone(), e.into_val()))
+                    }
                     y => box_once(y),
                 }))
             }
@@ -160,9 +275,7 @@ impl<F: FilterT<F>> FilterT<F> for Id {
 
             Ast::Comma(l, r) => Box::new(l.run(lut, cv.clone()).chain(r.run(lut, cv))),
             Ast::Alt(l, r) => {
-                let mut l = l
-                    .run(lut, cv.clone())
-                    .filter(|v| v.as_ref().map_or(true, ValT::as_bool));
+                let mut l = l.run(lut, cv.clone()).filter(|y| alt_truthy(y.as_ref()));
                 match l.next() {
                     Some(head) => Box::new(once(head).chain(l)),
                     None => r.run(lut, cv),
@@ -228,13 +341,28 @@ impl<F: FilterT<F>> FilterT<F> for Id {
                 use Fold::{Input, Output};
                 let xs = rc_lazy_list::List::from_iter(xs.run(lut, cv.clone()));
                 let init = init.run(lut, cv.clone());
+                let ctx = cv.0.clone();
                 let f = move |x, v| f.run(lut, (cv.0.clone().cons_var(x), v));
                 match typ {
                     FoldType::Reduce => Box::new(fold(false, xs, Output(init), f)),
                     FoldType::For => Box::new(fold(true, xs, Output(init), f)),
-                    FoldType::Foreach => flat_map_with(init, xs, move |i, xs| {
+                    FoldType::Foreach(None) => flat_map_with(init, xs, move |i, xs| {
                         then(i, |i| Box::new(fold(true, xs, Input(i), f.clone())))
                     }),
+                    FoldType::Foreach(Some(project)) => {
+                        // remember the value of `$x` for every output of `f`
+                        let f = move |x: Self::V, (_, v)| -> Results<_, _> {
+                            Box::new(f(x.clone(), v).map(move |y| Ok((x.clone(), y?))))
+                        };
+                        let project = move |(x, y)| project.run(lut, (ctx.clone().cons_var(x), y));
+                        flat_map_with(init, xs, move |i, xs| {
+                            then(i, |i| {
+                                let ys = fold(true, xs, Input((i.clone(), i)), f.clone());
+                                let project = project.clone();
+                                Box::new(ys.flat_map(move |y| then(y, &project)))
+                            })
+                        })
+                    }
                 }
             }
 
@@ -246,7 +374,7 @@ impl<F: FilterT<F>> FilterT<F> for Id {
             },
             Ast::CallDef(id, args, skip, tailrec) => {
                 use core::ops::ControlFlow;
-                let inputs = cv.0.inputs;
+                let ctx = cv.0.without_vars();
                 let cvs = bind_vars(args, lut, cv.0.clone().skip_vars(*skip), cv);
                 match tailrec {
                     None => run_cvs(id, lut, cvs),
@@ -254,7 +382,7 @@ impl<F: FilterT<F>> FilterT<F> for Id {
                         [run_cvs(id, lut, cvs)].into(),
                         move |r| match r {
                             Err(Exn(exn::Inner::TailCall(id_, vars, v))) if id == id_ => {
-                                ControlFlow::Continue(id.run(lut, (Ctx { vars, inputs }, v)))
+                                ControlFlow::Continue(id.run(lut, (ctx.with_vars(vars), v)))
                             }
                             Ok(_) | Err(_) => ControlFlow::Break(r),
                         },
@@ -265,9 +393,10 @@ impl<F: FilterT<F>> FilterT<F> for Id {
                 }
             }
             Ast::Native(id, args) => {
-                let cvs = bind_vars(args, lut, Ctx::new([], cv.0.inputs), cv);
+                let cvs = bind_vars(args, lut, cv.0.without_vars(), cv);
                 run_cvs(&lut.funs[*id], lut, cvs)
             }
+            Ast::Origin(origin, f) => or_origin(f.run(lut, cv), origin),
             Ast::Label(id) => Box::new(id.run(lut, cv).map_while(|y| match y {
                 Err(Exn(exn::Inner::Break(n))) => {
                     n.checked_sub(1).map(|m| Err(Exn(exn::Inner::Break(m))))
@@ -287,14 +416,16 @@ impl<F: FilterT<F>> FilterT<F> for Id {
         let err = box_once(Err(Exn::from(Error::path_expr())));
         match &lut.terms[self.0] {
             Ast::ToString => err,
-            Ast::Int(_) | Ast::Num(_) | Ast::Str(_) => err,
+            Ast::Null | Ast::Bool(_) | Ast::Int(_) | Ast::Num(_) | Ast::Str(_) => err,
             Ast::Arr(_) | Ast::ObjEmpty | Ast::ObjSingle(..) => err,
             Ast::Neg(_) | Ast::Logic(..) | Ast::Math(..) | Ast::Cmp(..) => err,
             Ast::Update(..) | Ast::UpdateMath(..) | Ast::UpdateAlt(..) | Ast::Assign(..) => err,
 
-            // these are up for grabs to implement :)
+            // like in jq, these are updated by first determining all their paths
+            // on the original input and then updating the value at each path
             Ast::TryCatch(..) | Ast::Label(_) | Ast::Fold(..) => {
-                unimplemented!("updating with this operator is not supported yet")
+                let ps = self.paths(lut, (cv.0, (cv.1.clone(), Vec::new())));
+                update_paths(ps.map(|y| y.map(|(_, p)| p)), cv.1, f)
             }
 
             Ast::Id => f(cv.1),
@@ -318,18 +449,18 @@ impl<F: FilterT<F>> FilterT<F> for Id {
                 r.update(lut, (cv.0.clone().cons_var(x), v), f.clone())
             }),
             Ast::Comma(l, r) => {
-                let l = l.update(lut, (cv.0.clone(), cv.1), f.clone());
-                Box::new(
-                    l.flat_map(move |v| then(v, |v| r.update(lut, (cv.0.clone(), v), f.clone()))),
-                )
+                // like in jq, the paths of `r` are determined on the original input,
+                // so `r` does not visit values that were added by updating `l`
+                let rps = r.paths(lut, (cv.0.clone(), (cv.1.clone(), Vec::new())));
+                let rps = rc_lazy_list::List::from_iter(rps.map(|y| y.map(|(_, p)| p)));
+                let l = l.update(lut, (cv.0, cv.1), f.clone());
+                Box::new(l.flat_map(move |v| then(v, |v| update_paths(rps.clone(), v, f.clone()))))
             }
             Ast::Ite(if_, then_, else_) => reduce(if_.run(lut, cv.clone()), cv.1, move |x, v| {
                 if x.as_bool() { then_ } else { else_ }.update(lut, (cv.0.clone(), v), f.clone())
             }),
             Ast::Alt(l, r) => {
-                let some_true = l
-                    .run(lut, cv.clone())
-                    .any(|y| y.map_or(true, |y| y.as_bool()));
+                let some_true = l.run(lut, cv.clone()).any(|y| alt_truthy(y.as_ref()));
                 if some_true { l } else { r }.update(lut, cv, f)
             }
 
@@ -347,11 +478,129 @@ impl<F: FilterT<F>> FilterT<F> for Id {
             }
             Ast::Native(id, args) => {
                 let init = cv.1.clone();
-                let cvs = bind_vars(args, lut, Ctx::new([], cv.0.inputs), cv);
+                let cvs = bind_vars(args, lut, cv.0.without_vars(), cv);
                 reduce(cvs, init, move |cv, v| {
                     lut.funs[*id].update(lut, (cv.0, v), f.clone())
                 })
             }
+            Ast::Origin(origin, g) => {
+                // errors yielded by the update function stem from outside `g`
+                let f = Box::new(move |v| or_origin(f(v), &exn::Origin::User));
+                or_origin(g.update(lut, cv, f), origin)
+            }
+            Ast::Break(skip) => box_once(Err(Exn(exn::Inner::Break(*skip)))),
+        }
+    }
+
+    fn paths<'a>(&'a self, lut: &'a Lut<F>, cv: Cvp<'a, Self::V>) -> ValPathXs<'a, Self::V> {
+        match &lut.terms[self.0] {
+            Ast::ToString => not_paths(self.run(lut, (cv.0, cv.1 .0))),
+            Ast::Null | Ast::Bool(_) | Ast::Int(_) | Ast::Num(_) | Ast::Str(_) => {
+                not_paths(self.run(lut, (cv.0, cv.1 .0)))
+            }
+            Ast::Arr(_) | Ast::ObjEmpty | Ast::ObjSingle(..) => {
+                not_paths(self.run(lut, (cv.0, cv.1 .0)))
+            }
+            Ast::Neg(_) | Ast::Logic(..) | Ast::Math(..) | Ast::Cmp(..) => {
+                not_paths(self.run(lut, (cv.0, cv.1 .0)))
+            }
+            Ast::Update(..) | Ast::UpdateMath(..) | Ast::UpdateAlt(..) | Ast::Assign(..) => {
+                not_paths(self.run(lut, (cv.0, cv.1 .0)))
+            }
+            Ast::Fold(..) => not_paths(self.run(lut, (cv.0, cv.1 .0))),
+
+            Ast::Id => box_once(Ok(cv.1)),
+            Ast::Path(l, path) => {
+                let path = path.map_ref(|i| {
+                    let cv = (cv.0.clone(), cv.1 .0.clone());
+                    crate::into_iter::collect_if_once(move || i.run(lut, cv))
+                });
+                flat_map_with(l.paths(lut, cv), path, |y, path| {
+                    then(y, |y| {
+                        flat_map_with(path.explode(), y, |path, y| {
+                            then(path, |path| {
+                                Box::new(path.paths(y).map(|r| r.map_err(Exn::from)))
+                            })
+                        })
+                    })
+                })
+            }
+            // like in jq, a value without path is only an error if `r` yields outputs for it,
+            // so `1 | empty` yields nothing
+            Ast::Pipe(l, false, r) => {
+                let l = l.paths(lut, (cv.0.clone(), cv.1));
+                flat_map_with(l, cv.0, move |y, ctx| match y {
+                    Ok(y) => r.paths(lut, (ctx, y)),
+                    Err(Exn(exn::Inner::Err(e))) => match e.path_expr_val() {
+                        Ok(v) => {
+                            let ys = r.paths(lut, (ctx, (v, Vec::new())));
+                            not_paths(Box::new(ys.map(|y| y.map(|(v, _)| v))))
+                        }
+                        Err(e) => box_once(Err(Exn::from(e))),
+                    },
+                    Err(e) => box_once(Err(e)),
+                })
+            }
+            Ast::Pipe(l, true, r) => {
+                let xs = l.run(lut, (cv.0.clone(), cv.1 .0.clone()));
+                flat_map_with(xs, cv, move |x, cv| {
+                    then(x, |x| r.paths(lut, (cv.0.cons_var(x), cv.1)))
+                })
+            }
+            Ast::Comma(l, r) => Box::new(l.paths(lut, cv.clone()).chain(r.paths(lut, cv))),
+            Ast::Ite(if_, then_, else_) => {
+                let xs = if_.run(lut, (cv.0.clone(), cv.1 .0.clone()));
+                flat_map_with(xs, cv, move |x, cv| {
+                    then(x, |x| {
+                        if x.as_bool() { then_ } else { else_ }.paths(lut, cv)
+                    })
+                })
+            }
+            Ast::Alt(l, r) => {
+                let truthy = |y: &Result<ValPath<_>, _>| alt_truthy(y.as_ref().map(|(v, _)| v));
+                let mut l = l.paths(lut, cv.clone()).filter(truthy);
+                match l.next() {
+                    Some(head) => Box::new(core::iter::once(head).chain(l)),
+                    None => r.paths(lut, cv),
+                }
+            }
+            // the catch handler receives an error, which has no path
+            Ast::TryCatch(f, c) => Box::new(f.paths(lut, (cv.0.clone(), cv.1)).flat_map(
+                move |y| match y {
+                    Err(Exn(exn::Inner::Err(e))) if e.as_halt().is_none() => {
+                        not_paths(c.run(lut, (cv.0.clone(), e.into_val())))
+                    }
+                    y => box_once(y),
+                },
+            )),
+
+            Ast::Var(v, skip) => match cv.0.vars.get(*v).unwrap() {
+                Bind::Var(_) => box_once(Err(Exn::from(Error::path_expr()))),
+                Bind::Fun((id, vars)) => {
+                    label_skip(id.paths(lut, (cv.0.with_vars(vars.clone()), cv.1)), *skip)
+                }
+            },
+            Ast::CallDef(id, args, skip, _tailrec) => {
+                let (ctx, (v, path)) = cv;
+                let cvs = bind_vars(args, lut, ctx.clone().skip_vars(*skip), (ctx, v));
+                flat_map_with(cvs, path, move |cv, path| {
+                    then(cv, |cv| id.paths(lut, (cv.0, (cv.1, path))))
+                })
+            }
+            Ast::Native(id, args) => {
+                let (ctx, (v, path)) = cv;
+                let cvs = bind_vars(args, lut, ctx.without_vars(), (ctx, v));
+                flat_map_with(cvs, path, move |cv, path| {
+                    then(cv, |cv| lut.funs[*id].paths(lut, (cv.0, (cv.1, path))))
+                })
+            }
+            Ast::Origin(origin, f) => or_origin(f.paths(lut, cv), origin),
+            Ast::Label(id) => Box::new(id.paths(lut, cv).map_while(|y| match y {
+                Err(Exn(exn::Inner::Break(n))) => {
+                    n.checked_sub(1).map(|m| Err(Exn(exn::Inner::Break(m))))
+                }
+                y => Some(y),
+            })),
             Ast::Break(skip) => box_once(Err(Exn(exn::Inner::Break(*skip)))),
         }
     }
@@ -377,6 +626,15 @@ pub trait FilterT<F: FilterT<F, V = Self::V> = Self> {
         f: BoxUpdate<'a, Self::V>,
     ) -> ValXs<'a, Self::V>;
 
+    /// `f.paths((c, (v, p)))` returns the outputs of `v | f` in the context `c`,
+    /// together with their paths, where the path of `v` is `p`.
+    ///
+    /// The default implementation yields an error, meaning that `f` is no path expression.
+    fn paths<'a>(&'a self, lut: &'a Lut<F>, cv: Cvp<'a, Self::V>) -> ValPathXs<'a, Self::V> {
+        let _ = (lut, cv);
+        box_once(Err(Exn::from(Error::path_expr())))
+    }
+
     /// For every value `v` returned by `self.run(cv)`, call `f(cv, v)` and return all results.
     ///
     /// This has a special optimisation for the case where only a single `v` is returned.
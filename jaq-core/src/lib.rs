@@ -70,11 +70,13 @@ pub mod val;
 
 pub use compile::Compiler;
 pub use exn::{Error, Exn};
-pub use filter::{Cv, FilterT, Native, RunPtr, UpdatePtr};
+pub use filter::{Cv, Cvp, FilterT, Native, PathsPtr, RunPtr, UpdatePtr, ValPathXs};
 pub use rc_iter::RcIter;
 pub use val::{ValR, ValT, ValX, ValXs};
 
-use alloc::string::String;
+#[cfg(feature = "std")]
+use alloc::vec::Vec;
+use alloc::{boxed::Box, string::String};
 use rc_list::List as RcList;
 use stack::Stack;
 
@@ -127,18 +129,52 @@ impl<'a, V> Vars<'a, V> {
     }
 }
 
+/// Environment yielded by the filter `env`.
+pub enum Env<'a, V> {
+    /// Fixed environment, such as a snapshot of the process environment taken at startup
+    Snapshot(V),
+    /// Function that yields the current environment whenever `env` is called
+    Live(&'a dyn Fn() -> V),
+}
+
+impl<V: Clone> Env<'_, V> {
+    /// Return the environment.
+    pub fn get(&self) -> V {
+        match self {
+            Self::Snapshot(v) => v.clone(),
+            Self::Live(f) => f(),
+        }
+    }
+}
+
 /// Filter execution context.
 #[derive(Clone)]
 pub struct Ctx<'a, V> {
     vars: Vars<'a, V>,
     inputs: &'a Inputs<'a, V>,
+    env: Option<&'a Env<'a, V>>,
 }
 
 impl<'a, V> Ctx<'a, V> {
     /// Construct a context.
     pub fn new(vars: impl IntoIterator<Item = V>, inputs: &'a Inputs<'a, V>) -> Self {
         let vars = Vars(RcList::new().extend(vars.into_iter().map(Bind::Var)));
-        Self { vars, inputs }
+        let env = None;
+        Self { vars, inputs, env }
+    }
+
+    /// Set the environment yielded by `env`.
+    ///
+    /// If no environment is set, the standard library makes `env` yield
+    /// a snapshot of the process environment taken when `env` is first called.
+    pub fn with_env(self, env: &'a Env<'a, V>) -> Self {
+        let env = Some(env);
+        Self { env, ..self }
+    }
+
+    /// Return the environment set by [`Self::with_env`].
+    pub fn env(&self) -> Option<&'a Env<'a, V>> {
+        self.env
     }
 
     /// Add a new variable binding.
@@ -163,8 +199,13 @@ impl<'a, V> Ctx<'a, V> {
 
     /// Replace variables in context with given ones.
     fn with_vars(&self, vars: Vars<'a, V>) -> Self {
-        let inputs = self.inputs;
-        Self { vars, inputs }
+        let (inputs, env) = (self.inputs, self.env);
+        Self { vars, inputs, env }
+    }
+
+    /// Remove all variables from the context.
+    fn without_vars(&self) -> Self {
+        self.with_vars(Vars(RcList::new()))
     }
 
     /// Return remaining input values.
@@ -194,9 +235,8 @@ impl<'a, V: Clone> Ctx<'a, V> {
             Some((Bind::Fun(head), tail)) => (head, tail),
             _ => panic!(),
         };
-        let inputs = self.inputs;
         self.vars.0 = tail;
-        (id, Self { vars, inputs })
+        (id, self.with_vars(vars))
     }
 }
 
@@ -212,6 +252,81 @@ impl<F: FilterT> Filter<F> {
             .map(|v| v.map_err(|e| e.get_err().ok().unwrap()))
     }
 
+    /// Run a filter on every value of `inputs`, yielding all output values.
+    ///
+    /// Like the jaq binary does, this takes each input from `inputs`
+    /// that has not yet been consumed by `input` or `inputs` in the filter.
+    /// For example, running `[., input]` on four inputs yields two arrays.
+    /// Errors in `inputs` are yielded as errors.
+    pub fn run_with_inputs<'a>(
+        &'a self,
+        vars: impl IntoIterator<Item = F::V> + Clone + 'a,
+        inputs: &'a Inputs<'a, F::V>,
+    ) -> impl Iterator<Item = ValR<F::V>> + 'a {
+        use box_iter::{box_once, BoxIter};
+        inputs.flat_map(move |x| -> BoxIter<_> {
+            match x {
+                Ok(x) => Box::new(self.run((Ctx::new(vars.clone(), inputs), x))),
+                Err(e) => box_once(Err(Error::str(e))),
+            }
+        })
+    }
+
+    /// Run a filter on every value of `inputs` using up to `threads` threads,
+    /// yielding the outputs for each input in the order of `inputs`.
+    ///
+    /// Because values are usually not [`Send`], inputs are converted to values by
+    /// `to_val` and output values are converted back by `from_val` in each thread.
+    /// The filter sees no global variables and no inputs,
+    /// meaning that `input` fails and `inputs` yields nothing.
+    /// Side effects, such as the messages printed by `debug`,
+    /// may be interleaved arbitrarily between inputs.
+    ///
+    /// ~~~
+    /// # use jaq_core::{load, Compiler, Native};
+    /// let arena = load::Arena::default();
+    /// let file = load::File { path: "".into(), code: ". * 2" };
+    /// let modules = load::Loader::new([]).load(&arena, file).unwrap();
+    /// let filter = Compiler::<_, Native<_>>::default().compile(modules).unwrap();
+    ///
+    /// let to_val = |x: i64| jaq_json::Val::from(x as isize);
+    /// let from_val = |y: jaq_core::ValR<jaq_json::Val>| y.unwrap().to_string();
+    /// let out = filter.run_parallel(Vec::from([1, 2, 3]), 2, to_val, from_val);
+    /// assert_eq!(out, [["2"], ["4"], ["6"]]);
+    /// ~~~
+    #[cfg(feature = "std")]
+    pub fn run_parallel<T: Send, U: Send>(
+        &self,
+        inputs: Vec<T>,
+        threads: usize,
+        to_val: impl Fn(T) -> F::V + Sync,
+        from_val: impl Fn(ValR<F::V>) -> U + Sync,
+    ) -> Vec<Vec<U>>
+    where
+        F: Sync,
+    {
+        let threads = threads.max(1);
+        let chunk_len = ((inputs.len() + threads - 1) / threads).max(1);
+        let mut inputs = inputs.into_iter();
+        let chunks = core::iter::from_fn(|| {
+            let chunk: Vec<_> = inputs.by_ref().take(chunk_len).collect();
+            (!chunk.is_empty()).then_some(chunk)
+        });
+        let (to_val, from_val) = (&to_val, &from_val);
+        let run = move |x| {
+            let inputs = RcIter::new(core::iter::empty());
+            let ys = self.run((Ctx::new([], &inputs), to_val(x)));
+            ys.map(from_val).collect()
+        };
+        std::thread::scope(|s| {
+            let spawn =
+                |chunk: Vec<_>| s.spawn(move || chunk.into_iter().map(run).collect::<Vec<_>>());
+            let handles: Vec<_> = chunks.map(spawn).collect();
+            let join = |h: std::thread::ScopedJoinHandle<_>| h.join().unwrap();
+            handles.into_iter().flat_map(join).collect()
+        })
+    }
+
     /// Run a filter on given input, panic if it does not yield the given output.
     ///
     /// This is for testing purposes.
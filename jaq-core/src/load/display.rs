@@ -0,0 +1,555 @@
+//! Conversion of parsed terms back to jq source code.
+//!
+//! Parsing the output of this conversion yields again the original term.
+//! Parentheses are inserted only where necessary to preserve this property.
+//!
+//! The alternate format (`{:#}`) spreads terms that do not fit in a line
+//! over multiple lines and indents them.
+
+use super::lex::StrPart;
+use super::parse::{BinaryOp, Def, Module, Pattern, Term};
+use super::path::{Opt, Part, Path};
+use super::prec_climb::{self, Op};
+use alloc::string::ToString;
+use core::fmt::{self, Display, Formatter};
+use core::ops::Deref;
+
+/// Syntactic context in which a term occurs.
+///
+/// This determines whether a term has to be surrounded by parentheses.
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum Ctx {
+    /// delimited position, such as inside parentheses, where any term is allowed
+    Top,
+    /// object value, where any term is allowed that does not contain a top-level comma
+    NoComma,
+    /// operand of an operator, where binary operations of at least given precedence are allowed
+    Operand(usize),
+    /// position such as `try t`, where only atomic terms are allowed
+    Atom,
+    /// term that is followed by a path, such as `f` in `f.a`
+    PathBase,
+    /// term that is followed by `?`
+    OptBase,
+}
+
+/// Term that is displayed in a given context.
+struct InCtx<'a, S>(&'a Term<S>, Ctx);
+
+impl<S: Deref<Target = str>> Display for InCtx<'_, S> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        if self.0.parens_in(self.1) {
+            write!(f, "({})", InCtx(self.0, Ctx::Top))
+        } else {
+            self.0.fmt_in(f, self.1)
+        }
+    }
+}
+
+/// Term that is displayed in a given context over multiple lines with given indentation.
+struct Pretty<'a, S>(&'a Term<S>, Ctx, usize);
+
+/// Maximal number of characters in a line that the alternate format aims for.
+const WIDTH: usize = 80;
+
+/// Indentation per level in the alternate format.
+const INDENT: &str = "  ";
+
+/// Return true if a line with given indentation and contents fits into [`WIDTH`].
+fn fits(indent: usize, s: &str) -> bool {
+    indent * INDENT.len() + s.chars().count() <= WIDTH
+}
+
+/// Start a new line with given indentation.
+fn newline(f: &mut Formatter, indent: usize) -> fmt::Result {
+    writeln!(f)?;
+    (0..indent).try_for_each(|_| f.write_str(INDENT))
+}
+
+impl<S: Deref<Target = str>> Display for Pretty<'_, S> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let Self(t, ctx, indent) = *self;
+        let flat = t.in_(ctx).to_string();
+        // definitions are always put on separate lines
+        if !matches!(t, Term::Def(..)) && fits(indent, &flat) {
+            f.write_str(&flat)
+        } else if t.parens_in(ctx) {
+            write!(f, "(")?;
+            newline(f, indent + 1)?;
+            write!(f, "{}", Pretty(t, Ctx::Top, indent + 1))?;
+            newline(f, indent)?;
+            write!(f, ")")
+        } else {
+            t.pretty_in(f, ctx, indent)
+        }
+    }
+}
+
+/// Return true if the given string can be written without quotes, such as in `.a` or `{a: 1}`.
+fn is_ident(s: &str) -> bool {
+    let mut chars = s.chars();
+    let first = chars
+        .next()
+        .map_or(false, |c| c.is_ascii_alphabetic() || c == '_');
+    first && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Write elements of an iterator with a separator between them.
+fn sep_by<T>(f: &mut Formatter, sep: &str, xs: impl IntoIterator<Item = T>) -> fmt::Result
+where
+    T: Display,
+{
+    let mut xs = xs.into_iter();
+    if let Some(x) = xs.next() {
+        write!(f, "{x}")?;
+    }
+    xs.try_for_each(|x| write!(f, "{sep}{x}"))
+}
+
+impl<S: Deref<Target = str>> Term<S> {
+    fn ident(&self) -> Option<&str> {
+        self.as_str().map(|s| &**s).filter(|s| is_ident(s))
+    }
+
+    fn in_(&self, ctx: Ctx) -> InCtx<'_, S> {
+        InCtx(self, ctx)
+    }
+
+    /// Return true if the term has to be surrounded by parentheses in the given context.
+    fn parens_in(&self, ctx: Ctx) -> bool {
+        use Ctx::*;
+        match self {
+            // these terms extend as far to the right as possible
+            Self::Pipe(..) | Self::Destruct(..) => !matches!(ctx, Top | NoComma),
+            Self::Def(..) | Self::Label(..) => ctx != Top,
+            Self::BinOp(_, op, _) => match ctx {
+                Top => false,
+                NoComma => matches!(op, BinaryOp::Comma),
+                Operand(prec) => op.precedence() < prec,
+                Atom | PathBase | OptBase => true,
+            },
+            Self::Neg(_) | Self::TryCatch(_, Some(_)) => matches!(ctx, PathBase | OptBase),
+            // `f??` is the same as `f?`
+            Self::TryCatch(_, None) => ctx == OptBase,
+            // `1.a` and `...a` do not lex, and
+            // `(.a).b` has a different structure than `.a.b`
+            Self::Num(_) | Self::Recurse | Self::Path(..) => matches!(ctx, PathBase | OptBase),
+            _ => false,
+        }
+    }
+
+    fn pretty(&self, ctx: Ctx, indent: usize) -> Pretty<'_, S> {
+        Pretty(self, ctx, indent)
+    }
+
+    /// Write the term over multiple lines, assuming that it needs no parentheses.
+    fn pretty_in(&self, f: &mut Formatter, ctx: Ctx, indent: usize) -> fmt::Result {
+        let args = |f: &mut Formatter, args: &[Self]| {
+            write!(f, "(")?;
+            for (i, arg) in args.iter().enumerate() {
+                if i > 0 {
+                    write!(f, ";")?;
+                }
+                newline(f, indent + 1)?;
+                write!(f, "{}", arg.pretty(Ctx::Top, indent + 1))?;
+            }
+            newline(f, indent)?;
+            write!(f, ")")
+        };
+        match self {
+            Self::Pipe(l, _, r) | Self::Destruct(l, _, r) => {
+                let prec = if ctx == Ctx::NoComma { 2 } else { 1 };
+                write!(f, "{}", l.pretty(Ctx::Operand(prec), indent))?;
+                self.fmt_binder(f)?;
+                newline(f, indent)?;
+                write!(f, "| ")?;
+                // put every element of a pipe chain on a separate line
+                match **r {
+                    Self::Pipe(..) | Self::Destruct(..) => r.pretty_in(f, ctx, indent),
+                    _ => write!(f, "{}", r.pretty(ctx, indent + 1)),
+                }
+            }
+            Self::BinOp(l, BinaryOp::Comma, r) => {
+                let prec = BinaryOp::Comma.precedence();
+                match **l {
+                    Self::BinOp(_, BinaryOp::Comma, _) => l.pretty_in(f, ctx, indent)?,
+                    _ => write!(f, "{}", l.pretty(Ctx::Operand(prec), indent))?,
+                }
+                write!(f, ",")?;
+                newline(f, indent)?;
+                write!(f, "{}", r.pretty(Ctx::Operand(prec + 1), indent))
+            }
+            Self::BinOp(l, op, r) => {
+                use prec_climb::Associativity::{Left, Right};
+                let prec = op.precedence();
+                let (lp, rp) = match op.associativity() {
+                    Left => (prec, prec + 1),
+                    Right => (prec + 1, prec),
+                };
+                let l = l.pretty(Ctx::Operand(lp), indent);
+                let r = r.pretty(Ctx::Operand(rp), indent);
+                write!(f, "{l} {op} {r}")
+            }
+            Self::Arr(Some(t)) => {
+                write!(f, "[")?;
+                newline(f, indent + 1)?;
+                write!(f, "{}", t.pretty(Ctx::Top, indent + 1))?;
+                newline(f, indent)?;
+                write!(f, "]")
+            }
+            Self::Obj(kvs) => {
+                write!(f, "{{")?;
+                for (i, (k, v)) in kvs.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    newline(f, indent + 1)?;
+                    fmt_key(f, k)?;
+                    if let Some(v) = v {
+                        write!(f, ": {}", v.pretty(Ctx::NoComma, indent + 1))?;
+                    }
+                }
+                newline(f, indent)?;
+                write!(f, "}}")
+            }
+            Self::Neg(t) => {
+                let space = if matches!(**t, Self::Neg(_)) { " " } else { "" };
+                write!(f, "-{space}{}", t.pretty(Ctx::Atom, indent))
+            }
+            Self::Label(x, t) => {
+                write!(f, "label {}", &**x)?;
+                newline(f, indent)?;
+                write!(f, "| {}", t.pretty(Ctx::Top, indent))
+            }
+            Self::Fold(name, xs, x, fold_args) => {
+                let xs = xs.pretty(Ctx::Atom, indent);
+                write!(f, "{} {xs} as {} ", &**name, &**x)?;
+                args(f, fold_args)
+            }
+            Self::TryCatch(t, None) => write!(f, "{}?", t.pretty(Ctx::OptBase, indent)),
+            Self::TryCatch(t, Some(c)) => {
+                let t = t.pretty(Ctx::Atom, indent);
+                write!(f, "try {t} catch {}", c.pretty(Ctx::Atom, indent))
+            }
+            Self::IfThenElse(if_thens, else_) => {
+                for (i, (if_, then_)) in if_thens.iter().enumerate() {
+                    if i > 0 {
+                        newline(f, indent)?;
+                        write!(f, "el")?;
+                    }
+                    write!(f, "if {} then", if_.pretty(Ctx::Top, indent))?;
+                    newline(f, indent + 1)?;
+                    write!(f, "{}", then_.pretty(Ctx::Top, indent + 1))?;
+                }
+                if let Some(else_) = else_ {
+                    newline(f, indent)?;
+                    write!(f, "else")?;
+                    newline(f, indent + 1)?;
+                    write!(f, "{}", else_.pretty(Ctx::Top, indent + 1))?;
+                }
+                newline(f, indent)?;
+                write!(f, "end")
+            }
+            Self::Def(defs, t) => {
+                for d in defs {
+                    d.pretty(f, indent)?;
+                    newline(f, indent)?;
+                }
+                write!(f, "{}", t.pretty(Ctx::Top, indent))
+            }
+            Self::Call(name, call_args) if !call_args.is_empty() => {
+                write!(f, "{}", &**name)?;
+                args(f, call_args)
+            }
+            Self::Path(t, path) if !matches!(**t, Self::Id) => {
+                write!(f, "{}", t.pretty(Ctx::PathBase, indent))?;
+                fmt_path(f, path)
+            }
+            _ => self.fmt_in(f, ctx),
+        }
+    }
+
+    /// Write the variable binding of a pipe, such as ` as $x` or ` as [$x] ?// {a: $x}`.
+    fn fmt_binder(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Self::Pipe(_, Some(x), _) => write!(f, " as {}", &**x),
+            Self::Destruct(_, pats, _) => {
+                write!(f, " as ")?;
+                sep_by(f, " ?// ", pats)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn fmt_in(&self, f: &mut Formatter, ctx: Ctx) -> fmt::Result {
+        match self {
+            Self::Id => write!(f, "."),
+            Self::Recurse => write!(f, ".."),
+            Self::Bool(b) => write!(f, "{b}"),
+            Self::Null => write!(f, "null"),
+            Self::Num(n) => write!(f, "{}", &**n),
+            Self::Str(fmt, parts) => {
+                if let Some(fmt) = fmt {
+                    write!(f, "{} ", &**fmt)?;
+                }
+                write!(f, "\"")?;
+                parts.iter().try_for_each(|part| match part {
+                    StrPart::Str(s) => write!(f, "{}", &**s),
+                    StrPart::Term(t) => write!(f, "\\({})", t.in_(Ctx::Top)),
+                    StrPart::Char(c) => fmt_char(f, *c),
+                })?;
+                write!(f, "\"")
+            }
+            Self::Arr(None) => write!(f, "[]"),
+            Self::Arr(Some(t)) => write!(f, "[{}]", t.in_(Ctx::Top)),
+            Self::Obj(kvs) => {
+                write!(f, "{{")?;
+                for (i, (k, v)) in kvs.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    fmt_key(f, k)?;
+                    if let Some(v) = v {
+                        write!(f, ": {}", v.in_(Ctx::NoComma))?;
+                    }
+                }
+                write!(f, "}}")
+            }
+            Self::Neg(t) => {
+                // `--1` would be lexed as a single symbol `--`
+                let space = if matches!(**t, Self::Neg(_)) { " " } else { "" };
+                write!(f, "-{space}{}", t.in_(Ctx::Atom))
+            }
+            Self::Pipe(l, _, r) | Self::Destruct(l, _, r) => {
+                let prec = if ctx == Ctx::NoComma { 2 } else { 1 };
+                write!(f, "{}", l.in_(Ctx::Operand(prec)))?;
+                self.fmt_binder(f)?;
+                write!(f, " | {}", r.in_(ctx))
+            }
+            Self::BinOp(l, op, r) => {
+                use prec_climb::Associativity::{Left, Right};
+                let prec = op.precedence();
+                let (lp, rp) = match op.associativity() {
+                    Left => (prec, prec + 1),
+                    Right => (prec + 1, prec),
+                };
+                let l = l.in_(Ctx::Operand(lp));
+                let r = r.in_(Ctx::Operand(rp));
+                match op {
+                    BinaryOp::Comma => write!(f, "{l}, {r}"),
+                    op => write!(f, "{l} {op} {r}"),
+                }
+            }
+            Self::Label(x, t) => write!(f, "label {} | {}", &**x, t.in_(Ctx::Top)),
+            Self::Break(x) => write!(f, "break {}", &**x),
+            Self::Fold(name, xs, x, args) => {
+                write!(f, "{} {} as {} (", &**name, xs.in_(Ctx::Atom), &**x)?;
+                sep_by(f, "; ", args.iter().map(|a| a.in_(Ctx::Top)))?;
+                write!(f, ")")
+            }
+            Self::TryCatch(t, None) => write!(f, "{}?", t.in_(Ctx::OptBase)),
+            Self::TryCatch(t, Some(c)) => {
+                write!(f, "try {} catch {}", t.in_(Ctx::Atom), c.in_(Ctx::Atom))
+            }
+            Self::IfThenElse(if_thens, else_) => {
+                let mut if_thens = if_thens.iter();
+                if let Some((if_, then_)) = if_thens.next() {
+                    write!(f, "if {} then {}", if_.in_(Ctx::Top), then_.in_(Ctx::Top))?;
+                }
+                for (if_, then_) in if_thens {
+                    write!(
+                        f,
+                        " elif {} then {}",
+                        if_.in_(Ctx::Top),
+                        then_.in_(Ctx::Top)
+                    )?;
+                }
+                if let Some(else_) = else_ {
+                    write!(f, " else {}", else_.in_(Ctx::Top))?;
+                }
+                write!(f, " end")
+            }
+            Self::Def(defs, t) => {
+                defs.iter().try_for_each(|d| write!(f, "{d} "))?;
+                write!(f, "{}", t.in_(Ctx::Top))
+            }
+            Self::Call(name, args) => {
+                write!(f, "{}", &**name)?;
+                if !args.is_empty() {
+                    write!(f, "(")?;
+                    sep_by(f, "; ", args.iter().map(|a| a.in_(Ctx::Top)))?;
+                    write!(f, ")")?;
+                }
+                Ok(())
+            }
+            Self::Var(x) => write!(f, "{}", &**x),
+            Self::Path(t, path) => {
+                // `.a` and `.[0]` are paths that start from the identity
+                if let Self::Id = **t {
+                    if !matches!(path.0.first(), Some((Part::Index(k), _)) if k.ident().is_some()) {
+                        write!(f, ".")?;
+                    }
+                } else {
+                    write!(f, "{}", t.in_(Ctx::PathBase))?;
+                }
+                fmt_path(f, path)
+            }
+        }
+    }
+}
+
+impl<S: Deref<Target = str>> Display for Pattern<S> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Self::Var(x) => write!(f, "{}", &**x),
+            Self::Arr(pats) => {
+                write!(f, "[")?;
+                sep_by(f, ", ", pats)?;
+                write!(f, "]")
+            }
+            Self::Obj(entries) => {
+                write!(f, "{{")?;
+                for (i, (k, p)) in entries.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    // `{$x: p}` would bind `$x` instead of using it as key
+                    match k {
+                        Term::Var(_) => write!(f, "({k})")?,
+                        k => fmt_key(f, k)?,
+                    }
+                    write!(f, ": {p}")?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+/// Write the key of an object entry.
+fn fmt_key<S: Deref<Target = str>>(f: &mut Formatter, k: &Term<S>) -> fmt::Result {
+    match (k.ident(), k) {
+        (Some(id), _) => write!(f, "{id}"),
+        (None, Term::Var(_) | Term::Str(..)) => write!(f, "{k}"),
+        (None, k) => write!(f, "({k})"),
+    }
+}
+
+fn fmt_path<S: Deref<Target = str>>(f: &mut Formatter, path: &Path<Term<S>>) -> fmt::Result {
+    for (part, opt) in &path.0 {
+        match part {
+            Part::Index(k) => match k.ident() {
+                Some(id) => write!(f, ".{id}"),
+                None => write!(f, "[{k}]"),
+            },
+            Part::Iter => write!(f, "[]"),
+            Part::Range(None, None) => write!(f, "[:]"),
+            Part::Range(Some(from), None) => write!(f, "[{from}:]"),
+            Part::Range(None, Some(upto)) => write!(f, "[:{upto}]"),
+            Part::Range(Some(from), Some(upto)) => write!(f, "[{from}:{upto}]"),
+        }?;
+        if let Opt::Optional = opt {
+            write!(f, "?")?;
+        }
+    }
+    Ok(())
+}
+
+/// Write a character that was written as escape sequence in a string.
+fn fmt_char(f: &mut Formatter, c: char) -> fmt::Result {
+    match c {
+        '\\' | '/' | '"' => write!(f, "\\{c}"),
+        '\x08' => write!(f, "\\b"),
+        '\x0C' => write!(f, "\\f"),
+        '\n' => write!(f, "\\n"),
+        '\r' => write!(f, "\\r"),
+        '\t' => write!(f, "\\t"),
+        c if (c as u32) <= 0xFFFF => write!(f, "\\u{:04x}", c as u32),
+        c => write!(f, "{c}"),
+    }
+}
+
+impl<S: Deref<Target = str>> Display for Term<S> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        if f.alternate() {
+            self.pretty(Ctx::Top, 0).fmt(f)
+        } else {
+            self.in_(Ctx::Top).fmt(f)
+        }
+    }
+}
+
+impl<S: Deref<Target = str>> Def<S> {
+    /// Write `def name(args):`.
+    fn fmt_head(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "def {}", &*self.name)?;
+        if !self.args.is_empty() {
+            write!(f, "(")?;
+            sep_by(f, "; ", self.args.iter().map(|a| &**a))?;
+            write!(f, ")")?;
+        }
+        write!(f, ":")
+    }
+
+    fn pretty(&self, f: &mut Formatter, indent: usize) -> fmt::Result {
+        let flat = self.to_string();
+        if !matches!(self.body, Term::Def(..)) && fits(indent, &flat) {
+            f.write_str(&flat)
+        } else {
+            self.fmt_head(f)?;
+            newline(f, indent + 1)?;
+            write!(f, "{};", self.body.pretty(Ctx::Top, indent + 1))
+        }
+    }
+}
+
+impl<S: Deref<Target = str>> Display for Def<S> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        self.fmt_head(f)?;
+        write!(f, " {};", self.body)
+    }
+}
+
+impl<S: Deref<Target = str>, B: Display> Display for Module<S, B> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let sep = if f.alternate() { "\n" } else { " " };
+        if let Some(meta) = &self.meta {
+            write!(f, "module {meta};{sep}")?;
+        }
+        for (path, name, meta) in &self.deps {
+            match name {
+                Some(name) => write!(f, "import \"{}\" as {}", &**path, &**name)?,
+                None => write!(f, "include \"{}\"", &**path)?,
+            }
+            if let Some(meta) = meta {
+                write!(f, " {meta}")?;
+            }
+            write!(f, ";{sep}")?;
+        }
+        if f.alternate() {
+            if self.meta.is_some() || !self.deps.is_empty() {
+                writeln!(f)?;
+            }
+            write!(f, "{:#}", self.body)
+        } else {
+            write!(f, "{}", self.body)
+        }
+    }
+}
+
+impl Display for BinaryOp {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Self::Comma => write!(f, ","),
+            Self::Alt => write!(f, "//"),
+            Self::Or => write!(f, "or"),
+            Self::And => write!(f, "and"),
+            Self::Math(op) => write!(f, "{}", op.as_str()),
+            Self::Cmp(op) => write!(f, "{}", op.as_str()),
+            Self::Assign => write!(f, "="),
+            Self::Update => write!(f, "|="),
+            Self::UpdateMath(op) => write!(f, "{}=", op.as_str()),
+            Self::UpdateAlt => write!(f, "//="),
+        }
+    }
+}
@@ -6,7 +6,8 @@ use alloc::vec::Vec;
 ///
 /// `S` is a type of strings (without escape sequences), and
 /// `F` is a type of interpolated filters.
-#[derive(Debug)]
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum StrPart<S, T> {
     /// string without escape sequences
     Str(S),
@@ -44,7 +45,7 @@ pub(crate) enum Tok<S> {
 #[derive(Clone, Debug)]
 #[non_exhaustive]
 pub enum Expect<S> {
-    /// `0e`, `0.`
+    /// `0e`, `1.5e+`
     Digit,
     /// `$`, `@`
     Ident,
@@ -54,11 +55,13 @@ pub enum Expect<S> {
     Escape,
     /// `"\ux"`
     Unicode,
+    /// `"\ud800"`, `"\udc00"`
+    Surrogate,
     /// `&`, `§`, `💣`
     Token,
 }
 
-impl<'a> Expect<&'a str> {
+impl Expect<&str> {
     /// Return human-readable description of what we expected.
     pub fn as_str(&self) -> &'static str {
         match self {
@@ -71,11 +74,23 @@ impl<'a> Expect<&'a str> {
             Self::Delim(_) => panic!(),
             Self::Escape => "string escape sequence",
             Self::Unicode => "4-digit hexadecimal UTF-8 code point",
+            Self::Surrogate => "surrogate pair",
             Self::Token => "token",
         }
     }
 }
 
+/// Parse four hexadecimal digits, returning the remaining input at the first non-digit.
+fn hex4<'a>(chars: &mut core::str::Chars<'a>) -> Result<u32, &'a str> {
+    let mut hex = 0;
+    for _ in 0..4 {
+        let i = chars.as_str();
+        let digit = chars.next().and_then(|c| c.to_digit(16)).ok_or(i)?;
+        hex = (hex << 4) + digit;
+    }
+    Ok(hex)
+}
+
 /// Lexer error, storing what we expected and what we got instead.
 pub type Error<S> = (Expect<S>, S);
 
@@ -180,13 +195,35 @@ impl<'a> Lexer<&'a str> {
         }
     }
 
-    /// Decimal with optional exponent.
+    /// Lex the longest operator at the start of the input, such as `|=` or `//`.
+    ///
+    /// This makes `|-1` two tokens `|` and `-`, just like `| -1`.
+    fn op(&mut self) -> &'a str {
+        const OPS: [&str; 12] = [
+            "//=", "|=", "==", "!=", "<=", ">=", "+=", "-=", "*=", "/=", "%=", "//",
+        ];
+        let op = OPS.iter().find(|op| self.i.starts_with(*op));
+        self.take(op.map_or(1, |op| op.len()))
+    }
+
+    /// Decimal with optional fraction and exponent, such as `1`, `1.`, or `1.5e3`.
     fn num(&mut self) {
         self.trim(|c| c.is_ascii_digit());
         if let Some(i) = self.i.strip_prefix('.') {
             self.i = i;
-            self.digits1();
+            self.trim(|c| c.is_ascii_digit());
         }
+        self.exp();
+    }
+
+    /// Fraction without integer part, with optional exponent, such as `5` in `.5`.
+    fn frac(&mut self) {
+        self.digits1();
+        self.exp();
+    }
+
+    /// Optional exponent, such as `e-3`.
+    fn exp(&mut self) {
         if let Some(i) = self.i.strip_prefix(['e', 'E']) {
             self.i = i.strip_prefix(['+', '-']).unwrap_or(i);
             self.digits1();
@@ -203,18 +240,36 @@ impl<'a> Lexer<&'a str> {
             Some('r') => StrPart::Char('\r'),
             Some('t') => StrPart::Char('\t'),
             Some('u') => {
-                let mut hex = 0;
-                for _ in 0..4 {
-                    let i = chars.as_str();
-                    if let Some(digit) = chars.next().and_then(|c| c.to_digit(16)) {
-                        hex = (hex << 4) + digit;
-                    } else {
+                let start = self.i;
+                let c = hex4(&mut chars).and_then(|hi| match hi {
+                    // a high surrogate has to be followed by an escaped low surrogate
+                    0xD800..=0xDBFF => match chars.as_str().strip_prefix("\\u") {
+                        Some(i) => {
+                            chars = i.chars();
+                            let lo = hex4(&mut chars)?;
+                            let lo = lo.checked_sub(0xDC00).filter(|lo| *lo < 0x400);
+                            Ok(lo.and_then(|lo| {
+                                char::from_u32(0x10000 + ((hi - 0xD800) << 10) + lo)
+                            }))
+                        }
+                        None => Ok(None),
+                    },
+                    // this yields `None` for a lone low surrogate
+                    _ => Ok(char::from_u32(hi)),
+                });
+                match c {
+                    Ok(Some(c)) => StrPart::Char(c),
+                    Ok(None) => {
+                        self.i = chars.as_str();
+                        self.e.push((Expect::Surrogate, start));
+                        return None;
+                    }
+                    Err(i) => {
                         self.i = i;
                         self.e.push((Expect::Unicode, self.i));
                         return None;
                     }
                 }
-                StrPart::Char(char::from_u32(hex).unwrap())
             }
             Some('(') => {
                 let (full, block) = self.with_consumed(Self::block);
@@ -268,10 +323,15 @@ impl<'a> Lexer<&'a str> {
             '$' => (self.consumed(1, Self::ident1), Tok::Var),
             '@' => (self.consumed(1, Self::ident1), Tok::Fmt),
             '0'..='9' => (self.consumed(1, Self::num), Tok::Num),
-            c if is_op(c) => (self.consumed(1, |lex| lex.trim(is_op)), Tok::Sym),
+            c if is_op(c) => (self.op(), Tok::Sym),
             '.' => match chars.next() {
-                Some('.') => (self.take(2), Tok::Sym),
+                // `..a` is lexed as a single token
+                Some('.') => match chars.next() {
+                    Some('a'..='z' | 'A'..='Z' | '_') => (self.consumed(3, Self::ident0), Tok::Sym),
+                    _ => (self.take(2), Tok::Sym),
+                },
                 Some('a'..='z' | 'A'..='Z' | '_') => (self.consumed(2, Self::ident0), Tok::Sym),
+                Some('0'..='9') => (self.consumed(1, Self::frac), Tok::Num),
                 _ => (self.take(1), Tok::Sym),
             },
             ':' | ';' | ',' | '?' => (self.take(1), Tok::Sym),
@@ -1,9 +1,12 @@
 //! Combined file loading, lexing, and parsing for multiple modules.
 
+mod display;
 pub mod lex;
 pub mod parse;
 mod prec_climb;
 pub mod test;
+pub mod unused;
+pub mod visit;
 
 use crate::{ops, path};
 use alloc::{boxed::Box, string::String, vec::Vec};
@@ -168,7 +171,7 @@ impl<'s> Loader<&'s str, ReadFn> {
     /// (including the main module).
     /// That means that all filters defined in the prelude can be called from any module.
     ///
-    /// The prelude is normally initialised with filters like `map` or `true`.
+    /// The prelude is normally initialised with filters like `map` or `select`.
     pub fn new(prelude: impl IntoIterator<Item = Def<&'s str>>) -> Self {
         let defs = [
             Def::new("!recurse", Vec::new(), Term::recurse("!recurse")),
@@ -419,6 +422,83 @@ where
     Parser::new(&Lexer::new(s).lex().ok()?).parse(f).ok()
 }
 
+/// Lex or parse error, with the location where it occurred.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseError<'a> {
+    /// byte range of `found` in the code
+    pub span: core::ops::Range<usize>,
+    /// description of what was expected, such as `"closing parenthesis"`
+    pub expected: &'a str,
+    /// unexpected character or token, or `""` at the end of input
+    pub found: &'a str,
+}
+
+impl<'a> ParseError<'a> {
+    fn new(code: &'a str, expected: &'a str, found: &'a str) -> Self {
+        let span = span(code, found);
+        Self {
+            span,
+            expected,
+            found,
+        }
+    }
+}
+
+/// Lex and parse a term, such as `def f: 1; f + 1`.
+///
+/// This is the simplest way to parse a filter
+/// when you do not need the definitions and imports handled by [`Loader`].
+/// Unlike [`parse`], this yields all errors with their location.
+///
+/// Example:
+///
+/// ~~~
+/// # use jaq_core::load::{parse_term, ParseError};
+/// assert!(parse_term("def f: 1; [f]").is_ok());
+///
+/// let errs = parse_term("[1, 2").unwrap_err();
+/// let (span, expected, found) = (5..5, "closing bracket", "");
+/// assert_eq!(errs, [ParseError { span, expected, found }]);
+///
+/// let errs = parse_term("1 + then").unwrap_err();
+/// let (span, expected, found) = (4..8, "term", "then");
+/// assert_eq!(errs, [ParseError { span, expected, found }]);
+/// ~~~
+pub fn parse_term(code: &str) -> Result<Term<&str>, Vec<ParseError<'_>>> {
+    let tokens = lex::Lexer::new(code).lex().map_err(|errs| {
+        let errs = errs.into_iter().map(|(expected, found)| {
+            // truncate found string to its first character
+            let found = &found[..found.chars().next().map_or(0, char::len_utf8)];
+            ParseError::new(code, expected.as_str(), found)
+        });
+        errs.collect::<Vec<_>>()
+    })?;
+    parse::Parser::new(&tokens)
+        .parse(|p| p.term())
+        .map_err(|errs| {
+            let errs = errs.into_iter().map(|(expected, found)| {
+                ParseError::new(code, expected.as_str(), Token::opt_as_str(found, code))
+            });
+            errs.collect()
+        })
+}
+
+/// Format the source code of a main module canonically.
+///
+/// Parsing the result yields the same module as parsing the original code.
+/// Comments are not preserved.
+///
+/// Example:
+///
+/// ~~~
+/// # use jaq_core::load::format;
+/// let code = "def f: .+1  ;[.[]|f]";
+/// assert_eq!(format(code).unwrap(), "def f: . + 1;\n[.[] | f]");
+/// ~~~
+pub fn format(code: &str) -> Result<String, Error<&str>> {
+    parse_main(code).map(|module| alloc::format!("{module:#}"))
+}
+
 /// Return the span of a string slice `part` relative to a string slice `whole`.
 ///
 /// The caller must ensure that `part` is fully contained inside `whole`.
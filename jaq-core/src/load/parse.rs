@@ -39,6 +39,8 @@ pub enum Expect<S> {
     Str,
     /// `0;`
     Nothing,
+    /// `. as [$x] ?// [$y] | .`
+    AltVar,
 }
 
 impl<'a> Expect<&'a str> {
@@ -56,6 +58,7 @@ impl<'a> Expect<&'a str> {
             Self::Arg => "argument",
             Self::Str => "string",
             Self::Nothing => "nothing",
+            Self::AltVar => "variable bound by all alternatives",
         }
     }
 }
@@ -71,8 +74,20 @@ pub struct Parser<'s, 't> {
     fold: &'s [&'s str],
 }
 
+/// Version of the format of terms when they are serialized with the `serde` feature.
+///
+/// This is increased whenever the types of terms, such as [`Term`] or [`Def`],
+/// change in a way that makes serialized terms of former versions unreadable.
+/// To detect such incompatibilities, store this version together with serialized terms.
+pub const AST_VERSION: usize = 1;
+
 /// Function from value to stream of values, such as `.[] | add / length`.
-#[derive(Debug, Default)]
+///
+/// Terms are compared by their structure and their strings.
+/// For strings that are slices of the source code, this means that
+/// terms that differ only in whitespace, comments, or their position are equal.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Term<S> {
     /// Identity, i.e. `.`
     #[default]
@@ -80,6 +95,10 @@ pub enum Term<S> {
     /// Recursion (`..`)
     Recurse,
 
+    /// Boolean (`true`, `false`)
+    Bool(bool),
+    /// Null (`null`)
+    Null,
     /// Integer or floating-point number
     Num(S),
     /// String
@@ -96,6 +115,10 @@ pub enum Term<S> {
     Neg(Box<Self>),
     /// Application, i.e. `l | r` if no string is given, else `l as $x | r`
     Pipe(Box<Self>, Option<S>, Box<Self>),
+    /// Destructuring variable binding, e.g. `l as [$x, $y] | r` or `l as [$x] ?// {a: $x} | r`
+    ///
+    /// This stores one or more alternative patterns, all binding the same variables.
+    Destruct(Box<Self>, Vec<Pattern<S>>, Box<Self>),
 
     /// Sequence of binary operations, e.g. `1 + 2 - 3 * 4`
     BinOp(Box<Self>, BinaryOp, Box<Self>),
@@ -123,8 +146,23 @@ pub enum Term<S> {
     Path(Box<Self>, Path<Self>),
 }
 
+/// Pattern that destructures a value, binding variables to its parts.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Pattern<S> {
+    /// Variable, such as `$x`
+    Var(S),
+    /// Array, such as `[$x, [$y]]`
+    Arr(Vec<Self>),
+    /// Object, such as `{a: $x, (.k): $v}`
+    ///
+    /// The parser desugars `{$x}` to `{x: $x}` and `{$x: p}` to `{x: $x, x: p}`.
+    Obj(Vec<(Term<S>, Self)>),
+}
+
 /// Binary operators, such as `|`, `,`, `//`, ...
-#[derive(Debug)]
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum BinaryOp {
     /// Concatenation, i.e. `l, r`
     Comma,
@@ -166,7 +204,7 @@ impl<S> Term<S> {
     /// `..`, also known as `recurse/0`, is defined as `., (.[]? | ..)`.
     pub(crate) fn recurse(recurse: S) -> Self {
         // `[]?`
-        let path = (path::Part::Range(None, None), path::Opt::Optional);
+        let path = (path::Part::Iter, path::Opt::Optional);
         // `.[]?` (returns array/object elements or nothing instead)
         let path = Term::Path(Term::Id.into(), Path(Vec::from([path])));
 
@@ -181,7 +219,7 @@ impl<S> Term<S> {
     /// `{}[]` returns zero values.
     pub(crate) fn empty() -> Self {
         // `[]`
-        let path = (path::Part::Range(None, None), path::Opt::Essential);
+        let path = (path::Part::Iter, path::Opt::Essential);
         // `{}`
         let obj = Term::Obj(Vec::new());
         // `{}[]`
@@ -371,14 +409,14 @@ impl<'s, 't> Parser<'s, 't> {
         })
     }
 
-    /// If the next token starts with `.`, but is not `..`,
+    /// If the next token starts with `.`, but not with `..`,
     /// return the string after the initial `.` and advance input.
     ///
     /// This matches `.` and `.key`, where `key` is any valid identifier that matches
     /// `[a-zA-Z_][a-zA-Z0-9_]*`.
     fn dot(&mut self) -> Option<&'s str> {
         self.maybe(|p| match p.i.next() {
-            Some(Token(c, _)) if *c != ".." => c.strip_prefix('.'),
+            Some(Token(c, Tok::Sym)) if !c.starts_with("..") => c.strip_prefix('.'),
             _ => None,
         })
     }
@@ -421,18 +459,114 @@ impl<'s, 't> Parser<'s, 't> {
         let pipe = self.try_maybe(|p| match p.i.next() {
             Some(Token("|", _)) => Ok(Some(None)),
             Some(Token("as", _)) => {
-                let x = p.var()?;
+                let pats = p.patterns()?;
                 p.just("|")?;
-                Ok(Some(Some(x)))
+                Ok(Some(Some(pats)))
             }
             _ => Ok(None),
         })?;
-        Ok(match pipe {
-            None => tm,
-            Some(x) => Term::Pipe(Box::new(tm), x, Box::new(self.term_with_comma(with_comma)?)),
+        let pats = match pipe {
+            None => return Ok(tm),
+            Some(pats) => pats,
+        };
+        let (tm, r) = (Box::new(tm), Box::new(self.term_with_comma(with_comma)?));
+        Ok(match pats {
+            None => Term::Pipe(tm, None, r),
+            Some(pats) => match &pats[..] {
+                [Pattern::Var(x)] => Term::Pipe(tm, Some(*x), r),
+                _ => Term::Destruct(tm, pats, r),
+            },
         })
     }
 
+    /// Parse one or more patterns separated by `?//`, such as `[$x] ?// {a: $x}`.
+    ///
+    /// If the patterns do not all bind the same variables,
+    /// this records an error for every variable that is not bound by all patterns.
+    fn patterns(&mut self) -> Result<'s, 't, Vec<Pattern<&'s str>>> {
+        let alt = |p: &mut Self| p.char0('?').and_then(|_| p.just("//").ok());
+        let mut vars = Vec::from([Vec::new()]);
+        let mut pats = Vec::from([self.pattern(&mut vars[0])?]);
+        while self.maybe(alt).is_some() {
+            let mut vs = Vec::new();
+            pats.push(self.pattern(&mut vs)?);
+            vars.push(vs);
+        }
+
+        let bound_by_all = |x: &str| vars.iter().all(|vs| vs.iter().any(|Token(y, _)| *y == x));
+        let mut unbound: Vec<&Token<_>> = Vec::new();
+        for tk in vars.iter().flatten().copied() {
+            if !bound_by_all(tk.0) && !unbound.iter().any(|Token(y, _)| *y == tk.0) {
+                unbound.push(tk);
+            }
+        }
+        self.e
+            .extend(unbound.into_iter().map(|tk| (Expect::AltVar, Some(tk))));
+        Ok(pats)
+    }
+
+    /// Parse a pattern, such as `$x`, `[$x, $y]`, or `{a: $x, $y}`,
+    /// adding the tokens of the variables that it binds to `vars`.
+    fn pattern(&mut self, vars: &mut Vec<&'t Token<&'s str>>) -> Result<'s, 't, Pattern<&'s str>> {
+        match self.i.next() {
+            Some(tk @ Token(x, Tok::Var)) => {
+                vars.push(tk);
+                Ok(Pattern::Var(*x))
+            }
+            Some(Token(full, Tok::Block(tokens))) if full.starts_with('[') => {
+                Ok(Pattern::Arr(self.with(tokens, "]", |p| {
+                    let mut pats = Vec::from([p.pattern(vars)?]);
+                    while p.char0(',').is_some() {
+                        pats.push(p.pattern(vars)?);
+                    }
+                    Ok(pats)
+                })))
+            }
+            Some(Token(full, Tok::Block(tokens))) if full.starts_with('{') => {
+                Ok(Pattern::Obj(self.with(tokens, "}", |p| {
+                    let mut entries = Vec::new();
+                    p.obj_pattern_entry(vars, &mut entries)?;
+                    while p.char0(',').is_some() {
+                        p.obj_pattern_entry(vars, &mut entries)?;
+                    }
+                    Ok(entries)
+                })))
+            }
+            next => Err((Expect::Var, next)),
+        }
+    }
+
+    /// Parse an entry of an object pattern, such as `a: $x`, `$y`, or `$z: [$w]`,
+    /// and add it to `entries`.
+    fn obj_pattern_entry(
+        &mut self,
+        vars: &mut Vec<&'t Token<&'s str>>,
+        entries: &mut Vec<(Term<&'s str>, Pattern<&'s str>)>,
+    ) -> Result<'s, 't, ()> {
+        let i = self.i.clone();
+        let key = match self.i.next() {
+            Some(tk @ Token(x, Tok::Var)) => {
+                vars.push(tk);
+                entries.push((Term::from_str(&x[1..]), Pattern::Var(*x)));
+                if self.char0(':').is_some() {
+                    entries.push((Term::from_str(&x[1..]), self.pattern(vars)?));
+                }
+                return Ok(());
+            }
+            Some(Token(full, Tok::Block(tokens))) if full.starts_with('(') => {
+                self.with(tokens, ")", Self::term)
+            }
+            Some(Token(id, Tok::Word)) if !id.contains("::") => Term::from_str(*id),
+            _ => {
+                self.i = i;
+                self.key()?
+            }
+        };
+        self.just(":")?;
+        entries.push((key, self.pattern(vars)?));
+        Ok(())
+    }
+
     /// Parse an atomic term.
     ///
     /// A term `t` is atomic if and only if `try t catch 0` is syntactically correct.
@@ -501,8 +635,22 @@ impl<'s, 't> Parser<'s, 't> {
                     Some(parts) => Term::Str(Some(*id), parts),
                 }
             }
+            // keywords that can only follow a term, such as `catch` without `try`
+            next @ Some(Token("then" | "elif" | "else" | "end" | "catch" | "as", Tok::Word)) => {
+                return Err((Expect::Term, next))
+            }
+            Some(Token("null", Tok::Word)) => Term::Null,
+            Some(Token("true", Tok::Word)) => Term::Bool(true),
+            Some(Token("false", Tok::Word)) => Term::Bool(false),
             Some(Token(id, Tok::Word)) => Term::Call(*id, self.args(Self::term)),
             Some(Token("..", _)) => Term::Recurse,
+            // `..a` is equivalent to `.. | .a`
+            Some(Token(c, Tok::Sym)) if c.starts_with("..") => {
+                let head = (path::Part::Index(Term::from_str(&c[2..])), self.opt());
+                let path = core::iter::once(head).chain(self.path()?.0).collect();
+                let path = Term::Path(Box::new(Term::Id), Path(path));
+                Term::Pipe(Box::new(Term::Recurse), None, Box::new(path))
+            }
             Some(Token(c, Tok::Sym)) if c.starts_with('.') => {
                 let key = if c.len() > 1 {
                     Some(Term::from_str(&c[1..]))
@@ -524,7 +672,15 @@ impl<'s, 't> Parser<'s, 't> {
             Some(Token(full, Tok::Block(tokens))) => match &full[..1] {
                 "[" if matches!(tokens[..], [Token("]", _)]) => Term::Arr(None),
                 "{" if matches!(tokens[..], [Token("}", _)]) => Term::Obj(Vec::new()),
-                "[" => Term::Arr(Some(Box::new(self.with(tokens, "]", Self::term)))),
+                // ignore trailing comma, such as in `[1, 2,]`
+                "[" => match &tokens[..] {
+                    [init @ .., Token(",", _), Token("]", _)]
+                        if !matches!(init, [] | [.., Token(",", _)]) =>
+                    {
+                        Term::Arr(Some(Box::new(self.with(init, "", Self::term))))
+                    }
+                    _ => Term::Arr(Some(Box::new(self.with(tokens, "]", Self::term)))),
+                },
                 "{" => self.with(tokens, "", |p| p.obj_items(Self::obj_entry).map(Term::Obj)),
                 "(" => self.with(tokens, ")", Self::term),
                 _ => panic!(),
@@ -593,25 +749,33 @@ impl<'s, 't> Parser<'s, 't> {
     fn path(&mut self) -> Result<'s, 't, Path<Term<&'s str>>> {
         let mut path: Vec<_> = core::iter::from_fn(|| self.path_part_opt()).collect();
         while let Some(key) = self.dot() {
-            let key = if key.is_empty() {
-                self.key()?
+            if key.is_empty() {
+                // `.[t]` after a path part is the same as `[t]`
+                if let Some(part) = self.path_part_opt() {
+                    path.push(part);
+                } else {
+                    path.push((path::Part::Index(self.key()?), self.opt()));
+                }
             } else {
-                Term::from_str(key)
-            };
-            path.push((path::Part::Index(key), self.opt()));
+                path.push((path::Part::Index(Term::from_str(key)), self.opt()));
+            }
             path.extend(core::iter::from_fn(|| self.path_part_opt()));
         }
         Ok(Path(path))
     }
 
-    /// Parse `[]`, `[t]`, `[t:]`, `[t:t]`, `[:t]` (all without brackets).
+    /// Parse `[]`, `[t]`, `[t:]`, `[t:t]`, `[:t]`, `[:]` (all without brackets).
     fn path_part(&mut self) -> Result<'s, 't, path::Part<Term<&'s str>>> {
-        use path::Part::{Index, Range};
+        use path::Part::{Index, Iter, Range};
         let done = |p: &Self| matches!(p.i.as_slice(), [Token("]", _)]);
         Ok(if done(self) {
-            Range(None, None)
+            Iter
         } else if self.char0(':').is_some() {
-            Range(None, Some(self.term()?))
+            if done(self) {
+                Range(None, None)
+            } else {
+                Range(None, Some(self.term()?))
+            }
         } else {
             let tm = self.term()?;
             if self.char0(':').is_some() {
@@ -764,7 +928,8 @@ pub(crate) struct Module<S, B> {
 /// def map(f): [.[] | f];
 /// def recurse(f; cond): recurse(f | select(cond));
 /// ~~~
-#[derive(Debug)]
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Def<S, F = Term<S>> {
     /// name, e.g. `"double"` or `"map"`
     pub name: S,
@@ -800,7 +965,7 @@ impl prec_climb::Op for BinaryOp {
     fn associativity(&self) -> prec_climb::Associativity {
         use prec_climb::Associativity;
         match self {
-            Self::Assign | Self::Update | Self::UpdateMath(_) | Self::UpdateAlt => {
+            Self::Assign | Self::Update | Self::UpdateMath(_) | Self::UpdateAlt | Self::Alt => {
                 Associativity::Right
             }
             _ => Associativity::Left,
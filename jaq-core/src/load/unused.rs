@@ -0,0 +1,104 @@
+//! Detection of unused definitions.
+
+use super::parse::{Def, Term};
+use super::visit::{self, Visit};
+use alloc::{vec, vec::Vec};
+use core::ops::Deref;
+
+/// Collector of calls to filters that are not bound locally.
+struct Calls<S> {
+    /// filters bound in the current scope, with their arity
+    bound: Vec<(S, usize)>,
+    /// calls to filters not bound in the current scope, with their arity
+    free: Vec<(S, usize)>,
+}
+
+impl<S> Default for Calls<S> {
+    fn default() -> Self {
+        Self {
+            bound: Vec::new(),
+            free: Vec::new(),
+        }
+    }
+}
+
+impl<S: Clone + Deref<Target = str>> Visit<S> for Calls<S> {
+    fn visit_term(&mut self, t: &Term<S>) {
+        match t {
+            Term::Def(defs, t) => {
+                let len = self.bound.len();
+                for d in defs {
+                    self.bound.push((d.name.clone(), d.args.len()));
+                    self.visit_def(d);
+                }
+                self.visit_term(t);
+                self.bound.truncate(len);
+            }
+            t => visit::walk_term(self, t),
+        }
+    }
+
+    fn visit_def(&mut self, def: &Def<S>) {
+        let len = self.bound.len();
+        // an argument `$x` can also be called as filter `x`
+        let args = def.args.iter().map(|a| (a.clone(), 0));
+        self.bound.extend(args);
+        self.visit_term(&def.body);
+        self.bound.truncate(len);
+    }
+
+    fn visit_call(&mut self, name: &S, args: &[Term<S>]) {
+        let arity = args.len();
+        let mut bound = self.bound.iter();
+        if !bound.any(|(x, a)| *a == arity && x.trim_start_matches('$') == &**name) {
+            self.free.push((name.clone(), arity))
+        }
+        visit::walk_args(self, args)
+    }
+}
+
+/// Return the top-level definitions that can not be reached from the main filter.
+///
+/// A definition is reachable if it is called by the main filter or
+/// by a reachable definition.
+/// This means that a definition that is only called by itself or
+/// by other unreachable definitions is unused.
+///
+/// Because the names of definitions are usually slices of the source code,
+/// [`super::span`] yields the location of unused definitions.
+///
+/// Example:
+///
+/// ~~~
+/// # use jaq_core::load::{parse, unused};
+/// let t = parse("def f: 1; def g: g; def h: f; h", |p| p.term()).unwrap();
+/// let unused: Vec<_> = unused::defs(&t).iter().map(|d| d.name).collect();
+/// assert_eq!(unused, ["g"]);
+/// ~~~
+pub fn defs<S: Clone + Deref<Target = str>>(t: &Term<S>) -> Vec<&Def<S>> {
+    let (defs, main) = match t {
+        Term::Def(defs, main) => (&defs[..], &**main),
+        main => (&[][..], main),
+    };
+    // return definitions called by `f` that are visible in `defs[..visible]`
+    let callees = |f: &dyn Fn(&mut Calls<S>), visible: usize| {
+        let mut calls = Calls::default();
+        f(&mut calls);
+        let callee = move |(name, arity): (S, _)| {
+            let same = |d: &Def<S>| *d.name == *name && d.args.len() == arity;
+            defs[..visible].iter().rposition(same)
+        };
+        calls.free.into_iter().filter_map(callee)
+    };
+
+    let mut used = vec![false; defs.len()];
+    let mut todo: Vec<_> = callees(&|c| c.visit_term(main), defs.len()).collect();
+    while let Some(i) = todo.pop() {
+        if !used[i] {
+            used[i] = true;
+            todo.extend(callees(&|c| c.visit_def(&defs[i]), i + 1));
+        }
+    }
+    let unused = defs.iter().zip(used).filter(|(_, used)| !used);
+    unused.map(|(d, _)| d).collect()
+}
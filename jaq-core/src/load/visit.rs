@@ -0,0 +1,151 @@
+//! Traversal of parsed terms.
+//!
+//! This is useful for static analysis, such as
+//! collecting all filters that a program calls:
+//!
+//! ~~~
+//! use jaq_core::load::{parse, visit::{self, Visit}};
+//!
+//! #[derive(Default)]
+//! struct Calls<'s>(Vec<&'s str>);
+//!
+//! impl<'s> Visit<&'s str> for Calls<'s> {
+//!     fn visit_call(&mut self, name: &&'s str, args: &[parse::Term<&'s str>]) {
+//!         self.0.push(*name);
+//!         visit::walk_args(self, args)
+//!     }
+//! }
+//!
+//! let t = parse("map(.+1) | first(f)", |p| p.term()).unwrap();
+//! let mut calls = Calls::default();
+//! calls.visit_term(&t);
+//! assert_eq!(calls.0, ["map", "first", "f"]);
+//! ~~~
+//!
+//! Because the strings in a term are usually slices of the source code,
+//! [`super::span`] yields the location of visited names in the source code.
+
+use super::lex::StrPart;
+use super::parse::{BinaryOp, Def, Pattern, Term};
+use super::path::{Part, Path};
+
+/// Visitor of terms.
+///
+/// Every method has a default implementation that visits
+/// all subterms of its arguments in the order in which they appear in the source.
+/// Implementors can override only the methods for the terms they are interested in;
+/// to continue traversal below such a term, they can call the corresponding `walk_*` function.
+pub trait Visit<S> {
+    /// Visit any term.
+    fn visit_term(&mut self, t: &Term<S>) {
+        walk_term(self, t)
+    }
+
+    /// Visit a definition of a filter, such as `def f(g; $x): g + $x;`.
+    fn visit_def(&mut self, def: &Def<S>) {
+        self.visit_term(&def.body)
+    }
+
+    /// Visit a call of a filter, such as `f` or `g(1; 2)`.
+    fn visit_call(&mut self, _name: &S, args: &[Term<S>]) {
+        walk_args(self, args)
+    }
+
+    /// Visit a variable, such as `$x`.
+    fn visit_var(&mut self, _x: &S) {}
+
+    /// Visit a path, such as `.a[0]` or `f[]?`.
+    fn visit_path(&mut self, t: &Term<S>, path: &Path<Term<S>>) {
+        self.visit_term(t);
+        walk_path(self, path)
+    }
+
+    /// Visit a binary operation, such as `1 + 2` or `f, g`.
+    fn visit_bin_op(&mut self, l: &Term<S>, _op: &BinaryOp, r: &Term<S>) {
+        self.visit_term(l);
+        self.visit_term(r)
+    }
+}
+
+/// Visit all subterms of a term.
+pub fn walk_term<S, V: Visit<S> + ?Sized>(v: &mut V, t: &Term<S>) {
+    match t {
+        Term::Id | Term::Recurse | Term::Bool(_) | Term::Null | Term::Num(_) | Term::Break(_) => (),
+        Term::Str(_, parts) => parts.iter().for_each(|part| {
+            if let StrPart::Term(t) = part {
+                v.visit_term(t)
+            }
+        }),
+        Term::Arr(t) => t.iter().for_each(|t| v.visit_term(t)),
+        Term::Obj(kvs) => kvs.iter().for_each(|(k, val)| {
+            v.visit_term(k);
+            val.iter().for_each(|val| v.visit_term(val))
+        }),
+        Term::Neg(t) | Term::Label(_, t) => v.visit_term(t),
+        Term::Pipe(l, _, r) => {
+            v.visit_term(l);
+            v.visit_term(r)
+        }
+        Term::Destruct(l, pats, r) => {
+            v.visit_term(l);
+            pats.iter().for_each(|p| walk_pattern(v, p));
+            v.visit_term(r)
+        }
+        Term::BinOp(l, op, r) => v.visit_bin_op(l, op, r),
+        Term::Fold(_, xs, _, args) => {
+            v.visit_term(xs);
+            walk_args(v, args)
+        }
+        Term::TryCatch(t, c) => {
+            v.visit_term(t);
+            c.iter().for_each(|c| v.visit_term(c))
+        }
+        Term::IfThenElse(if_thens, else_) => {
+            if_thens.iter().for_each(|(if_, then_)| {
+                v.visit_term(if_);
+                v.visit_term(then_)
+            });
+            else_.iter().for_each(|e| v.visit_term(e))
+        }
+        Term::Def(defs, t) => {
+            defs.iter().for_each(|d| v.visit_def(d));
+            v.visit_term(t)
+        }
+        Term::Call(name, args) => v.visit_call(name, args),
+        Term::Var(x) => v.visit_var(x),
+        Term::Path(t, path) => v.visit_path(t, path),
+    }
+}
+
+/// Visit all arguments of a call or fold.
+pub fn walk_args<S, V: Visit<S> + ?Sized>(v: &mut V, args: &[Term<S>]) {
+    args.iter().for_each(|a| v.visit_term(a))
+}
+
+/// Visit all terms in the keys of a pattern, such as `f` in `{(f): $x}`.
+///
+/// Variables bound by the pattern, such as `$x`, are not visited.
+pub fn walk_pattern<S, V: Visit<S> + ?Sized>(v: &mut V, p: &Pattern<S>) {
+    match p {
+        Pattern::Var(_) => (),
+        Pattern::Arr(pats) => pats.iter().for_each(|p| walk_pattern(v, p)),
+        Pattern::Obj(entries) => entries.iter().for_each(|(k, p)| {
+            v.visit_term(k);
+            walk_pattern(v, p)
+        }),
+    }
+}
+
+/// Visit all terms in the parts of a path, such as `f` and `g` in `.[f][g:]`.
+pub fn walk_path<S, V: Visit<S> + ?Sized>(v: &mut V, path: &Path<Term<S>>) {
+    for (part, _) in &path.0 {
+        match part {
+            Part::Index(i) => v.visit_term(i),
+            Part::Iter => (),
+            Part::Range(from, upto) => {
+                from.iter().for_each(|t| v.visit_term(t));
+                upto.iter().for_each(|t| v.visit_term(t))
+            }
+        }
+    }
+}
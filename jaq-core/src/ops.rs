@@ -4,6 +4,7 @@ use core::ops::{Add, Div, Mul, Rem, Sub};
 
 /// Arithmetic operation, such as `+`, `-`, `*`, `/`, `%`.
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Math {
     /// Addition
     Add,
@@ -48,6 +49,7 @@ impl Math {
 
 /// An operation that orders two values, such as `<`, `<=`, `>`, `>=`, `==`, `!=`.
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Cmp {
     /// Less-than (<).
     Lt,
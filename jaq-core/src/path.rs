@@ -3,19 +3,24 @@
 use crate::box_iter::{box_once, flat_map_with, map_with, BoxIter};
 use crate::results::then;
 use crate::val::{ValR, ValT, ValX, ValXs};
-use alloc::{boxed::Box, vec::Vec};
+use crate::Error;
+use alloc::{boxed::Box, string::ToString, vec::Vec};
 
 /// Path such as `.[].a?[1:]`.
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Path<F>(pub Vec<(Part<F>, Opt)>);
 
 /// Part of a path, such as `[]`, `a`, and `[1:]` in `.[].a?[1:]`.
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Part<I> {
     /// Access arrays with integer and objects with string indices
     Index(I),
-    /// Iterate over arrays with optional range bounds and over objects without bounds
-    /// If both are `None`, return iterator over whole array/object
+    /// Iterate over arrays and objects, such as `[]`
+    #[default]
+    Iter,
+    /// Slice arrays and strings with optional range bounds, such as `[1:]` or `[:]`
     Range(Option<I>, Option<I>),
 }
 
@@ -24,7 +29,8 @@ pub enum Part<I> {
 /// For example, `[] | .a` fails with an error, while `[] | .a?` returns nothing.
 /// By default, path parts are *essential*, meaning that they fail.
 /// Annotating them with `?` makes them *optional*.
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Opt {
     /// Return nothing if the input cannot be accessed with the path
     Optional,
@@ -32,11 +38,8 @@ pub enum Opt {
     Essential,
 }
 
-impl<I> Default for Part<I> {
-    fn default() -> Self {
-        Self::Range(None, None)
-    }
-}
+/// Value together with the path that leads to it, such as `["a", 0]` for `.a[0]`.
+pub type ValPath<V> = (V, Vec<V>);
 
 impl Opt {
     /// If `self` is optional, return `x`, else fail with `f(x)`.
@@ -79,6 +82,10 @@ impl<'a, V: ValT + 'a> Path<V> {
         run(self.0.into_iter(), v)
     }
 
+    pub(crate) fn paths(self, vp: ValPath<V>) -> BoxIter<'a, Result<ValPath<V>, Error<V>>> {
+        paths(self.0.into_iter(), vp)
+    }
+
     pub(crate) fn update<F>(mut self, v: V, f: F) -> ValX<'a, V>
     where
         F: Fn(V) -> ValXs<'a, V>,
@@ -105,6 +112,22 @@ where
     }
 }
 
+fn paths<'a, V: ValT + 'a, I>(
+    mut iter: I,
+    vp: ValPath<V>,
+) -> BoxIter<'a, Result<ValPath<V>, Error<V>>>
+where
+    I: Iterator<Item = (Part<V>, Opt)> + Clone + 'a,
+{
+    if let Some((part, opt)) = iter.next() {
+        let essential = matches!(opt, Opt::Essential);
+        let ys = part.paths(vp).filter(move |y| essential || y.is_ok());
+        flat_map_with(ys, iter, move |y, iter| then(y, |y| paths(iter, y)))
+    } else {
+        box_once(Ok(vp))
+    }
+}
+
 fn update<'a, V: ValT + 'a, P, F>(mut iter: P, last: (Part<V>, Opt), v: V, f: &F) -> ValX<'a, V>
 where
     P: Iterator<Item = (Part<V>, Opt)> + Clone,
@@ -122,19 +145,64 @@ impl<'a, V: ValT + 'a> Part<V> {
     fn run(&self, v: V) -> impl Iterator<Item = ValR<V>> + 'a {
         match self {
             Self::Index(idx) => box_once(v.index(idx)),
-            Self::Range(None, None) => Box::new(v.values()),
+            Self::Iter => Box::new(v.values()),
             Self::Range(from, upto) => box_once(v.range(from.as_ref()..upto.as_ref())),
         }
     }
 
-    fn update<F, I>(&self, v: V, opt: Opt, f: F) -> ValX<'a, V>
+    fn paths(&self, (v, path): ValPath<V>) -> BoxIter<'a, Result<ValPath<V>, Error<V>>> {
+        let push = |mut path: Vec<V>, i| {
+            path.push(i);
+            path
+        };
+        match self {
+            Self::Index(idx) => box_once(v.index(idx).map(|y| (y, push(path, idx.clone())))),
+            Self::Iter => Box::new(
+                v.key_values()
+                    .map(move |kv| kv.map(|(k, y)| (y, push(path.clone(), k)))),
+            ),
+            // like in jq, slices are represented as `{"start": from, "end": upto}`,
+            // but absent bounds are omitted instead of being `null`
+            Self::Range(from, upto) => {
+                let bounds = [("start", from), ("end", upto)].into_iter();
+                let bounds = bounds.filter_map(|(k, b)| Some((V::from(k.to_string()), b.clone()?)));
+                let slice = V::from_map(bounds);
+                box_once(
+                    slice.and_then(|k| Ok((v.range(from.as_ref()..upto.as_ref())?, push(path, k)))),
+                )
+            }
+        }
+    }
+
+    /// Convert a path component yielded by [`Part::paths`] back to a part.
+    pub(crate) fn from_key(k: V) -> Self {
+        let slice = |kvs: Vec<(V, V)>| {
+            let (mut from, mut upto) = (None, None);
+            for (k, v) in kvs {
+                match k.as_str() {
+                    Some("start") => from = Some(v),
+                    Some("end") => upto = Some(v),
+                    _ => return None,
+                }
+            }
+            Some(Self::Range(from, upto))
+        };
+        // `{}` is the path component of `[:]`
+        let unbounded = |k: &V| V::from_map([]).map_or(false, |o| o == *k);
+        match k.clone().key_values().collect::<Result<Vec<_>, _>>() {
+            Ok(kvs) if !kvs.is_empty() || unbounded(&k) => slice(kvs).unwrap_or(Self::Index(k)),
+            _ => Self::Index(k),
+        }
+    }
+
+    pub(crate) fn update<F, I>(&self, v: V, opt: Opt, f: F) -> ValX<'a, V>
     where
         F: Fn(V) -> I,
         I: Iterator<Item = ValX<'a, V>>,
     {
         match self {
             Self::Index(idx) => v.map_index(idx, opt, f),
-            Self::Range(None, None) => v.map_values(opt, f),
+            Self::Iter => v.map_values(opt, f),
             Self::Range(from, upto) => v.map_range(from.as_ref()..upto.as_ref(), opt, f),
         }
     }
@@ -142,9 +210,10 @@ impl<'a, V: ValT + 'a> Part<V> {
 
 impl<'a, U: Clone + 'a, F: IntoIterator<Item = U> + Clone + 'a> Part<F> {
     fn into_iter(self) -> BoxIter<'a, Part<U>> {
-        use Part::{Index, Range};
+        use Part::{Index, Iter, Range};
         match self {
             Index(i) => Box::new(i.into_iter().map(Index)),
+            Iter => box_once(Iter),
             Range(None, None) => box_once(Range(None, None)),
             Range(Some(from), None) => {
                 Box::new(from.into_iter().map(|from| Range(Some(from), None)))
@@ -174,9 +243,10 @@ impl<T> Path<T> {
 impl<T> Part<T> {
     /// Apply a function to the contained indices.
     pub(crate) fn map<U, F: FnMut(T) -> U>(self, mut f: F) -> Part<U> {
-        use Part::{Index, Range};
+        use Part::{Index, Iter, Range};
         match self {
             Index(i) => Index(f(i)),
+            Iter => Iter,
             Range(from, upto) => Range(from.map(&mut f), upto.map(&mut f)),
         }
     }
@@ -196,6 +266,7 @@ impl<T, E> Part<Result<T, E>> {
     fn transpose(self) -> Result<Part<T>, E> {
         match self {
             Self::Index(i) => Ok(Part::Index(i?)),
+            Self::Iter => Ok(Part::Iter),
             Self::Range(from, upto) => Ok(Part::Range(from.transpose()?, upto.transpose()?)),
         }
     }
@@ -205,6 +276,7 @@ impl<F> Part<F> {
     fn as_ref(&self) -> Part<&F> {
         match self {
             Self::Index(i) => Part::Index(i),
+            Self::Iter => Part::Iter,
             Self::Range(from, upto) => Part::Range(from.as_ref(), upto.as_ref()),
         }
     }
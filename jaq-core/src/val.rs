@@ -38,6 +38,11 @@ pub trait ValT:
     + Rem<Output = ValR<Self>>
     + Neg<Output = ValR<Self>>
 {
+    /// Create a null value.
+    ///
+    /// This is used by `null`.
+    fn null() -> Self;
+
     /// Create a number from a string.
     ///
     /// The number should adhere to the format accepted by [`f64::from_str`].
@@ -53,6 +58,14 @@ pub trait ValT:
     /// This is used by `.[]`.
     fn values(self) -> alloc::boxed::Box<dyn Iterator<Item = ValR<Self>>>;
 
+    /// Yield the children of a value together with their indices.
+    ///
+    /// This is used by `path(.[])`.
+    /// The children must be yielded in the same order as by [`Self::values`].
+    fn key_values(
+        self,
+    ) -> alloc::boxed::Box<dyn Iterator<Item = Result<(Self, Self), crate::Error<Self>>>>;
+
     /// Yield the child of a value at the given index.
     ///
     /// This is used by `.[k]`.
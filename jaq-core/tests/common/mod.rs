@@ -15,6 +15,36 @@ fn yields(x: Val, code: &str, ys: impl Iterator<Item = ValR>) {
     filter.yields(x, ys)
 }
 
+/// Return the names of all undefined symbols in the given code.
+pub fn undefined(code: &str) -> Vec<String> {
+    use jaq_core::load::{Arena, File, Loader};
+    use jaq_core::{Compiler, Native};
+
+    let arena = Arena::default();
+    let loader = Loader::new([]);
+    let path = "".into();
+    let modules = loader.load(&arena, File { path, code }).unwrap();
+    let errs = Compiler::<_, Native<Val>>::default()
+        .compile(modules)
+        .err()
+        .unwrap_or_default();
+    let errs = errs.into_iter().flat_map(|(_file, errs)| errs);
+    errs.map(|(x, _undefined)| x.to_string()).collect()
+}
+
+/// Return a debug representation of the filter compiled from the given code.
+pub fn compiled(code: &str) -> String {
+    use jaq_core::load::{Arena, File, Loader};
+    use jaq_core::Compiler;
+
+    let arena = Arena::default();
+    let loader = Loader::new([]);
+    let path = "".into();
+    let modules = loader.load(&arena, File { path, code }).unwrap();
+    let filter = Compiler::<_, ()>::default().compile(modules);
+    format!("{:?}", filter.unwrap())
+}
+
 pub fn fail(x: Value, f: &str, err: Error) {
     yields(x.into(), f, core::iter::once(Err(err)))
 }
@@ -0,0 +1,329 @@
+//! Tests for displaying parsed terms as jq source code.
+
+use jaq_core::load::{parse::Term, Lexer, Parser};
+
+fn term(code: &str) -> Term<&str> {
+    let tokens = Lexer::new(code).lex().unwrap();
+    Parser::new(&tokens).parse(|p| p.term()).unwrap()
+}
+
+fn parse(code: &str) -> String {
+    term(code).to_string()
+}
+
+/// Check that displaying a term yields the expected code,
+/// and that parsing the displayed code again yields the same term.
+fn roundtrip(code: &str, expected: &str) {
+    let shown = parse(code);
+    assert_eq!(shown, expected);
+
+    assert_eq!(term(code), term(&shown));
+    assert_eq!(parse(&shown), shown);
+}
+
+#[test]
+fn precedence() {
+    roundtrip("1 - (2 - 3)", "1 - (2 - 3)");
+    roundtrip("(1 - 2) - 3", "1 - 2 - 3");
+    roundtrip("1+2*3", "1 + 2 * 3");
+    roundtrip("(1+2)*3", "(1 + 2) * 3");
+    roundtrip("(1, 2) | 3", "1, 2 | 3");
+    roundtrip("1, (2 | 3)", "1, (2 | 3)");
+    roundtrip(".a = .b = 1", ".a = .b = 1");
+    roundtrip("(.a = .b) = 1", "(.a = .b) = 1");
+    roundtrip(".a |= . + 1", ".a |= . + 1");
+    roundtrip("1 // 2 // 3", "1 // 2 // 3");
+    roundtrip("1 // (2 // 3)", "1 // 2 // 3");
+    roundtrip("(1 // 2) // 3", "(1 // 2) // 3");
+    roundtrip("1 or 2 and 3", "1 or 2 and 3");
+    roundtrip("(1 or 2) and 3", "(1 or 2) and 3");
+    roundtrip("1 < 2 == true", "1 < 2 == true");
+}
+
+/// Regression corpus of operator groupings.
+#[test]
+fn groupings() {
+    let corpus = [
+        ("1, 2 | 3, 4", "(1, 2) | (3, 4)"),
+        (".a = 1, 2", "(.a = 1), 2"),
+        (".a |= 1 // 2", ".a |= (1 // 2)"),
+        (".a += .b -= 1", ".a += (.b -= 1)"),
+        ("1 // 2 or 3", "1 // (2 or 3)"),
+        ("1 or 2 and 3 or 4", "(1 or (2 and 3)) or 4"),
+        ("1 and 2 == 3", "1 and (2 == 3)"),
+        ("1 == 2 < 3", "1 == (2 < 3)"),
+        ("1 < 2 + 3", "1 < (2 + 3)"),
+        ("1 - 2 + 3", "(1 - 2) + 3"),
+        ("1 + 2 * 3 - 4", "(1 + (2 * 3)) - 4"),
+        ("1 / 2 * 3", "(1 / 2) * 3"),
+        ("1 * 2 % 3", "1 * (2 % 3)"),
+        ("-1 + 2", "(-1) + 2"),
+        ("1 + .a? * 2", "1 + ((.a?) * 2)"),
+        ("1 + try 2 catch 3 + 4", "(1 + (try 2 catch 3)) + 4"),
+        (
+            "1 + reduce 2 as $x (3; 4) * 5",
+            "1 + ((reduce 2 as $x (3; 4)) * 5)",
+        ),
+    ];
+    for (code, grouped) in corpus {
+        assert_eq!(term(code), term(grouped), "{code}");
+    }
+}
+
+#[test]
+fn pipes() {
+    roundtrip(". as $x | $x", ". as $x | $x");
+    roundtrip("(1 | 2) | 3", "(1 | 2) | 3");
+    roundtrip("1 | 2 | 3", "1 | 2 | 3");
+    roundtrip("(1 | 2) + 3", "(1 | 2) + 3");
+    roundtrip("1 + (def f: 1; f)", "1 + (def f: 1; f)");
+    roundtrip(
+        "def f(g; $x): g + $x; f(1; 2)",
+        "def f(g; $x): g + $x; f(1; 2)",
+    );
+    roundtrip("label $x | 1, break $x", "label $x | 1, break $x");
+    roundtrip("(label $x | 1), 2", "(label $x | 1), 2");
+    let code = "label $out | range(10) | if . == 3 then break $out else . end";
+    roundtrip(code, code);
+    assert!(matches!(term(code), Term::Label("$out", _)));
+}
+
+#[test]
+fn patterns() {
+    use jaq_core::load::parse::Pattern;
+    let code = ". as [$x, [$y]] | $x";
+    roundtrip(code, code);
+    let code = r#". as {a: $x, "b c": [$y], ($k): $z} | $x"#;
+    roundtrip(code, code);
+    roundtrip(
+        ". as {$x, $y: [$z]} | 0",
+        ". as {x: $x, y: $y, y: [$z]} | 0",
+    );
+    roundtrip(
+        ".[] as {a: $x} ?// [$x] | $x",
+        ".[] as {a: $x} ?// [$x] | $x",
+    );
+    roundtrip("(1 as [$x] | $x) + 1", "(1 as [$x] | $x) + 1");
+    // a single variable is a regular binding
+    assert_eq!(term(". as $x | $x"), term(". as $x|$x"));
+    assert!(matches!(term(". as $x | $x"), Term::Pipe(_, Some("$x"), _)));
+    let alts = Vec::from([
+        Pattern::Var("$x"),
+        Pattern::Arr(Vec::from([Pattern::Var("$x")])),
+    ]);
+    let b = Box::new;
+    let expected = Term::Destruct(b(Term::Id), alts, b(Term::Var("$x")));
+    assert_eq!(term(". as $x ?// [$x] | $x"), expected);
+}
+
+#[test]
+fn objects() {
+    roundtrip("{a: 1 | 2, b}", "{a: 1 | 2, b}");
+    roundtrip("{a: (1, 2)}", "{a: (1, 2)}");
+    roundtrip(r#"{"a b": 1, $x, (1): 2}"#, r#"{"a b": 1, $x, (1): 2}"#);
+    roundtrip(r#"{"a": @base64 "x"}"#, r#"{a: @base64 "x"}"#);
+    roundtrip("{a, $b, (.c): 1, d: 2}", "{a, $b, (.c): 1, d: 2}");
+    // `{$x}` is short for `{x: $x}`, whereas `{$x: 1}` uses the value of `$x` as key
+    assert_eq!(term("{$x}"), Term::Obj([(Term::Var("$x"), None)].into()));
+    let x1 = Term::Obj([(Term::Var("$x"), Some(Term::Num("1")))].into());
+    assert_eq!(term("{$x: 1}"), x1);
+    roundtrip("[]", "[]");
+    roundtrip("[1, 2,]", "[1, 2]");
+}
+
+#[test]
+fn paths() {
+    roundtrip(".a.b[0]?", ".a.b[0]?");
+    roundtrip(r#"."a b".c"#, r#".["a b"].c"#);
+    roundtrip(r#".["a"]"#, ".a");
+    roundtrip(".[1:], .[:2], .[1:2], .[]", ".[1:], .[:2], .[1:2], .[]");
+    roundtrip(".[:]", ".[:]");
+    roundtrip(r#".a.["b"].[0]?.c"#, ".a.b[0]?.c");
+    assert_eq!(term(r#".a."b".["c"]"#), term(".a.b.c"));
+    roundtrip("$x.a", "$x.a");
+    roundtrip("(.a).b", "(.a).b");
+    roundtrip("(..).a", "(..).a");
+    roundtrip("(1).a", "(1).a");
+    roundtrip("f(1)[0]", "f(1)[0]");
+    roundtrip("(1 + 2)[0]", "(1 + 2)[0]");
+}
+
+#[test]
+fn negation_try() {
+    roundtrip("-(-1)", "- -1");
+    roundtrip("-(1 + 2)", "-(1 + 2)");
+    roundtrip("(-1)?", "(-1)?");
+    roundtrip("(.a)?", "(.a)?");
+    roundtrip("(.a?)?", "(.a?)?");
+    roundtrip("try -1 catch 0", "try -1 catch 0");
+    roundtrip("try (1, 2) catch 0 + 1", "try (1, 2) catch 0 + 1");
+    roundtrip("(try 1 catch 0).a", "(try 1 catch 0).a");
+    // negation applies to the whole path
+    assert_eq!(term("-a.b"), Term::Neg(Box::new(term("a.b"))));
+    assert_eq!(term("-.a[0]"), Term::Neg(Box::new(term(".a[0]"))));
+    roundtrip("-a.b", "-a.b");
+    roundtrip("(-a).b", "(-a).b");
+    roundtrip("- -1", "- -1");
+    assert_eq!(term("1 -1"), term("1 - 1"));
+    assert_eq!(term("1|-1"), term("1 | -1"));
+    let tokens = Lexer::new("1 1").lex().unwrap();
+    assert!(Parser::new(&tokens).parse(|p| p.term()).is_err());
+}
+
+#[test]
+fn numbers() {
+    // numbers are stored as in the source code
+    let code = "[1e10, 1.5e-3, .5, 1., 1.e3, 0.5E+2, -0, 1e1000]";
+    roundtrip(code, code);
+    assert_eq!(term(".5e1"), Term::Num(".5e1"));
+    // `1..2` is not a single number, but `1.` followed by `.2`
+    let tokens = Lexer::new("1..2").lex().unwrap();
+    assert!(Parser::new(&tokens).parse(|p| p.term()).is_err());
+    assert_eq!(parse("1..a"), "(1.).a");
+}
+
+#[test]
+fn strings() {
+    roundtrip(r#""a\n\"\\ä😀""#, r#""a\n\"\\ä😀""#);
+    roundtrip(r#""x\(1, 2)y""#, r#""x\(1, 2)y""#);
+    roundtrip(r#"@base64 "x\(.)""#, r#"@base64 "x\(.)""#);
+    roundtrip("@text", "@text");
+}
+
+#[test]
+fn keywords() {
+    let code = "if . then 1 elif 2 then 3 else 4 end";
+    roundtrip(code, code);
+    roundtrip("if . then 1 end", "if . then 1 end");
+    roundtrip(
+        "if a then b elif c then d end",
+        "if a then b elif c then d end",
+    );
+    let code = "reduce .[] as $x (0; . + $x)";
+    roundtrip(code, code);
+    let code = "foreach (1, 2) as $x (0; . + $x; [$x, .])";
+    roundtrip(code, code);
+    roundtrip(".. | numbers", ".. | numbers");
+    roundtrip("[..a?]", "[.. | .a?]");
+    roundtrip("..a.b + 1", "(.. | .a.b) + 1");
+    // `..a` cannot continue a path, like in jq
+    for code in [".a..b", ".[0]..a", "..a..b"] {
+        let tokens = Lexer::new(code).lex().unwrap();
+        assert!(Parser::new(&tokens).parse(|p| p.term()).is_err(), "{code}");
+    }
+}
+
+#[test]
+fn constants() {
+    assert_eq!(term("null"), Term::Null);
+    assert_eq!(term("true"), Term::Bool(true));
+    assert_eq!(term("false"), Term::Bool(false));
+    roundtrip("[null, true, false]", "[null, true, false]");
+    roundtrip("null.a, true?", "null.a, true?");
+    // as object keys, constants are just identifiers
+    roundtrip("{null: 1, true}", "{null: 1, true}");
+}
+
+/// Check that formatting code yields the expected code with the same meaning.
+fn format(code: &str, expected: &str) {
+    let formatted = jaq_core::load::format(code).unwrap();
+    assert_eq!(formatted, expected);
+    assert_eq!(term(code), term(&formatted));
+    assert_eq!(jaq_core::load::format(&formatted).unwrap(), formatted);
+}
+
+#[test]
+fn format_short() {
+    format(
+        "def f:  .+1 ;  # increment\n[.[]|f]",
+        "def f: . + 1;\n[.[] | f]",
+    );
+    format(
+        "if .   then 1 elif 2 then 3 end",
+        "if . then 1 elif 2 then 3 end",
+    );
+}
+
+#[test]
+fn format_long() {
+    let code = r#"def select_big(f): .[]|select(.size>10 and (.kind|f));
+    def names: reduce .[] as $x ([]; . + [$x.name, $x.meta.name, "some string that is long"]);
+  select_big(.=="a")|{id,name:.meta.name,tags:[.tags[]|ascii_downcase]} | if .id then "yes \(.id) and more text to make it long" elif .x then 0 else error("no id") end"#;
+    let expected = r#"def select_big(f): .[] | select(.size > 10 and (.kind | f));
+def names:
+  reduce .[] as $x ([]; . + [$x.name, $x.meta.name, "some string that is long"]);
+select_big(. == "a")
+| {id, name: .meta.name, tags: [.tags[] | ascii_downcase]}
+| if .id then
+    "yes \(.id) and more text to make it long"
+  elif .x then
+    0
+  else
+    error("no id")
+  end"#;
+    format(code, expected);
+}
+
+#[test]
+fn format_nested() {
+    let fs = ["f"; 30].join(", ");
+    let code = format!("def f: def g: 1; g; [{fs}]");
+    let expected = format!(
+        "def f:\n  def g: 1;\n  g;\n[\n{}\n]",
+        ["  f"; 30].join(",\n")
+    );
+    format(&code, &expected);
+}
+
+#[test]
+fn eq_whitespace() {
+    let code = "def f($x): $x + 1; {a: .b, \"c\": [.[0]?]} | f(2)";
+    let spaced = " def f( $x ) :\n  $x+1 ;\n{ a : .b , \"c\" : [ .[0] ? ] }  # comment\n|f(2)";
+    assert_eq!(term(code), term(spaced));
+    assert_ne!(
+        term(code),
+        term("def f($x): $x + 1; {a: .b, \"c\": [.[1]?]} | f(2)")
+    );
+    assert_ne!(term("1 - 2 - 3"), term("1 - (2 - 3)"));
+}
+
+#[test]
+fn bind_precedence() {
+    use jaq_core::load::parse::BinaryOp;
+    use jaq_core::ops::Math;
+    let b = Box::new;
+    let x_plus_1 = Term::BinOp(
+        b(Term::Var("$x")),
+        BinaryOp::Math(Math::Add),
+        b(Term::Num("1")),
+    );
+    let expected = Term::Pipe(b(Term::Num("1")), Some("$x"), b(x_plus_1));
+    assert_eq!(term("1 as $x | $x + 1"), expected);
+    // the binder takes the whole term on its left as source
+    assert_eq!(term("1, 2 as $x | $x"), term("(1, 2) as $x | $x"));
+    assert_eq!(
+        term("1 as $x | 2 as $y | $y"),
+        term("1 as $x | (2 as $y | $y)")
+    );
+}
+
+#[test]
+fn format_binding() {
+    use jaq_core::load::{lex::StrPart, parse::BinaryOp};
+    use jaq_core::ops::Math;
+    let b = Box::new;
+    // unknown formats are parsed, leaving their validation to the compiler
+    assert_eq!(term("@foo"), Term::Call("@foo", Vec::new()));
+    assert_eq!(
+        term(r#"@foo "a""#),
+        Term::Str(Some("@foo"), [StrPart::Str("a")].into())
+    );
+    // a format applies only to the string literal immediately following it
+    let fmt = Term::Str(Some("@base64"), [StrPart::Term(term(".x"))].into());
+    let expected = Term::BinOp(b(fmt), BinaryOp::Math(Math::Add), b(term(r#""\(.x)""#)));
+    assert_eq!(term(r#"@base64 "\(.x)" + "\(.x)""#), expected);
+    roundtrip(
+        r#"@base64 "\(.x)" + "\(.x)""#,
+        r#"@base64 "\(.x)" + "\(.x)""#,
+    );
+}
@@ -45,7 +45,19 @@ fn iter_access() {
     gives(json!({"a": 1, "b": 2}), ".[]", [json!(1), json!(2)]);
     // TODO: correct this
     //gives(json!({"b": 2, "a": 1}), ".[]", [json!(2), json!(1)]);
+    // objects constructed in jaq keep their insertion order
+    give(json!(null), "{b: 2, a: 1, c: 3} | [.[]]", json!([2, 1, 3]));
+    give(
+        json!(null),
+        "{b: 2, a: 1} | .c = 3 | [.[]]",
+        json!([2, 1, 3]),
+    );
     gives(json!("asdf"), ".[]?", []);
+    gives(json!(0), ".[]?", []);
+
+    let err = |v: serde_json::Value| Error::typ(v.into(), "iterable (array or object)");
+    fail(json!(0), ".[]", err(json!(0)));
+    fail(json!(null), ".[]", err(json!(null)));
 }
 
 #[test]
@@ -61,6 +73,38 @@ fn range_access() {
     give(json!([0, 1, 2]), ".[4:5]", json!([]));
 
     give(json!([0, 1, 2]), ".[0:2,3.14]?", json!([0, 1]));
+    give(json!([0, 1, 2]), ".[:]", json!([0, 1, 2]));
+    give(json!("abc"), ".[:]", json!("abc"));
+}
+
+#[test]
+fn range_terms() {
+    let v = json!({"a": [0, 1, 2, 3], "from": 1, "upto": 3});
+    give(v.clone(), ".a[.from:.upto]", json!([1, 2]));
+    give(v.clone(), ".a[.from + 1:]", json!([2, 3]));
+    give(v.clone(), ".a[1:2]", json!([1]));
+    give(v, "[.from[1:3]?]", json!([]));
+}
+
+#[test]
+fn null_access() {
+    give(json!(null), ".a.b", json!(null));
+    give(json!(null), ".[0]", json!(null));
+    give(json!(null), ".[1:2]", json!(null));
+    give(json!({"a": null}), ".a.b.c", json!(null));
+
+    let err = |s| Error::str(format_args!("cannot index {s}"));
+    fail(json!(5), ".a", err(r#"number (5) with string ("a")"#));
+    fail(
+        json!(null),
+        ".[true]",
+        err("null (null) with boolean (true)"),
+    );
+    fail(
+        json!({"a": 5}),
+        ".a.b",
+        err(r#"number (5) with string ("b")"#),
+    );
 }
 
 #[test]
@@ -75,6 +119,11 @@ fn iter_assign() {
 
 yields!(index_keyword, r#"{"if": 0} | .if"#, 0);
 yields!(obj_keyword, "{if: 0} | .if", 0);
+yields!(
+    obj_constants,
+    "{null: 0, true: 1, false: 2} | [.null, .true, .false]",
+    [0, 1, 2]
+);
 
 yields!(key_update1, "{} | .a  |= .+1", json!({"a": 1}));
 yields!(key_update2, "{} | .a? |= .+1", json!({"a": 1}));
@@ -84,6 +133,17 @@ yields!(key_update2, "{} | .a? |= .+1", json!({"a": 1}));
 yields!(index_opt_inner, "try .[0[]]? catch 1", 1);
 yields!(index_opt_outer, "1, (.[0[]])?", 1);
 
+// `.[f]` after a path part is the same as `[f]`
+yields!(dot_index_chain, r#"{a: {b: {c: 1}}} | .a.["b"].c"#, 1);
+yields!(dot_index_num, "{a: [1, 2]} | .a.[1]", 2);
+yields!(dot_index_iter, "{a: [1, 2]} | [.a.[]]", [1, 2]);
+yields!(dot_index_opt, r#"{a: 1} | [.a.["b"]?, .a."b"?]"#, json!([]));
+yields!(
+    dot_index_update,
+    r#"{a: {b: 1}} | .a.["b"] |= .+1"#,
+    json!({"a": {"b": 2}})
+);
+
 #[test]
 fn index_update() {
     give(json!({"a": 1}), ".b |= .", json!({"a": 1, "b": null}));
@@ -132,6 +192,36 @@ fn iter_update() {
     give(json!([[0, 1], "a"]), ".[][]? |= .+1", json!([[1, 2], "a"]));
 }
 
+#[test]
+fn comma_update() {
+    give(json!([[1, 2], [3]]), ".[][] |= .+1", json!([[2, 3], [4]]));
+    give(
+        json!({"a": 1, "b": [2, 3]}),
+        "(.a, .b[]) |= .+1",
+        json!({"a": 2, "b": [3, 4]}),
+    );
+    // updates are applied from left to right
+    give(json!({"a": 1}), "(.a, .a) |= .*2+1", json!({"a": 7}));
+    give(
+        json!([[1], [2, 3]]),
+        "(.[0], .[]) |= .+[0]",
+        json!([[1, 0, 0], [2, 3, 0]]),
+    );
+    // like in jq, all paths are determined on the original input,
+    // so values added by an update are not visited by later updates
+    give(json!({"a": 1}), "(.c, .[]) |= .+1", json!({"a": 2, "c": 1}));
+    give(json!({"a": 1}), "(.[], .c) |= .+1", json!({"a": 2, "c": 1}));
+    give(json!([1]), "(.[0], .[1:]) |= .", json!([1]));
+    give(json!([1, 2, 3]), "(.[1:], .[0]) |= [9]", json!([[9], 9]));
+    // after deleting an element, later paths refer to the elements that moved up
+    give(json!([[1], [2]]), "(.[0], .[0][0]) |= {}[]", json!([[]]));
+    give(
+        json!([[1, 2], [3]]),
+        "(.[0][0], .[1], .[0][0]) |= {}[]",
+        json!([[]]),
+    );
+}
+
 #[test]
 fn range_update() {
     give(json!([0, 1, 2]), ".[:2] |= [.[] | .+5]", json!([5, 6, 2]));
@@ -178,8 +268,72 @@ fn update_complex() {
     // in general, `a | a |= .`
     // works in jq when `a` is either null, a number, or a boolean --- it
     // does *not* work when `a` is a string, an array, or an object!
-    fail(json!(0), "0 |= .+1", Error::path_expr());
+    // jaq rejects this already when compiling
+    assert_eq!(common::undefined("0 |= .+1"), ["0"]);
+    fail(json!(0), "def f: 0; f |= .+1", Error::path_expr());
+}
+
+#[test]
+fn try_label_update() {
+    give(json!({"a": 0}), "(try .a) |= 1", json!({"a": 1}));
+    give(json!(1), "(try .a) |= 1", json!(1));
+    give(json!({"a": 0}), "(.a | try .b) |= 1", json!({"a": 0}));
+    give(
+        json!({"a": [1, 2]}),
+        "(try .a[] catch .) |= .*10",
+        json!({"a": [10, 20]}),
+    );
+    give(
+        json!([1, 2]),
+        "(label $x | .[] | ., break $x) |= .+1",
+        json!([2, 2]),
+    );
+    fail(
+        json!([1, 2]),
+        "reduce .[] as $x (.; .) |= 3",
+        Error::path_expr(),
+    );
 }
 
 yields!(alt_update_l, "[1, 2] | .[] // . |= 3", [3, 3]);
 yields!(alt_update_r, "[] | .[] // . |= 3", 3);
+
+// In `l = r` and `l op= r`, `r` is evaluated with the original input,
+// whereas in `l |= f`, `f` is evaluated with the value at the path `l`.
+#[test]
+fn update_rhs_input() {
+    let x = || json!({"a": {"b": 10}, "b": 2});
+    give(x(), ".a = .b", json!({"a": 2, "b": 2}));
+    give(x(), ".a |= .b", json!({"a": 10, "b": 2}));
+    give(x(), ".a = .b + 1", json!({"a": 3, "b": 2}));
+    give(x(), ".a |= .b + 1", json!({"a": 11, "b": 2}));
+    give(x(), ".a.b += .b", json!({"a": {"b": 12}, "b": 2}));
+    give(x(), ".a.b |= . + 1", json!({"a": {"b": 11}, "b": 2}));
+    give(
+        json!({"a": {}, "b": 2}),
+        ".a |= .b",
+        json!({"a": null, "b": 2}),
+    );
+
+    let x = || json!({"a": 1, "b": 2});
+    give(x(), ".a += .b", json!({"a": 3, "b": 2}));
+    give(x(), ".a -= .b", json!({"a": -1, "b": 2}));
+    give(x(), ".a *= .b", json!({"a": 2, "b": 2}));
+    give(x(), ".a /= .b", json!({"a": 0.5, "b": 2}));
+    give(x(), ".a %= .b", json!({"a": 1, "b": 2}));
+    give(x(), ".c //= .b", json!({"a": 1, "b": 2, "c": 2}));
+    give(x(), ".a = .a + .b", json!({"a": 3, "b": 2}));
+    // the right-hand side is evaluated once for all paths
+    give(x(), "(.a, .b) = .a + 1", json!({"a": 2, "b": 2}));
+    give(x(), "(.a, .b) += .a", json!({"a": 2, "b": 3}));
+    give(x(), "(.a, .b) |= . + 1", json!({"a": 2, "b": 3}));
+    // `.a |= . + .b` fails because `.b` is evaluated with `1`
+    let err = Error::str(r#"cannot index number (1) with string ("b")"#);
+    fail(x(), ".a |= . + .b", err);
+
+    // every output of the right-hand side yields an output
+    let ys = [json!({"a": 2, "b": 2}), json!({"a": 1, "b": 2})];
+    gives(x(), ".a = (.b, .a)", ys);
+    let ys = [json!({"a": 3, "b": 2}), json!({"a": 2, "b": 2})];
+    gives(x(), ".a += (.b, .a)", ys);
+}
@@ -0,0 +1,47 @@
+//! Serialization of terms.
+#![cfg(feature = "serde")]
+
+use jaq_core::load::parse::{Def, Term};
+use jaq_core::load::{parse, parse_term};
+
+const FILTERS: &[&str] = &[
+    ".",
+    "..",
+    "null, true, 1, 1.5e3, \"a\"",
+    "@base64 \"x\\(.)y\"",
+    "[.[] | {a, \"b\": 1, (.c): .d?}]",
+    "-(1 + 2 * 3) // 4 == 5 and 6 or 7",
+    ". as [$x, {a: $y}] ?// [$y, $x] | $x + $y",
+    "label $x | 1, break $x",
+    "reduce .[] as $x (0; . + $x)",
+    "foreach .[] as $x (0; . + $x; [$x, .])",
+    "try error catch .",
+    "if . then 1 elif . == 2 then 3 else 4 end",
+    "def f(g; $x): g + $x; f(.; 1)",
+    ".a.b[0][1:][:2].[]? |= . + 1",
+    "$__loc__, $ENV",
+];
+
+// the terms obtained by deserializing serialized terms are equal to the original terms
+#[test]
+fn roundtrip_terms() {
+    for f in FILTERS {
+        let t = parse_term(f).unwrap();
+        let json = serde_json::to_value(&t).unwrap();
+        let t2: Term<String> = serde_json::from_value(json.clone()).unwrap();
+        assert_eq!(serde_json::to_value(&t2).unwrap(), json, "{f}");
+        if !f.contains('\\') {
+            let s = serde_json::to_string(&t).unwrap();
+            let t2: Term<&str> = serde_json::from_str(&s).unwrap();
+            assert_eq!(t2, t, "{f}");
+        }
+    }
+}
+
+#[test]
+fn roundtrip_defs() {
+    let defs = parse("def a: 1; def b($x; f): $x | f;", |p| p.defs()).unwrap();
+    let s = serde_json::to_string(&defs).unwrap();
+    let defs2: Vec<Def<&str>> = serde_json::from_str(&s).unwrap();
+    assert_eq!(defs2, defs);
+}
@@ -2,7 +2,8 @@
 
 pub mod common;
 
-use common::{give, gives};
+use common::{fail, give, gives, undefined};
+use jaq_core::Error;
 use serde_json::json;
 
 #[test]
@@ -17,6 +18,38 @@ fn update_assign() {
 // idem for other arithmetic operations
 yields!(cartesian_arith, "[(1,2) * (3,4)]", [3, 4, 6, 8]);
 
+#[test]
+fn assign_non_path() {
+    assert_eq!(undefined("(.a + 1) = 2"), ["a + 1"]);
+    assert_eq!(undefined("(1+2) = 3"), ["1+2"]);
+    assert_eq!(undefined("1 |= 2"), ["1"]);
+    assert_eq!(undefined(r#".a, "b" += 1"#), ["b"]);
+    assert_eq!(undefined("[.a] //= 1"), ["a"]);
+    assert_eq!(undefined("if . then .a else -1 end |= 2"), ["1"]);
+    assert_eq!(undefined("1 as $x | $x = 2"), ["$x"]);
+    assert_eq!(undefined(".a | {} = 1"), ["1"]);
+    // every invalid left-hand side is reported
+    assert_eq!(undefined(".a = 1 | 2 = 3"), ["2"]);
+
+    assert!(undefined(".a.b |= .+1").is_empty());
+    assert!(undefined(".. |= 1").is_empty());
+    assert!(undefined(".a[0] = 1").is_empty());
+    assert!(undefined("(.a, .b) |= 1").is_empty());
+    assert!(undefined(".[] += 1").is_empty());
+    assert!(undefined("def f: .a; f = 1").is_empty());
+    assert!(undefined(".a as $x | .[$x] = 1").is_empty());
+    assert!(undefined("if .a then .b elif .c then .d end |= 1").is_empty());
+    assert!(undefined(r#"(.a // .b) = 1"#).is_empty());
+    assert!(undefined("(.[] | .a) |= 2").is_empty());
+}
+
+yields!(num_frac, "[.5, .5e1, 1.5e-3]", [0.5, 5.0, 0.0015]);
+yields!(num_trailing_dot, "[1., 1.e3]", json!([1.0, 1000.0]));
+yields!(num_trailing_dot_str, r#""\(1.) \(1.e3)""#, "1.0 1.0e3");
+yields!(num_neg_zero, "-0", 0);
+yields!(num_huge_exp, "1e1000 > 1e308", true);
+yields!(num_path, "{a: 1} | [.5, .a]", json!([0.5, 1]));
+
 #[test]
 fn add() {
     give(json!(1), ". + 2", json!(3));
@@ -44,6 +77,12 @@ fn sub() {
 }
 
 yields!(sub_arr, "[1, 2, 3] - [2, 3, 4]", json!([1]));
+yields!(sub_arr_all, "[1, 2, 3, 2] - [2]", [1, 3]);
+yields!(
+    sub_arr_obj,
+    r#"[{"a": 1}, {"a": 2}, {"a": 1}] - [{"a": 1}]"#,
+    json!([{"a": 2}])
+);
 
 #[test]
 fn mul() {
@@ -63,11 +102,73 @@ fn mul() {
     );
 }
 
+#[test]
+fn mul_obj() {
+    let f = r#"{"a": {"b": 1, "c": 2}} * {"a": {"b": 10}}"#;
+    give(json!(null), f, json!({"a": {"b": 10, "c": 2}}));
+
+    // `null` overwrites, it does not delete
+    let f = r#"{"a": {"b": 1}, "c": 2} * {"a": {"b": {}.x}, "c": {}.x}"#;
+    give(json!(null), f, json!({"a": {"b": null}, "c": null}));
+
+    // non-objects overwrite objects and vice versa
+    let f = r#"{"a": {"b": 1}, "c": 2} * {"a": [1], "c": {"d": 3}}"#;
+    give(json!(null), f, json!({"a": [1], "c": {"d": 3}}));
+}
+
+#[test]
+fn div_rem_zero() {
+    let err = |l, r| {
+        let s =
+            format!("number ({l}) and number ({r}) cannot be divided because the divisor is zero");
+        Error::str(s)
+    };
+    fail(json!(1), ". / 0", err("1", "0"));
+    fail(json!(1), ". % 0", err("1", "0"));
+    // unlike jq, jaq writes the float divisor as `0.0`
+    fail(json!(1.5), ". / 0.0", err("1.5", "0.0"));
+    fail(json!(0), ". / 0", err("0", "0"));
+}
+
 yields!(div_str, r#""abcabcdab" / "ab""#, ["", "c", "cd", ""]);
 yields!(div_str_empty, r#""" / """#, json!([]));
 yields!(div_str_empty_str, r#""" / "ab""#, json!([]));
 yields!(div_str_empty_sep, r#""aöß" / """#, ["a", "ö", "ß"]);
 
+// type errors show the types and values of their operands
+#[test]
+fn type_error_msgs() {
+    let err = |s: &str| Error::str(s);
+    fail(
+        json!(null),
+        "1 + {}",
+        err("cannot calculate number (1) + object ({})"),
+    );
+    fail(
+        json!(null),
+        r#"{} - "a""#,
+        err(r#"cannot calculate object ({}) - string ("a")"#),
+    );
+    fail(
+        json!([1]),
+        r#".["a"]"#,
+        err(r#"cannot index array ([1]) with string ("a")"#),
+    );
+    fail(
+        json!(true),
+        ".[0]",
+        err("cannot index boolean (true) with number (0)"),
+    );
+
+    // long values are truncated
+    let f = "[1000, 1001, 1002, 1003, 1004, 1005, 1006] - 1";
+    let msg = "cannot calculate array ([1000,1001,1002,1003,1004,1005...) - number (1)";
+    fail(json!(null), f, err(msg));
+    let f = r#""äöüäöüäöüäöüäöüäöüäöüäöüäöüäöüäöü" - 1"#;
+    let msg = r#"cannot calculate string ("äöüäöüäöüäöüäöüäöüäöüäöüäöüäö...) - number (1)"#;
+    fail(json!(null), f, err(msg));
+}
+
 #[test]
 fn logic() {
     let tf = json!([true, false]);
@@ -84,6 +185,30 @@ fn alt() {
     give(json!([1, 2]), r#"[.[] // -"a"]"#, json!([1, 2]));
 }
 
+#[test]
+fn alt_chain() {
+    give(json!([null, false, 3]), ".[0] // .[1] // .[2]", json!(3));
+    let x = json!([null, false]);
+    give(x.clone(), "[.[] // (.[1], .[0]) // (3, 4)]", json!([3, 4]));
+    give(x.clone(), "[(.[0], 1) // (2, 3) // 4]", json!([1]));
+    give(x, "[.[0] // (.[1], 2, .[0], 3) // 4]", json!([2, 3]));
+}
+
+#[test]
+fn alt_fallback() {
+    // `.a[0]` fails because `.a` is an object, and `.b` is null
+    let f = r#".a[0] // .b // .c // "default""#;
+    give(json!({"a": {}, "b": null, "c": 3}), f, json!(3));
+    give(json!({"a": {}, "b": null}), f, json!("default"));
+    give(json!({"a": [1], "c": 3}), f, json!(1));
+    // errors are skipped like `null` and `false`
+    give(json!([1, {}, 2]), "[.[] | .[0]] // 3", json!(3));
+    give(json!([1, {}, 2]), "[.[] | (. + 1) // 0]", json!([2, 0, 3]));
+    // errors on the last alternative are not suppressed
+    let err = Error::str("cannot index object ({}) with number (0)");
+    fail(json!({"c": {}}), ".a // .b // .c[0]", err);
+}
+
 #[test]
 fn try_() {
     give(json!(0), ".?", json!(0));
@@ -91,6 +216,47 @@ fn try_() {
     give(json!(0), r#"[(1, -"a", 2)?]"#, json!([1, 2]));
 }
 
+yields!(try_opt_binop, "[{a: 1}, 0 | .a? + 1]", [2]);
+yields!(try_opt_twice, "{a: 1} | .a??", 1);
+yields!(try_no_catch, "[{a: 1}, 0 | try .a]", [1]);
+yields!(try_catch_str, r#"0 | try .a catch "oops""#, "oops");
+
+#[test]
+fn catch_without_try() {
+    use jaq_core::load::{format, Error};
+    let found = |code| match format(code) {
+        Err(Error::Parse(errs)) => errs.into_iter().map(|(_, found)| found).collect(),
+        _ => Vec::new(),
+    };
+    assert_eq!(found("1 catch 2"), ["catch"]);
+    assert_eq!(found(".a | catch 2"), ["catch"]);
+    assert_eq!(found("[catch]"), ["catch"]);
+}
+
+yields!(try_binop_err, "[({} + 1)?]", json!([]));
+yields!(try_binop_ok, "(1 + 1)?", 2);
+yields!(try_binop_path, "{a: {}, b: 1} | [(.a + .b)?]", json!([]));
+
+#[test]
+fn const_fold() {
+    let math = |code| common::compiled(code).contains("Math");
+    assert!(!math("2 * 3 + 1"));
+    assert!(common::compiled("2 * 3 + 1").contains("Int(7)"));
+    assert!(!math("-(1 - 3) * 2"));
+    assert!(!math(r#""a" + "b\n" + "c""#));
+    assert!(common::compiled(r#""a" + "b" + "c""#).contains(r#"Str("abc")"#));
+
+    assert!(math(". + 1"));
+    assert!(math("1 + 2 * ."));
+    assert!(math("1 / 2"));
+    assert!(math("9223372036854775807 + 1"));
+    assert!(math(r#""a\(1)""#));
+
+    give(json!(null), "2 * 3 + 1", json!(7));
+    give(json!(null), "-(1 - 3) * 2", json!(4));
+    give(json!(null), r#""a" + "b\n" + "c""#, json!("ab\nc"));
+}
+
 #[test]
 fn precedence() {
     // concatenation binds stronger than application
@@ -108,11 +274,17 @@ fn precedence() {
     // disjunction binds stronger than alternation
     give(json!(false), ". or . // 0", json!(0));
     give(json!(false), ". or (. // 0)", json!(true));
+    give(json!([false, true]), ".[0] or .[0] // .[1]", json!(true));
+    give(json!([false, true]), ".[0] and .[1] // 1", json!(1));
 
     // conjunction binds stronger than disjunction
     give(json!(true), "(0 != 0) and . or .", json!(true));
     give(json!(true), "(0 != 0) and (. or .)", json!(false));
 
+    // comparison binds stronger than conjunction
+    give(json!(1), ". < 2 and . > 0", json!(true));
+    give(json!(1), ". == 1 or . == 2 // 3", json!(true));
+
     give(json!(null), "1 + 2 * 3", json!(7));
     give(json!(null), "2 * 3 + 1", json!(7));
 }
@@ -140,6 +312,39 @@ yields!(atomic_opt, "def x: 0; try x? catch 1", 0);
 
 yields!(neg_arr_iter1, "[-[][]]", json!([]));
 yields!(neg_arr_iter2, "try (-[])[] catch 0", 0);
+yields!(neg_path, r#"{a: {b: 2}} | -.a.b"#, -2);
+yields!(neg_neg, "- -1", 1);
+yields!(neg_sub, "[1 - -1, 1 -1, 1--1]", [2, 0, 2]);
+yields!(neg_mul, "-2 | . * .", 4);
+// operators directly followed by `-` are separate tokens
+yields!(neg_after_op, r#"{a: 1} | .a |=-. | [.a, (2|-.)]"#, [-1, -2]);
+
+yields!(
+    str_escapes,
+    r#""\n\t\r\"\\\/\b\f""#,
+    "\n\t\r\"\\/\u{8}\u{c}"
+);
+yields!(str_unicode, r#""\u0041\u00e4\u2764""#, "Aä❤");
+yields!(str_surrogate_pair, r#""\ud83d\ude00""#, "😀");
+
+#[test]
+fn str_escape_errors() {
+    use jaq_core::load::{span, Lexer};
+    let errs = |code| {
+        let errs = Lexer::new(code).lex().unwrap_err();
+        let errs = errs
+            .into_iter()
+            .map(|(e, rest)| (e.as_str(), span(code, rest).start));
+        errs.collect::<Vec<_>>()
+    };
+    let unicode = "4-digit hexadecimal UTF-8 code point";
+    assert_eq!(errs(r#""\x""#), [("string escape sequence", 2)]);
+    assert_eq!(errs(r#""\u12""#), [(unicode, 5)]);
+    assert_eq!(errs(r#""\ud83d\u12""#), [(unicode, 11)]);
+    assert_eq!(errs(r#""\ud83d""#), [("surrogate pair", 2)]);
+    assert_eq!(errs(r#""\udc00""#), [("surrogate pair", 2)]);
+    assert_eq!(errs(r#""a\ud83d\u0041""#), [("surrogate pair", 3)]);
+}
 
 yields!(interpolation, r#"1 | "yields \(.+1)!""#, "yields 2!");
 // this diverges from jq, which yields ["2 2", "3 2", "2 4", "3 4"],
@@ -149,6 +354,11 @@ yields!(
     r#"2 | ["\(., .+1) \(., .*2)"]"#,
     ["2 2", "2 4", "3 2", "3 4"]
 );
+yields!(
+    interpolation_many3,
+    r#"["\(1, 2)-\(3, 4)-\(5, 6)"]"#,
+    ["1-3-5", "1-3-6", "1-4-5", "1-4-6", "2-3-5", "2-3-6", "2-4-5", "2-4-6"]
+);
 // this does not work in jq, because jq does not allow for defining formatters
 yields!(
     interpolation_fmt,
@@ -169,6 +379,42 @@ yields!(
 );
 yields!(interpolation_obj_str, r#""\({"❤\n": 0})""#, "{\"❤\\n\":0}");
 
+// non-string values are interpolated as their compact JSON representation
+yields!(interpolation_obj, r#""\({a: 1})""#, "{\"a\":1}");
+yields!(interpolation_arr, r#""x\([1, [2]])y""#, "x[1,[2]]y");
+yields!(interpolation_num, r#""\(1) \(-1.5)""#, "1 -1.5");
+yields!(interpolation_null, r#""\({}.a)""#, "null");
+// an escaped backslash followed by a parenthesis does not start an interpolation
+yields!(interpolation_escaped, r#""a\\(1)b""#, "a\\(1)b");
+// parentheses in strings inside interpolations do not close the interpolation
+yields!(
+    interpolation_parens,
+    r#""\(")" + "(")\((1 + (2)) * 3)""#,
+    ")(9"
+);
+yields!(
+    interpolation_nested_parens,
+    r#""a\("b\("c\(("d"))")")e""#,
+    "abcde"
+);
+
+yields!(arr_trailing_comma, "[1, 2, 3,]", [1, 2, 3]);
+yields!(arr_trailing_comma_pipe, "[1, 2 | . + 1,]", [2, 3]);
+
+#[test]
+fn trailing_comma_invalid() {
+    use jaq_core::load::{Lexer, Parser};
+    let parse = |code| {
+        let tokens = Lexer::new(code).lex().unwrap();
+        Parser::new(&tokens).parse(|p| p.term()).is_ok()
+    };
+    assert!(parse("[1, 2,]"));
+    let invalid = ["[1,,2]", "[1,,]", "[,]", "{a: 1,,}", "{,}", "(1,)", ".[0,]"];
+    for code in invalid {
+        assert!(!parse(code), "{code}");
+    }
+}
+
 yields!(
     obj_trailing_comma,
     "{a:1, b:2, c:3,}",
@@ -179,6 +425,25 @@ yields!(
     r#""c" | {a: 1, "b": 2, (.): 3}"#,
     json!({"a": 1, "b": 2, "c": 3})
 );
+yields!(
+    obj_interpolated_key,
+    r#"{"k": "color", "v": "red"} | {"\(.k)": .v, "x\(.k)y": 0}"#,
+    json!({"color": "red", "xcolory": 0})
+);
+yields!(
+    obj_interpolated_key_many,
+    r#"[{"\(1, 2)": 0}]"#,
+    json!([{"1": 0}, {"2": 0}])
+);
+
+#[test]
+fn obj_key_non_string() {
+    let err = |v: serde_json::Value| Error::typ(v.into(), "object key");
+    fail(json!(null), r#"{(1 + 1): "x"}"#, err(json!(2)));
+    fail(json!(null), "{(null): 0}", err(json!(null)));
+    fail(json!({"k": [1]}), "{(.k): 0}", err(json!([1])));
+}
+
 yields!(obj_proj, "{a: 1, b: 2} | {a,}", json!({"a": 1}));
 yields!(
     obj_proj_set,
@@ -187,6 +452,11 @@ yields!(
 );
 yields!(obj_var, r#""x" as $k | {$k}"#, json!({"k": "x"}));
 yields!(obj_var_val, r#""x" as $k | {$k: 0}"#, json!({"x": 0}));
+yields!(
+    obj_mixed,
+    r#"{a: 0, c: "k"} | 2 as $b | {a, $b, (.c): 1, "d": 2}"#,
+    json!({"a": 0, "b": 2, "k": 1, "d": 2})
+);
 yields!(
     obj_multi_keys,
     r#"[{("a", "b"): 1}]"#,
@@ -210,8 +480,62 @@ fn if_then_else() {
 
     let f = r#"if .>0, .<0 then 0 elif .>0, .<0 then 1 else 2 end"#;
     gives(json!(1), f, [json!(0), json!(1), json!(2)]);
+
+    // one output per output of the condition
+    let f = r#"[if .[] then "y" else "n" end]"#;
+    give(json!([true, false]), f, json!(["y", "n"]));
 }
 
+// a missing `else` branch yields the input
+yields!(
+    if_elif_no_else,
+    "[(1, 2, 3) | if . == 1 then 10 elif . == 2 then 20 end]",
+    [10, 20, 3]
+);
+
+#[test]
+fn if_missing_then() {
+    use jaq_core::load::{format, span, Error};
+    let spans = |code| match format(code) {
+        Err(Error::Parse(errs)) => errs
+            .into_iter()
+            .map(|(e, found)| (e.as_str(), span(code, found))),
+        _ => panic!("expected parse error"),
+    };
+    let errs: Vec<_> = spans("if 1 2 end").collect();
+    assert_eq!(errs, [("then", 5..6)]);
+    let errs: Vec<_> = spans("if 1 then 2 elif 3 4 end").collect();
+    assert_eq!(errs, [("then", 19..20)]);
+}
+
+/// Unclosed delimiters are reported with the opening delimiter and
+/// the position where the closing delimiter was expected.
+#[test]
+fn unclosed_delims() {
+    use jaq_core::load::{format, lex::Expect, span, Error};
+    let spans = |code| match format(code) {
+        Err(Error::Lex(errs)) => errs.into_iter().map(|(e, found)| match e {
+            Expect::Delim(open) => (span(code, open), span(code, found).start),
+            _ => panic!("expected unclosed delimiter"),
+        }),
+        _ => panic!("expected lex error"),
+    };
+    let errs: Vec<_> = spans("(1 + 2 | f(3; 4)").collect();
+    assert_eq!(errs, [(0..1, 16)]);
+    let errs: Vec<_> = spans(r#"{a: "b"#).collect();
+    assert_eq!(errs, [(4..5, 6), (0..1, 6)]);
+    // an unclosed delimiter does not cause errors in the rest of the program
+    let errs: Vec<_> = spans("[1, (2 | 3], 4 | {a: .} | [.[]]").collect();
+    assert_eq!(errs, [(4..5, 10)]);
+}
+
+// everything except `null` and `false` is truthy, including `0`, `""`, and `[]`
+yields!(
+    if_truthy,
+    r#"[if (0, "", [], {}, {}.a) then "y" else "n" end]"#,
+    ["y", "y", "y", "y", "n"]
+);
+
 yields!(
     label_break,
     "[label $x | 0, (label $y | 1, break $x, 2), 3]",
@@ -240,12 +564,12 @@ yields!(
 yields!(
     try_catch_does_not_short_circuit,
     "[try (\"1\", \"2\", {}[0], \"4\") catch .]",
-    ["1", "2", "cannot index {} with 0", "4"]
+    ["1", "2", "cannot index object ({}) with number (0)", "4"]
 );
 yields!(
     try_catch_nested,
     "try try {}[0] catch {}[1] catch .",
-    "cannot index {} with 1"
+    "cannot index object ({}) with number (1)"
 );
 yields!(
     try_catch_multi_valued,
@@ -343,7 +667,127 @@ fn vars() {
     give(json!(null), f, out());
 }
 
-yields!(shadow_funs, "def a: 1; def b: a; def a: 2; a + b", 3);
+yields!(
+    destruct_arr,
+    "[[1, [2, 3]], 4, 5] as [[$a, [$b, $c]], $d] | [$a, $b, $c, $d]",
+    [1, 2, 3, 4]
+);
+yields!(
+    destruct_arr_short,
+    "[1] as [$a, $b] | [$a, $b]",
+    json!([1, null])
+);
+yields!(
+    destruct_obj,
+    r#"{a: 1, b: {c: [2]}, d: 3} as {a: $x, "b": {c: [$y]}, $d} | [$x, $y, $d]"#,
+    [1, 2, 3]
+);
+// `{$x: p}` binds `$x` and matches `p` against the same value
+yields!(
+    destruct_obj_var,
+    "{x: [1]} as {$x: [$y]} | [$x, $y]",
+    json!([[1], 1])
+);
+// keys are evaluated on the destructured value and can use previously bound variables
+yields!(
+    destruct_obj_key,
+    r#"{a: "b", b: 1, c: 2} as {a: $k, ($k): $v, (.a | "c"): $w} | [$k, $v, $w]"#,
+    json!(["b", 1, 2])
+);
+yields!(
+    destruct_vars,
+    "def f($a): [1, 2] as [$x, $y] | $a + $x + $y; 0 as $z | f(10) + $z",
+    13
+);
+yields!(
+    destruct_alt,
+    "[{a: 1}, [2]] | [.[] | . as {a: $x} ?// [$x] | $x]",
+    [1, 2]
+);
+// errors in the body also make us try the next alternative
+yields!(
+    destruct_alt_body,
+    "[[1], 2] | [.[] as [$x] ?// $x | if $x == 1 then $x + {} else [$x] end]",
+    json!([[[1]], [2]])
+);
+
+#[test]
+fn destruct_alt_vars() {
+    use jaq_core::load::{format, span, Error};
+    let spans = |code| match format(code) {
+        Err(Error::Parse(errs)) => errs
+            .into_iter()
+            .map(|(e, found)| (e.as_str(), span(code, found))),
+        _ => panic!("expected parse error"),
+    };
+    let errs: Vec<_> = spans(". as [$x, $y] ?// {$x, $z} | 0").collect();
+    let expected = "variable bound by all alternatives";
+    assert_eq!(errs, [(expected, 10..12), (expected, 23..25)]);
+}
+
+yields!(def_local, "def f: def g: .+1; g | g; 1 | f", 3);
+yields!(def_local_expr, "1 | (def g: .*2; g) + 1", 3);
+yields!(def_local_after_op, "1 + def g: 2; g", 3);
+yields!(def_chain, "def inc: .+1; def inc2: inc | inc; 1 | inc2", 3);
+yields!(def_nullary, "def f: .+1; def g: f | f; 1 | g", 3);
+
+// like in jq, filters without arguments are defined and called without parentheses
+#[test]
+fn def_nullary_parens() {
+    use jaq_core::load::{format, span, Error};
+    let spans = |code| match format(code) {
+        Err(Error::Parse(errs)) => errs
+            .into_iter()
+            .map(|(e, found)| (e.as_str(), span(code, found))),
+        _ => panic!("expected parse error"),
+    };
+    let errs: Vec<_> = spans("def f: 1; f()").collect();
+    assert_eq!(errs, [("term", 12..13)]);
+    let errs: Vec<_> = spans("def f(): 1; f").collect();
+    assert_eq!(errs, [("argument", 6..7)]);
+}
+yields!(
+    def_local_shadow,
+    "def f: 1; [f, (def f: 2; f), f]",
+    [1, 2, 1]
+);
+
+#[test]
+fn def_local_scope() {
+    assert_eq!(undefined("def f: def g: 1; g; g"), ["g"]);
+    assert_eq!(undefined("(def g: 1; g) | g"), ["g"]);
+    assert!(undefined("def f: def g: 1; g; f").is_empty());
+}
+
+#[test]
+fn var_unbound() {
+    assert_eq!(undefined("$x"), ["$x"]);
+    assert_eq!(undefined("1 as $x | $x, $y"), ["$y"]);
+    assert_eq!(undefined("(1 as $x | $x), $x"), ["$x"]);
+    assert_eq!(undefined("def f($a): $a, $b; f(1)"), ["$b"]);
+    assert_eq!(undefined("reduce 1 as $x (0; $x) | $x"), ["$x"]);
+    assert!(undefined(". as [$a, {b: $c}] | $a, $c").is_empty());
+    assert!(undefined("$__loc__").is_empty());
+}
+
+yields!(constants, "[null, true, false]", json!([null, true, false]));
+// definitions cannot change the meaning of constants, like in jq
+yields!(
+    shadow_constants,
+    "def null: 1; def true: 2; [null, true]",
+    json!([null, true])
+);
+
+yields!(shadow_funs, "def a: 1; def b: a; (def a: 2; a + b)", 3);
+
+#[test]
+fn def_duplicate() {
+    // only the later definition is reported
+    assert_eq!(undefined("def f: 1; def f(g): g; def f: 2; f"), ["f"]);
+    assert!(undefined("def f: 1; def g: 2; def f(g): g; f").is_empty());
+    assert!(undefined("def f: def f: 1; f; f").is_empty());
+    assert_eq!(undefined("def g: def f: 1; def f: 2; f; g"), ["f"]);
+}
 yields!(shadow_vars, "1 as $x | 2 as $x | $x", 2);
 // arguments from the right are stronger than from the left
 yields!(shadow_args, "def f(g; g): g; f(1; 2)", 2);
@@ -363,6 +807,41 @@ const ACKERMANN: &str = "def ack($m; $n):
 
 yields!(ackermann, &(ACKERMANN.to_owned() + "ack(3; 4)"), 125);
 
+const ACKERMANN_COMMENTED: &str = "def # name follows
+  ack # arguments follow
+  ( # first argument
+    $m # separator follows
+    ; # second argument
+    $n # arguments end
+  ) # body follows
+  : # condition follows
+  if $m == 0 # no more recursion
+  then $n + # increment
+    1
+  elif $n == 0 then ack($m-1; # argument
+    1)
+  else ack($m-1; ack($m; $n-1)) # nested call
+  end # body ends
+  ; # comments may also end programs";
+
+#[test]
+fn def_comments() {
+    use jaq_core::load::{Lexer, Parser};
+    let defs = |code| {
+        let tokens = Lexer::new(code).lex().unwrap();
+        let defs = Parser::new(&tokens).parse(|p| p.defs()).unwrap();
+        format!("{defs:?}")
+    };
+    assert_eq!(defs(ACKERMANN), defs(ACKERMANN_COMMENTED));
+    let f = ACKERMANN_COMMENTED.to_owned() + "\nack(3; 4)";
+    give(json!(null), &f, json!(125));
+}
+
+yields!(comment_end, "1 + 2 # no newline after comment", 3);
+yields!(comment_lines, "[1, # one\n# two\n 3]", [1, 3]);
+yields!(comment_in_str, r#""a # b" # c"#, "a # b");
+yields!(comment_in_interpolation, "\"\\(1 # \"\n)\"", "1");
+
 #[test]
 fn reduce() {
     let ff = |s| format!(". as $x | reduce 2 as $y (4; {}) | . + $x", s);
@@ -374,6 +853,51 @@ fn reduce() {
     give(json!(1), &f, json!(11));
 }
 
+yields!(reduce_empty, "reduce {}[] as $x (0; .+$x)", 0);
+yields!(foreach_empty, "[foreach {}[] as $x (0; .+$x)]", json!([]));
+yields!(
+    foreach_project,
+    "[foreach (1, 2, 3) as $x (0; .+$x; [$x, .])]",
+    [[1, 1], [2, 3], [3, 6]]
+);
+// the extractor is run on every output of the update, and may yield multiple outputs
+yields!(
+    foreach_project_many,
+    "[foreach (5, 10) as $x (1; .+$x, -.; ., $x)]",
+    [6, 5, 16, 10, -6, 10, -1, 5, 9, 10, 1, 10]
+);
+yields!(
+    reduce_nested,
+    "reduce (1, 2) as $x (0; . + reduce (10, 20) as $y (0; . + $x * $y))",
+    90
+);
+
+#[test]
+fn fold_arity() {
+    assert_eq!(undefined("reduce 1 as $x (0; 1; 2)"), ["reduce"]);
+    assert_eq!(undefined("for 1 as $x (0; 1; 2)"), ["for"]);
+    assert_eq!(undefined("foreach 1 as $x (0; 1; 2; 3)"), ["foreach"]);
+    assert_eq!(undefined("foreach 1 as $x (0)"), ["foreach"]);
+}
+
+// INIT is evaluated with the input of `reduce`
+#[test]
+fn reduce_init_input() {
+    let f = "reduce .[] as $x (.; . + [$x])";
+    give(json!([1, 2]), f, json!([1, 2, 1, 2]));
+    let f = "reduce {}[] as $x (.; . + [$x])";
+    give(json!([1, 2]), f, json!([1, 2]));
+}
+
+// the whole reduction is run once per output of INIT
+#[test]
+fn reduce_init_many() {
+    let f = "[reduce .[] as $x ((0, 100); . + $x)]";
+    give(json!([1, 2]), f, json!([3, 103]));
+    let f = "[foreach .[] as $x ((0, 100); . + $x)]";
+    give(json!([1, 2]), f, json!([1, 3, 101, 103]));
+}
+
 yields!(
     foreach_cumulative_sum,
     "[1, 2, 3] | [foreach .[] as $x (0; .+$x)]",
@@ -399,12 +923,44 @@ yields!(
     [1, 4, 8, 16, 3, 7, 12]
 );
 
+// jq yields only [0] here, because it takes the *last* output of the update;
+// jaq continues the reduction from every output, yielding one value per path through
+// the outputs, ordered by the output taken first: `1+2`, `1*2`, `0+2`, `0*2`
+yields!(
+    reduce_many_outputs,
+    "[reduce (1, 2) as $x (0; .+$x, .*$x)]",
+    [3, 2, 2, 0]
+);
+// if the update yields no output, then the reduction yields no output (jq yields null)
+yields!(
+    reduce_no_outputs,
+    "[reduce (1, 2) as $x (0; {}[])]",
+    json!([])
+);
+
 yields!(update_alt, "[[0!=0, 3] | .[] //= (1, 2)]", [[1, 3], [2, 3]]);
 
 const FIRST: &str = "def first(f): label $x | f | ., break $x;";
 
 yields!(first, &(FIRST.to_owned() + "first(1, 2, 3)"), 1);
 
+// labels are resolved lexically, so labels with the same name do not interfere
+yields!(
+    label_first_nested,
+    &(FIRST.to_owned() + "[first(first(0, 1), 2), first(3, first(4))]"),
+    [0, 3]
+);
+yields!(
+    label_first_outer,
+    &(FIRST.to_owned() + "[label $x | first(break $x, 1), 2]"),
+    json!([])
+);
+yields!(
+    label_first_inner,
+    &(FIRST.to_owned() + "[label $x | first(first(0, break $x)), 1]"),
+    [0, 1]
+);
+
 const LIMIT: &str = "def limit($n; f):
   foreach f as $x ({$n}; {$x, n: .n - 1} | if .n < 0 then {}[] end) | .x;";
 
@@ -414,3 +970,31 @@ yields!(limit10, &(LIMIT.to_owned() + "[limit(1; {}[])]"), json!([]));
 yields!(limit12, &(LIMIT.to_owned() + "[limit(1; 1, 2)]"), [1]);
 yields!(limit21, &(LIMIT.to_owned() + "[limit(2; 1)]"), [1]);
 yields!(limit22, &(LIMIT.to_owned() + "[limit(2; 1, 2)]"), [1, 2]);
+
+yields!(loc, "{a: 1} | $__loc__", json!({"file": "", "line": 1}));
+yields!(loc_line, "[1,\n\n  $__loc__.line]", [1, 3]);
+yields!(loc_shadow, "0 as $__loc__ | $__loc__", 0);
+
+#[test]
+fn loc_module() {
+    use jaq_core::load::{Arena, File, Import, Loader};
+    use jaq_core::{Compiler, Native};
+
+    let read = |import: Import<&str>| {
+        assert_eq!(*import.path, "loc");
+        let code = "def f: # comment\n  $__loc__;".to_string();
+        let path = "loc.jq".to_string();
+        Ok(File { code, path })
+    };
+    let code = r#"import "loc" as loc; [loc::f, $__loc__]"#;
+
+    let arena = Arena::default();
+    let loader = Loader::new([]).with_read(read);
+    let path = "main.jq".into();
+    let modules = loader.load(&arena, File { path, code }).unwrap();
+    let filter = Compiler::<_, Native<jaq_json::Val>>::default()
+        .compile(modules)
+        .unwrap();
+    let y = json!([{"file": "loc.jq", "line": 2}, {"file": "main.jq", "line": 1}]);
+    filter.yields(json!(null).into(), core::iter::once(Ok(y.into())));
+}
@@ -0,0 +1,69 @@
+//! Tests for visiting parsed terms.
+
+use jaq_core::load::parse::{Def, Term};
+use jaq_core::load::visit::{self, Visit};
+use jaq_core::load::{Lexer, Parser};
+
+/// Names of definitions, calls, and variables, in order of their occurrence.
+#[derive(Default)]
+struct Names<'s>(Vec<&'s str>);
+
+impl<'s> Visit<&'s str> for Names<'s> {
+    fn visit_def(&mut self, def: &Def<&'s str>) {
+        self.0.push(def.name);
+        self.visit_term(&def.body)
+    }
+
+    fn visit_call(&mut self, name: &&'s str, args: &[Term<&'s str>]) {
+        self.0.push(name);
+        visit::walk_args(self, args)
+    }
+
+    fn visit_var(&mut self, x: &&'s str) {
+        self.0.push(x)
+    }
+}
+
+fn names(code: &str) -> Vec<&str> {
+    let tokens = Lexer::new(code).lex().unwrap();
+    let term = Parser::new(&tokens).parse(|p| p.term()).unwrap();
+    let mut names = Names::default();
+    names.visit_term(&term);
+    names.0
+}
+
+#[test]
+fn calls() {
+    let code = r#"def f(g): g | h; [.[] | f(map(.+1))] | {(k): "\(v)"} | .[i:j]? | try a catch b"#;
+    let expected = ["f", "g", "h", "f", "map", "k", "v", "i", "j", "a", "b"];
+    assert_eq!(names(code), expected);
+}
+
+#[test]
+fn vars() {
+    let code = "reduce .[] as $x (0; . + $x) as $y | if $y then $z else label $l | break $l end";
+    assert_eq!(names(code), ["$x", "$y", "$z"]);
+}
+
+fn unused(code: &str) -> Vec<&str> {
+    let tokens = Lexer::new(code).lex().unwrap();
+    let term = Parser::new(&tokens).parse(|p| p.term()).unwrap();
+    let unused = jaq_core::load::unused::defs(&term);
+    unused.iter().map(|d| d.name).collect()
+}
+
+#[test]
+fn unused_defs() {
+    assert_eq!(unused("def used: 1; def unused: 2; used"), ["unused"]);
+    // recursion and mutual recursion do not make definitions used
+    let code = "def a: a; def b: c; def c: b; def d: d; d";
+    assert_eq!(unused(code), ["a", "b", "c"]);
+    // calls refer to the latest definition with the same arity
+    let code = "def f: 1; def f(g): g; def f: 2; f, f(3)";
+    assert_eq!(unused(code), ["f"]);
+    // calls to arguments and local definitions do not refer to top-level definitions
+    let code = "def g: 0; def h: 0; def f(g; $h): def h: g; g, h, $h; f(1; 2)";
+    assert_eq!(unused(code), ["g", "h"]);
+    // definitions are only visible after they are defined
+    assert_eq!(unused("def f: g; def g: 1; f"), ["g"]);
+}
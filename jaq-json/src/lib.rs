@@ -22,6 +22,35 @@ use hifijson::{LexAlloc, Token};
 /// Operations on numbers follow a few principles:
 /// * The sum, difference, product, and remainder of two integers is integer.
 /// * Any other operation between two numbers yields a float.
+///
+/// Values can be built and inspected without going through JSON text:
+///
+/// ~~~
+/// use jaq_core::{load, Compiler, Ctx, Native, RcIter};
+/// use jaq_json::Val;
+///
+/// let arr = Val::array([Val::from(1), Val::from(2.0), Val::from("three")]);
+/// let input = Val::object([("xs", arr), ("n", Val::from(i64::MAX))]);
+///
+/// let arena = load::Arena::default();
+/// let code = ".xs[0], .xs[1], .xs[2], .n";
+/// let file = load::File { path: "".into(), code };
+/// let modules = load::Loader::new([]).load(&arena, file).unwrap();
+/// let filter = Compiler::<_, Native<_>>::default().compile(modules).unwrap();
+///
+/// let inputs = RcIter::new(core::iter::empty());
+/// let out: Vec<_> = filter.run((Ctx::new([], &inputs), input)).map(Result::unwrap).collect();
+///
+/// // integers and floating-point numbers stay distinct
+/// assert_eq!(out[0].as_i64(), Some(1));
+/// assert_eq!(out[1].as_i64(), None);
+/// assert_eq!(out[2].as_str(), Some("three"));
+/// assert_eq!(out[3].as_i64(), Some(i64::MAX));
+///
+/// let arr = Val::array(out);
+/// assert_eq!(arr.as_array().map(|a| a.len()), Some(4));
+/// assert!(arr.as_object().is_none());
+/// ~~~
 #[derive(Clone, Debug)]
 pub enum Val {
     /// Null
@@ -51,11 +80,13 @@ enum Type {
     Float,
     /// `-"a"`, `"a" | round`
     Num,
-    /// `{(0): 1}` or `0 | fromjson` or `0 | explode` or `"a b c" | split(0)`
+    /// `0 | fromjson` or `0 | explode` or `"a b c" | split(0)`
     Str,
+    /// `{(0): 1}`
+    Key,
     /// `0 | sort` or `0 | implode` or `[] | .[0:] = 0`
     Arr,
-    /// `0 | .[]` or `0 | .[0]` or `0 | keys` (array or object)
+    /// `0 | .[]` or `0 | keys` (array or object)
     Iter,
     /// `{}[0:1]` (string or array)
     Range,
@@ -68,6 +99,7 @@ impl Type {
             Self::Float => "floating-point number",
             Self::Num => "number",
             Self::Str => "string",
+            Self::Key => "object key",
             Self::Arr => "array",
             Self::Iter => "iterable (array or object)",
             Self::Range => "rangeable (array or string)",
@@ -93,12 +125,16 @@ fn rc_unwrap_or_clone<T: Clone>(a: Rc<T>) -> T {
 }
 
 impl jaq_core::ValT for Val {
+    fn null() -> Self {
+        Self::Null
+    }
+
     fn from_num(n: &str) -> ValR {
         Ok(Val::Num(Rc::new(n.to_string())))
     }
 
     fn from_map<I: IntoIterator<Item = (Self, Self)>>(iter: I) -> ValR {
-        let iter = iter.into_iter().map(|(k, v)| Ok((k.into_str()?, v)));
+        let iter = iter.into_iter().map(|(k, v)| Ok((k.into_key()?, v)));
         Ok(Self::obj(iter.collect::<Result<_, _>>()?))
     }
 
@@ -110,14 +146,29 @@ impl jaq_core::ValT for Val {
         }
     }
 
+    fn key_values(self) -> Box<dyn Iterator<Item = Result<(Self, Self), Error>>> {
+        match self {
+            Self::Arr(a) => {
+                let iter = rc_unwrap_or_clone(a).into_iter().enumerate();
+                Box::new(iter.map(|(i, v)| Ok((Val::Int(i as isize), v))))
+            }
+            Self::Obj(o) => {
+                let iter = rc_unwrap_or_clone(o).into_iter();
+                Box::new(iter.map(|(k, v)| Ok((Val::Str(k), v))))
+            }
+            _ => Box::new(core::iter::once(Err(Error::typ(self, Type::Iter.as_str())))),
+        }
+    }
+
     fn index(self, index: &Self) -> ValR {
         match (self, index) {
             (Val::Arr(a), Val::Int(i)) => {
                 Ok(abs_index(*i, a.len()).map_or(Val::Null, |i| a[i].clone()))
             }
             (Val::Obj(o), Val::Str(s)) => Ok(o.get(s).cloned().unwrap_or(Val::Null)),
-            (s @ (Val::Arr(_) | Val::Obj(_)), _) => Err(Error::index(s, index.clone())),
-            (s, _) => Err(Error::typ(s, Type::Iter.as_str())),
+            // like in jq, `null | .a` and `null | .[0]` yield `null`
+            (Val::Null, Val::Str(_) | Val::Int(_)) => Ok(Val::Null),
+            (s, _) => Err(index_error(&s, index)),
         }
     }
 
@@ -146,6 +197,7 @@ impl jaq_core::ValT for Val {
                     Val::from(s.chars().skip(skip).take(take).collect::<String>())
                 })
             }
+            Val::Null => Ok(Val::Null),
             _ => Err(Error::typ(self, Type::Range.as_str())),
         }
     }
@@ -175,13 +227,32 @@ impl jaq_core::ValT for Val {
         opt: path::Opt,
         f: impl Fn(Self) -> I,
     ) -> ValX<'a> {
+        // like in jq, `null` is updated like an empty object or array,
+        // but deleting from it yields `null`
+        if let Val::Null = self {
+            match index {
+                Val::Str(k) => {
+                    return match f(Val::Null).next().transpose()? {
+                        Some(y) => Ok(Val::obj(core::iter::once((k.clone(), y)).collect())),
+                        None => Ok(self),
+                    }
+                }
+                Val::Int(i) => {
+                    return match f(Val::Null).next().transpose()? {
+                        Some(_) => opt.fail(self, |_| Exn::from(oob_error(*i))),
+                        None => Ok(self),
+                    }
+                }
+                _ => (),
+            }
+        }
         match self {
             Val::Obj(ref mut o) => {
                 use indexmap::map::Entry::{Occupied, Vacant};
                 let o = Rc::make_mut(o);
                 let i = match index {
                     Val::Str(s) => s,
-                    i => return opt.fail(self, |v| Exn::from(Error::index(v, i.clone()))),
+                    i => return opt.fail(self, |v| Exn::from(index_error(&v, i))),
                 };
                 match o.entry(Rc::clone(i)) {
                     Occupied(mut e) => {
@@ -202,12 +273,20 @@ impl jaq_core::ValT for Val {
             }
             Val::Arr(ref mut a) => {
                 let a = Rc::make_mut(a);
-                let abs_or = |i| {
-                    abs_index(i, a.len()).ok_or(Error::str(format_args!("index {i} out of bounds")))
+                let abs_or = |i| abs_index(i, a.len()).ok_or_else(|| oob_error(i));
+                let i = match index {
+                    Val::Int(i) => *i,
+                    i => return opt.fail(self, |v| Exn::from(index_error(&v, i))),
                 };
-                let i = match index.as_int().and_then(abs_or) {
+                let i = match abs_or(i) {
                     Ok(i) => i,
-                    Err(e) => return opt.fail(self, |_| Exn::from(e)),
+                    // deleting an element that does not exist leaves the array unchanged
+                    Err(e) => {
+                        return match f(Val::Null).next().transpose()? {
+                            Some(_) => opt.fail(self, |_| Exn::from(e)),
+                            None => Ok(self),
+                        }
+                    }
                 };
 
                 if let Some(y) = f(a[i].clone()).next().transpose()? {
@@ -217,7 +296,7 @@ impl jaq_core::ValT for Val {
                 }
                 Ok(self)
             }
-            _ => opt.fail(self, |v| Exn::from(Error::typ(v, Type::Iter.as_str()))),
+            _ => opt.fail(self, |v| Exn::from(index_error(&v, index))),
         }
     }
 
@@ -256,11 +335,7 @@ impl jaq_core::ValT for Val {
 
     /// If the value is a string, return it, else fail.
     fn as_str(&self) -> Option<&str> {
-        if let Self::Str(s) = self {
-            Some(s)
-        } else {
-            None
-        }
+        Val::as_str(self)
     }
 }
 
@@ -357,7 +432,7 @@ fn base_funs() -> Box<[Filter<RunPtr<Val>>]> {
             ow!(cv.1.keys_unsorted().map(|v| Val::Arr(v.into())))
         }),
         ("contains", v(1), |_, cv| {
-            unary(cv, |x, y| Ok(Val::from(x.contains(&y))))
+            unary(cv, |x, y| x.contains_same(&y).map(Val::from))
         }),
         ("has", v(1), |_, cv| {
             unary(cv, |v, k| v.has(&k).map(Val::from))
@@ -384,12 +459,61 @@ fn from_json(s: &str) -> ValR {
 #[cfg(feature = "parse")]
 fn parse_fun() -> Filter<RunPtr<Val>> {
     ("fromjson", v(0), |_, cv| {
-        ow!(cv.1.as_str().and_then(|s| from_json(s)))
+        ow!(cv.1.to_str().and_then(|s| from_json(s)))
     })
 }
 
 fn skip_take(from: usize, until: usize) -> (usize, usize) {
-    (from, if until > from { until - from } else { 0 })
+    (from, until.saturating_sub(from))
+}
+
+/// Error that occurs when indexing a value with an index of the wrong type.
+fn index_error(v: &Val, i: &Val) -> Error {
+    let (v, i) = (Typed(v), Typed(i));
+    Error::str(format_args!("cannot index {v} with {i}"))
+}
+
+/// Error that occurs when updating an array at an index beyond its length.
+fn oob_error(i: isize) -> Error {
+    Error::str(format_args!("index {i} out of bounds"))
+}
+
+/// Error that occurs when applying an arithmetic operation to values of wrong types.
+fn math_error(l: &Val, op: ops::Math, r: &Val) -> Error {
+    let (l, op, r) = (Typed(l), op.as_str(), Typed(r));
+    Error::str(format_args!("cannot calculate {l} {op} {r}"))
+}
+
+/// Value that is displayed together with its type, such as `number (1)`.
+///
+/// Long values are truncated, in order to keep error messages readable.
+struct Typed<'a>(&'a Val);
+
+impl fmt::Display for Typed<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use fmt::Write;
+        let mut s = Truncate(String::new(), 30);
+        // formatting fails iff the value was truncated
+        let dots = if write!(s, "{}", self.0).is_ok() {
+            ""
+        } else {
+            "..."
+        };
+        write!(f, "{} ({}{dots})", self.0.type_name(), s.0)
+    }
+}
+
+/// Writer that stores at most the given number of characters, failing when exceeding it.
+struct Truncate(String, usize);
+
+impl fmt::Write for Truncate {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for c in s.chars() {
+            self.1 = self.1.checked_sub(1).ok_or(fmt::Error)?;
+            self.0.push(c);
+        }
+        Ok(())
+    }
 }
 
 /// If a range bound is given, absolutise and clip it between 0 and `len`,
@@ -429,6 +553,66 @@ impl Val {
         Self::Obj(m.into())
     }
 
+    /// Construct an array value from its elements.
+    pub fn array(iter: impl IntoIterator<Item = Self>) -> Self {
+        iter.into_iter().collect()
+    }
+
+    /// Construct an object value from its key-value pairs.
+    ///
+    /// If a key occurs multiple times, its last value is kept.
+    pub fn object<K: Into<String>>(iter: impl IntoIterator<Item = (K, Self)>) -> Self {
+        let iter = iter.into_iter().map(|(k, v)| (Rc::new(k.into()), v));
+        Self::obj(iter.collect())
+    }
+
+    /// If the value is a string, return it.
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Self::Str(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// If the value is an integer that fits into `i64`, return it.
+    ///
+    /// This yields `None` for floating-point numbers, even if they have no fractional part.
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            Self::Int(i) => (*i).try_into().ok(),
+            Self::Num(n) => n.parse().ok(),
+            _ => None,
+        }
+    }
+
+    /// If the value is an array, return its elements.
+    pub fn as_array(&self) -> Option<&[Self]> {
+        match self {
+            Self::Arr(a) => Some(a),
+            _ => None,
+        }
+    }
+
+    /// If the value is an object, return its key-value pairs.
+    pub fn as_object(&self) -> Option<&Map<Rc<String>, Self>> {
+        match self {
+            Self::Obj(o) => Some(o),
+            _ => None,
+        }
+    }
+
+    /// Return the name of the type of the value, as yielded by `type`.
+    fn type_name(&self) -> &'static str {
+        match self {
+            Self::Null => "null",
+            Self::Bool(_) => "boolean",
+            Self::Int(_) | Self::Float(_) | Self::Num(_) => "number",
+            Self::Str(_) => "string",
+            Self::Arr(_) => "array",
+            Self::Obj(_) => "object",
+        }
+    }
+
     /// If the value is integer, return it, else fail.
     fn as_int(&self) -> Result<isize, Error> {
         match self {
@@ -450,16 +634,16 @@ impl Val {
         }
     }
 
-    /// If the value is a string, return it, else fail.
-    fn into_str(self) -> Result<Rc<String>, Error> {
+    /// If the value is a string, return it for use as object key, else fail.
+    fn into_key(self) -> Result<Rc<String>, Error> {
         match self {
             Self::Str(s) => Ok(s),
-            _ => Err(Error::typ(self, Type::Str.as_str())),
+            _ => Err(Error::typ(self, Type::Key.as_str())),
         }
     }
 
     /// If the value is a string, return it, else fail.
-    fn as_str(&self) -> Result<&Rc<String>, Error> {
+    fn to_str(&self) -> Result<&Rc<String>, Error> {
         match self {
             Self::Str(s) => Ok(s),
             _ => Err(Error::typ(self.clone(), Type::Str.as_str())),
@@ -481,10 +665,11 @@ impl Val {
 
     /// Return true if `value | .[key]` is defined.
     ///
+    /// For arrays, this is only the case for `0 <= key < length`, like in jq.
     /// Fail on values that are neither arrays nor objects.
     fn has(&self, key: &Self) -> Result<bool, Error> {
         match (self, key) {
-            (Self::Arr(a), Self::Int(i)) if *i >= 0 => Ok((*i as usize) < a.len()),
+            (Self::Arr(a), Self::Int(i)) => Ok(*i >= 0 && (*i as usize) < a.len()),
             (Self::Obj(o), Self::Str(s)) => Ok(o.contains_key(&**s)),
             _ => Err(Error::index(self.clone(), key.clone())),
         }
@@ -507,6 +692,11 @@ impl Val {
     /// * for every key-value pair `k, v` in `b`,
     ///   there is a key-value pair `k, v'` in `a` such that `v'` contains `v`, or
     /// * `a` equals `b`.
+    ///
+    /// This means that for arrays of strings, `a` contains `b` iff
+    /// every string in `b` is a substring of some string in `a`, so
+    /// `["foobar"]` contains `["bar"]`, and
+    /// `[1, 2]` contains `[1, 1]` as well as `[]`.
     fn contains(&self, other: &Self) -> bool {
         match (self, other) {
             (Self::Str(l), Self::Str(r)) => l.contains(&**r),
@@ -518,14 +708,124 @@ impl Val {
         }
     }
 
+    /// Return true if `self` contains `other`, failing if they have different types.
+    ///
+    /// Like in jq, the types of `true` and `false` count as different here,
+    /// whereas values of different types nested inside arrays and objects
+    /// are just not contained in each other.
+    fn contains_same(&self, other: &Self) -> Result<bool, Error> {
+        let kind = |v: &Self| (v.type_name(), matches!(v, Self::Bool(true)));
+        if kind(self) == kind(other) {
+            Ok(self.contains(other))
+        } else {
+            let (l, r) = (Typed(self), Typed(other));
+            let msg = format_args!("{l} and {r} cannot have their containment checked");
+            Err(Error::str(msg))
+        }
+    }
+
     /// Parse at least one JSON value, given an initial token and a lexer.
     ///
     /// If the underlying lexer reads input fallibly (for example `IterLexer`),
     /// the error returned by this function might be misleading.
     /// In that case, always check whether the lexer contains an error.
+    ///
+    /// This does not limit how deeply values may be nested;
+    /// for that, use [`Self::parse_bounded`].
     #[cfg(feature = "hifijson")]
     pub fn parse(token: Token, lexer: &mut impl LexAlloc) -> Result<Self, hifijson::Error> {
-        use hifijson::{token, Error};
+        Self::parse_bounded(usize::MAX, token, lexer)
+    }
+
+    /// Parse at least one JSON value, nesting arrays and objects at most `depth` times.
+    ///
+    /// This keeps track of incomplete arrays and objects on the heap, so
+    /// parsing deeply nested values does not risk overflowing the stack.
+    #[cfg(feature = "hifijson")]
+    pub fn parse_bounded(
+        depth: usize,
+        mut token: Token,
+        lexer: &mut impl LexAlloc,
+    ) -> Result<Self, hifijson::Error> {
+        use hifijson::{token::Expect, Error};
+
+        /// Array or object whose remaining children have yet to be parsed.
+        enum Partial {
+            Arr(Vec<Val>),
+            /// object and the key of the value being parsed
+            Obj(Map<Rc<String>, Val>, Rc<String>),
+        }
+
+        fn key<L: LexAlloc>(token: Token, lexer: &mut L) -> Result<Rc<String>, Error> {
+            let key = lexer.str_colon(token, |lexer| lexer.str_string().map_err(Error::Str))?;
+            Ok(Rc::new(key.to_string()))
+        }
+
+        let mut stack = Vec::new();
+        loop {
+            // open arrays and objects until we get a complete value
+            let mut v = match token {
+                Token::LSquare | Token::LCurly if stack.len() >= depth => Err(Error::Depth)?,
+                Token::LSquare => match lexer.ws_token().ok_or(Expect::ValueOrEnd)? {
+                    Token::RSquare => Self::Arr(Rc::default()),
+                    next => {
+                        stack.push(Partial::Arr(Vec::new()));
+                        token = next;
+                        continue;
+                    }
+                },
+                Token::LCurly => match lexer.ws_token().ok_or(Expect::ValueOrEnd)? {
+                    Token::RCurly => Self::Obj(Rc::default()),
+                    next => {
+                        stack.push(Partial::Obj(Map::default(), key(next, lexer)?));
+                        token = lexer.ws_token().ok_or(Expect::Value)?;
+                        continue;
+                    }
+                },
+                token => Self::parse_scalar(token, lexer)?,
+            };
+
+            // add the value to its parent, closing parents as long as they end
+            token = loop {
+                let parent = match stack.last_mut() {
+                    Some(parent) => parent,
+                    None => return Ok(v),
+                };
+                let next = lexer.ws_token().ok_or(Expect::CommaOrEnd);
+                match parent {
+                    Partial::Arr(arr) => match next? {
+                        Token::Comma => arr.push(v),
+                        Token::RSquare => {
+                            arr.push(v);
+                            v = Self::Arr(core::mem::take(arr).into());
+                            stack.pop();
+                            continue;
+                        }
+                        _ => Err(Expect::CommaOrEnd)?,
+                    },
+                    Partial::Obj(obj, k) => {
+                        obj.insert(core::mem::take(k), v);
+                        match next? {
+                            Token::Comma => {
+                                *k = key(lexer.ws_token().ok_or(Expect::String)?, lexer)?
+                            }
+                            Token::RCurly => {
+                                v = Self::obj(core::mem::take(obj));
+                                stack.pop();
+                                continue;
+                            }
+                            _ => Err(Expect::CommaOrEnd)?,
+                        }
+                    }
+                }
+                break lexer.ws_token().ok_or(Expect::Value)?;
+            };
+        }
+    }
+
+    /// Parse a JSON value that is neither an array nor an object.
+    #[cfg(feature = "hifijson")]
+    fn parse_scalar(token: Token, lexer: &mut impl LexAlloc) -> Result<Self, hifijson::Error> {
         match token {
             Token::Null => Ok(Self::Null),
             Token::True => Ok(Self::Bool(true)),
@@ -542,28 +842,7 @@ impl Val {
                 Ok(Self::Num(Rc::new(num.to_string())))
             }
             Token::Quote => Ok(Self::from(lexer.str_string()?.to_string())),
-            Token::LSquare => Ok(Self::Arr({
-                let mut arr = Vec::new();
-                lexer.seq(Token::RSquare, |token, lexer| {
-                    arr.push(Self::parse(token, lexer)?);
-                    Ok::<_, hifijson::Error>(())
-                })?;
-                arr.into()
-            })),
-            Token::LCurly => Ok(Self::obj({
-                let mut obj = Map::default();
-                lexer.seq(Token::RCurly, |token, lexer| {
-                    let key =
-                        lexer.str_colon(token, |lexer| lexer.str_string().map_err(Error::Str))?;
-
-                    let token = lexer.ws_token().ok_or(token::Expect::Value)?;
-                    let value = Self::parse(token, lexer)?;
-                    obj.insert(Rc::new(key.to_string()), value);
-                    Ok::<_, Error>(())
-                })?;
-                obj
-            })),
-            _ => Err(token::Expect::Value)?,
+            _ => Err(hifijson::token::Expect::Value)?,
         }
     }
 }
@@ -620,6 +899,19 @@ impl From<isize> for Val {
     }
 }
 
+impl From<i32> for Val {
+    fn from(i: i32) -> Self {
+        Self::Int(i as isize)
+    }
+}
+
+impl From<i64> for Val {
+    fn from(i: i64) -> Self {
+        // `isize` may be smaller than `i64`, for example on 32-bit systems
+        isize::try_from(i).map_or_else(|_| Self::Num(Rc::new(i.to_string())), Self::Int)
+    }
+}
+
 impl From<f64> for Val {
     fn from(f: f64) -> Self {
         Self::Float(f)
@@ -632,6 +924,12 @@ impl From<String> for Val {
     }
 }
 
+impl From<&str> for Val {
+    fn from(s: &str) -> Self {
+        Self::from(s.to_string())
+    }
+}
+
 impl FromIterator<Self> for Val {
     fn from_iter<T: IntoIterator<Item = Self>>(iter: T) -> Self {
         Self::Arr(Rc::new(iter.into_iter().collect()))
@@ -663,7 +961,7 @@ impl core::ops::Add for Val {
                 Rc::make_mut(&mut l).extend(r.iter().map(|(k, v)| (k.clone(), v.clone())));
                 Ok(Obj(l))
             }
-            (l, r) => Err(Error::math(l, ops::Math::Add, r)),
+            (l, r) => Err(math_error(&l, ops::Math::Add, &r)),
         }
     }
 }
@@ -684,7 +982,7 @@ impl core::ops::Sub for Val {
                 Rc::make_mut(&mut l).retain(|x| !r.contains(x));
                 Ok(Arr(l))
             }
-            (l, r) => Err(Error::math(l, ops::Math::Sub, r)),
+            (l, r) => Err(math_error(&l, ops::Math::Sub, &r)),
         }
     }
 }
@@ -719,7 +1017,7 @@ impl core::ops::Mul for Val {
                 obj_merge(&mut l, r);
                 Ok(Obj(l))
             }
-            (l, r) => Err(Error::math(l, ops::Math::Mul, r)),
+            (l, r) => Err(math_error(&l, ops::Math::Mul, &r)),
         }
     }
 }
@@ -738,11 +1036,19 @@ fn split<'a>(s: &'a str, sep: &'a str) -> Box<dyn Iterator<Item = String> + 'a>
     }
 }
 
+/// Error that occurs when dividing a number by zero.
+fn div_zero(l: Val, r: Val) -> Error {
+    Error::str(format_args!(
+        "number ({l}) and number ({r}) cannot be divided because the divisor is zero"
+    ))
+}
+
 impl core::ops::Div for Val {
     type Output = ValR;
     fn div(self, rhs: Self) -> Self::Output {
         use Val::{Float, Int, Num, Str};
         match (self, rhs) {
+            (l @ (Int(_) | Float(_) | Num(_)), r) if r == Int(0) => Err(div_zero(l, r)),
             (Int(x), Int(y)) => Ok(Float(x as f64 / y as f64)),
             (Float(f), Int(i)) => Ok(Float(f / i as f64)),
             (Int(i), Float(f)) => Ok(Float(i as f64 / f)),
@@ -750,7 +1056,7 @@ impl core::ops::Div for Val {
             (Num(n), r) => Self::from_dec_str(&n) / r,
             (l, Num(n)) => l / Self::from_dec_str(&n),
             (Str(x), Str(y)) => Ok(split(&x, &y).map(Val::from).collect()),
-            (l, r) => Err(Error::math(l, ops::Math::Div, r)),
+            (l, r) => Err(math_error(&l, ops::Math::Div, &r)),
         }
     }
 }
@@ -758,10 +1064,11 @@ impl core::ops::Div for Val {
 impl core::ops::Rem for Val {
     type Output = ValR;
     fn rem(self, rhs: Self) -> Self::Output {
-        use Val::Int;
+        use Val::{Float, Int, Num};
         match (self, rhs) {
-            (Int(x), Int(y)) if y != 0 => Ok(Int(x % y)),
-            (l, r) => Err(Error::math(l, ops::Math::Rem, r)),
+            (l @ (Int(_) | Float(_) | Num(_)), r) if r == Int(0) => Err(div_zero(l, r)),
+            (Int(x), Int(y)) => Ok(Int(x % y)),
+            (l, r) => Err(math_error(&l, ops::Math::Rem, &r)),
         }
     }
 }
@@ -891,8 +1198,19 @@ pub fn fmt_str(f: &mut fmt::Formatter, s: &str) -> fmt::Result {
     write!(f, "\"")
 }
 
-impl fmt::Display for Val {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+/// Array or object whose children are being formatted.
+enum Nested<'a> {
+    Arr(core::slice::Iter<'a, Val>),
+    Obj(indexmap::map::Iter<'a, Rc<String>, Val>),
+}
+
+impl Val {
+    /// Format a scalar completely, or format the opening of an array or object.
+    fn fmt_open<'a>(
+        &'a self,
+        f: &mut fmt::Formatter,
+        stack: &mut Vec<(Nested<'a>, bool)>,
+    ) -> fmt::Result {
         match self {
             Self::Null => write!(f, "null"),
             Self::Bool(b) => write!(f, "{b}"),
@@ -902,23 +1220,46 @@ impl fmt::Display for Val {
             Self::Num(n) => write!(f, "{n}"),
             Self::Str(s) => fmt_str(f, s),
             Self::Arr(a) => {
-                write!(f, "[")?;
-                let mut iter = a.iter();
-                if let Some(first) = iter.next() {
-                    write!(f, "{first}")?;
-                };
-                iter.try_for_each(|x| write!(f, ",{x}"))?;
-                write!(f, "]")
+                stack.push((Nested::Arr(a.iter()), true));
+                write!(f, "[")
             }
             Self::Obj(o) => {
-                write!(f, "{{")?;
-                let mut iter = o.iter();
-                if let Some((k, v)) = iter.next() {
-                    write!(f, "{k:?}:{v}")?;
+                stack.push((Nested::Obj(o.iter()), true));
+                write!(f, "{{")
+            }
+        }
+    }
+}
+
+impl fmt::Display for Val {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // we use an explicit stack instead of recursion in order to
+        // format deeply nested values without overflowing the call stack
+        let mut stack = Vec::new();
+        self.fmt_open(f, &mut stack)?;
+        while let Some((nested, first)) = stack.last_mut() {
+            let first = core::mem::replace(first, false);
+            let (close, next) = match nested {
+                Nested::Arr(iter) => (']', iter.next().map(|v| (None, v))),
+                Nested::Obj(iter) => ('}', iter.next().map(|(k, v)| (Some(k), v))),
+            };
+            match next {
+                None => {
+                    stack.pop();
+                    write!(f, "{close}")?;
+                }
+                Some((k, v)) => {
+                    if !first {
+                        write!(f, ",")?;
+                    }
+                    if let Some(k) = k {
+                        fmt_str(f, k)?;
+                        write!(f, ":")?;
+                    }
+                    v.fmt_open(f, &mut stack)?;
                 }
-                iter.try_for_each(|(k, v)| write!(f, ",{k:?}:{v}"))?;
-                write!(f, "}}")
             }
         }
+        Ok(())
     }
 }
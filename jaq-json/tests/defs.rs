@@ -20,6 +20,10 @@ fn entries() {
     give(arr, "to_entries", entries);
 
     give(json!([]), "from_entries", json!({}));
+
+    // non-string keys are converted to strings
+    let entries = json!([{"key": 2, "value": "x"}, {"key": null, "value": 0}]);
+    give(entries, "from_entries", json!({"2": "x", "null": 0}));
 }
 
 #[test]
@@ -56,6 +60,22 @@ yields!(
     "{a: [1, [2]], b: {c: 3}} | [paths]",
     json!([["a"], ["a", 0], ["a", 1], ["a", 1, 0], ["b"], ["b", "c"]])
 );
+yields!(
+    paths_getpath,
+    "{a: [1, [2]], b: 3} | [getpath(paths)]",
+    json!([[1, [2]], 1, [2], 2, 3])
+);
+// parents come before their children, and object keys keep their order
+yields!(
+    paths_order,
+    r#"{"a": {"b": 1}, "c": [2]} | [[paths], [leaf_paths]]"#,
+    json!([[["a"], ["a", "b"], ["c"], ["c", 0]], [["a", "b"], ["c", 0]]])
+);
+yields!(
+    paths_filter,
+    r#"{c: [2, "x"], a: {b: 1}} | [paths(type == "number")]"#,
+    json!([["c", 0], ["a", "b"]])
+);
 
 const RECURSE_PATHS: &str = "def paths:
   { x: ., p: [] } |
@@ -68,6 +88,62 @@ yields!(
     json!([["a"], ["a", 0], ["a", 1], ["a", 1, 0], ["b"], ["b", "c"]])
 );
 
+#[test]
+fn tonumber() {
+    let f = r#"[.[] | try tonumber catch "err"]"#;
+    // surrounding whitespace is permitted like in jq
+    let ok = json!(["12", "1.5e3", " 4 ", 5, "-1.5"]);
+    give(ok, f, json!([12, 1500, 4, 5, -1.5]));
+    let err = json!(["12abc", "", "1 2", "[1]", "true", "\"1\"", null, [1]]);
+    give(
+        err,
+        f,
+        json!(["err", "err", "err", "err", "err", "err", "err", "err"]),
+    );
+}
+
+yields!(tostream_num, "1 | [tostream]", [[json!([]), json!(1)]]);
+yields!(tostream_obj, "{} | [tostream]", [[json!([]), json!({})]]);
+yields!(
+    tostream_nested,
+    r#"{a: [1, {b: []}], c: null} | [tostream]"#,
+    json!([
+        [["a", 0], 1],
+        [["a", 1, "b"], []],
+        [["a", 1, "b"]],
+        [["a", 1]],
+        [["c"], null],
+        [["c"]]
+    ])
+);
+
+#[test]
+fn fromstream_tostream() {
+    let f = "fromstream(tostream)";
+    give(json!(1), f, json!(1));
+    give(json!({}), f, json!({}));
+    let x = json!({"b": [1, [], [[2]], {"c": {}}], "a": null});
+    give(x.clone(), f, x);
+    give(
+        json!(null),
+        "[fromstream((1, [], {a: 2}) | tostream)]",
+        json!([1, [], {"a": 2}]),
+    );
+}
+
+yields!(
+    truncate_stream,
+    "[1 | truncate_stream([[0], 1], [[1, 0], 2], [[1, 0]], [[1]])]",
+    json!([[[0], 2], [[0]]])
+);
+
+#[test]
+fn truncate_fromstream() {
+    let x = json!({"a": [1, {"b": 2}], "c": 3, "d": {"e": []}});
+    let f = "[fromstream(truncate_stream(1; tostream))]";
+    give(x, f, json!([[1, {"b": 2}], {"e": []}]));
+}
+
 #[test]
 fn transpose() {
     let y = json!([[1, 2], [3, null]]);
@@ -5,6 +5,44 @@ pub mod common;
 use common::give;
 use serde_json::json;
 
+yields!(
+    contains_arr_str,
+    r#"["foobar", "barfoo"] | contains(["bar"])"#,
+    true
+);
+yields!(contains_arr_sub, "[1, 2, 3] | contains([1, 1, 2])", true);
+yields!(contains_arr_not, "[1, 2] | contains([3])", false);
+yields!(
+    contains_arr_obj,
+    r#"[{"a": [1, 2], "b": 3}] | contains([{"a": [1]}])"#,
+    true
+);
+yields!(contains_arr_kinds, r#"[1, "a"] | contains(["1"])"#, false);
+yields!(contains_str, r#""foobar" | contains("oba")"#, true);
+yields!(contains_str_not, r#""foobar" | contains("bao")"#, false);
+yields!(contains_str_empty, r#""foobar" | contains("")"#, true);
+yields!(contains_str_multibyte, r#""ǆ€x" | contains("€x")"#, true);
+yields!(
+    contains_str_kinds,
+    r#"[(1, ["o"]) as $x | "foobar" | contains($x)?]"#,
+    json!([])
+);
+yields!(contains_kinds, r#"[1 | contains("1")?]"#, json!([]));
+yields!(contains_bools, "[true | contains(false)?]", json!([]));
+yields!(
+    contains_kinds_long,
+    r#"[range(100)] | try contains("a") catch ."#,
+    "array ([0,1,2,3,4,5,6,7,8,9,10,11,12,...) and string (\"a\") cannot have their containment checked"
+);
+
+yields!(fromjson_try, r#"["not json" | fromjson?]"#, json!([]));
+yields!(fromjson_try_valid, r#""[1]" | fromjson?"#, [1]);
+yields!(
+    fromjson_try_path,
+    r#"[{a: "{"} | (.a | fromjson)?]"#,
+    json!([])
+);
+
 #[test]
 fn has() {
     /* TODO: reenable these tests
@@ -21,6 +59,12 @@ fn has() {
     give(json!([0, null]), "has(0)", json!(true));
     give(json!([0, null]), "has(1)", json!(true));
     give(json!([0, null]), "has(2)", json!(false));
+    // unlike `.[-1]`, negative indices are never contained
+    give(
+        json!([1, 2, 3]),
+        "[has(2), has(3), has(-1)]",
+        json!([true, false, false]),
+    );
 
     give(json!({"a": 1, "b": null}), r#"has("a")"#, json!(true));
     give(json!({"a": 1, "b": null}), r#"has("b")"#, json!(true));
@@ -62,6 +106,10 @@ fn keys_unsorted() {
 
 yields!(length_str_foo, r#""ƒoo" | length"#, 3);
 yields!(length_str_namaste, r#""नमस्ते" | length"#, 6);
+// strings count Unicode codepoints, not graphemes
+yields!(length_str_precomposed, r#""\u00e9" | length"#, 1);
+yields!(length_str_decomposed, r#""e\u0301" | length"#, 2);
+yields!(length_str_zwj, r#""👩\u200d💻" | length"#, 3);
 yields!(length_obj, r#"{"a": 5, "b": 3} | length"#, 2);
 yields!(length_int_pos, " 2 | length", 2);
 yields!(length_int_neg, "-2 | length", 2);
@@ -74,6 +122,102 @@ fn tojson() {
     give(json!(1.0), "tojson", json!("1.0"));
     give(json!(0), "1.0 | tojson", json!("1.0"));
     give(json!(0), "1.1 | tojson", json!("1.1"));
-    give(json!(0), "0.0 / 0.0 | tojson", json!("null"));
-    give(json!(0), "1.0 / 0.0 | tojson", json!("null"));
+    give(json!(0), "nan | tojson", json!("null"));
+    give(json!(0), "infinite | tojson", json!("null"));
+    // non-finite numbers nested in values are also printed as null
+    give(json!(0), "{x: infinite} | tojson", json!("{\"x\":null}"));
+    let f = r#""\({x: nan, y: [-infinite]})""#;
+    give(json!(0), f, json!("{\"x\":null,\"y\":[null]}"));
+    give(json!(0), r#"@json "\([infinite])""#, json!("[null]"));
+
+    // object keys are escaped like strings
+    give(json!({"\u{1}\n": 0}), "tojson", json!("{\"\\u0001\\n\":0}"));
+}
+
+// non-finite numbers are printed as null, but they stay numbers otherwise
+#[test]
+fn non_finite() {
+    give(json!(0), "infinite | tostring", json!("null"));
+    give(json!(0), "-infinite | @text", json!("null"));
+    give(
+        json!(0),
+        "[nan, infinite, -infinite] | tojson",
+        json!("[null,null,null]"),
+    );
+    give(json!(0), "\"\\(infinite)\"", json!("null"));
+    give(
+        json!(0),
+        "[nan, infinite] | map(type)",
+        json!(["number", "number"]),
+    );
+    give(
+        json!(0),
+        "infinite > 1e300 and -infinite < -1e300",
+        json!(true),
+    );
+}
+
+#[test]
+fn tojson_deep() {
+    use jaq_json::Val;
+    let depth = 10_000;
+    let mut v = (0..depth).fold(Val::Int(0), |v, _| Val::from_iter([v]));
+    let s = v.to_string();
+    assert_eq!(s.len(), 2 * depth + 1);
+    assert_eq!(&s[depth - 1..depth + 2], "[0]");
+
+    // dropping the value would recurse as deeply as it is nested,
+    // so we take it apart iteratively
+    while let Val::Arr(a) = v {
+        v = std::rc::Rc::try_unwrap(a).unwrap().pop().unwrap();
+    }
+}
+
+#[test]
+fn parse_deep() {
+    use hifijson::token::Lex;
+    use jaq_json::Val;
+    let depth = 10_000;
+    let s = "[".repeat(depth) + "{\"a\": 0}" + &"]".repeat(depth);
+    let mut lexer = hifijson::SliceLexer::new(s.as_bytes());
+    let mut v = lexer.exactly_one(Val::parse).unwrap();
+
+    let mut n = 0;
+    while let Val::Arr(a) = v {
+        v = std::rc::Rc::try_unwrap(a).unwrap().pop().unwrap();
+        n += 1;
+    }
+    assert_eq!(n, depth);
+    assert_eq!(
+        v,
+        Val::obj(
+            [(std::rc::Rc::new("a".into()), Val::Int(0))]
+                .into_iter()
+                .collect()
+        )
+    );
+}
+
+#[test]
+fn parse_bounded() {
+    use hifijson::token::Lex;
+    use jaq_json::Val;
+    let parse = |depth, s: &str| {
+        let mut lexer = hifijson::SliceLexer::new(s.as_bytes());
+        lexer.exactly_one(|token, lexer| Val::parse_bounded(depth, token, lexer))
+    };
+    assert!(matches!(
+        parse(2, "[{\"a\": []}]"),
+        Err(hifijson::Error::Depth)
+    ));
+    assert_eq!(
+        parse(3, "[{\"a\": []}]").unwrap().to_string(),
+        "[{\"a\":[]}]"
+    );
+    assert_eq!(parse(0, "1").unwrap(), Val::Int(1));
+    assert!(parse(0, "[]").is_err());
+    assert!(parse(9, "[1,]").is_err());
+    assert!(parse(9, "{\"a\": 1,}").is_err());
+    assert!(parse(9, "[1}").is_err());
+    assert!(parse(9, "{\"a\" 1}").is_err());
 }
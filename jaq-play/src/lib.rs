@@ -382,7 +382,11 @@ fn report_parse(code: &str, (expected, found): load::parse::Error<&str>) -> Repo
 
 fn report_compile(code: &str, (found, undefined): compile::Error<&str>) -> Report {
     let found_range = load::span(code, found);
-    let message = format!("undefined {}", undefined.as_str());
+    let message = match undefined {
+        compile::Undefined::Path => format!("expected {}", undefined.as_str()),
+        compile::Undefined::Duplicate(_) => format!("duplicate {}", undefined.as_str()),
+        _ => format!("undefined {}", undefined.as_str()),
+    };
     let found = [(message.clone(), None)].into();
 
     Report {
@@ -4,6 +4,12 @@
 //! These filters are either implemented as definitions or as functions.
 //! For example, the standard library provides the `map(f)` filter,
 //! which is defined using the more elementary filter `[.[] | f]`.
+//!
+//! The filter `env` yields the environment set via [`jaq_core::Ctx::with_env`].
+//! This is either a fixed environment, such as a snapshot taken at startup,
+//! or the current environment, read whenever `env` is called.
+//! If no environment is set, `env` yields a snapshot of the process environment
+//! taken when `env` is first called.
 #![no_std]
 #![forbid(unsafe_code)]
 #![warn(missing_docs)]
@@ -22,7 +28,8 @@ mod time;
 use alloc::string::{String, ToString};
 use alloc::{borrow::ToOwned, boxed::Box, vec::Vec};
 use jaq_core::results::{run_if_ok, then};
-use jaq_core::{load, Bind, Cv, Error, Exn, FilterT, Native, RunPtr, UpdatePtr, ValR, ValX, ValXs};
+use jaq_core::{load, Bind, Cv, Error, Exn, FilterT, Native, PathsPtr, RunPtr, UpdatePtr};
+use jaq_core::{ValR, ValX, ValXs};
 
 /// Definitions of the standard library.
 pub fn defs() -> impl Iterator<Item = load::parse::Def<&'static str>> {
@@ -59,7 +66,8 @@ pub fn funs<V: ValT>() -> impl Iterator<Item = Filter<Native<V>>> {
 /// Does not return filters from the standard library, such as `map`.
 pub fn base_funs<V: ValT>() -> impl Iterator<Item = Filter<Native<V>>> {
     let base_run = base_run().into_vec().into_iter().map(run);
-    base_run.chain([upd(error())])
+    let base_paths = base_paths().into_vec().into_iter().map(paths);
+    base_run.chain(base_paths).chain(error().map(upd))
 }
 
 /// Supplementary set of filters that are generic over the value type.
@@ -155,9 +163,19 @@ pub fn run<V>((name, arity, run): Filter<RunPtr<V>>) -> Filter<Native<V>> {
     (name, arity, Native::new(run))
 }
 
-/// Convert a filter with a run and an update pointer to a native filter.
-fn upd<V>((name, arity, (run, update)): Filter<(RunPtr<V>, UpdatePtr<V>)>) -> Filter<Native<V>> {
-    (name, arity, Native::new(run).with_update(update))
+type RunPaths<V> = (RunPtr<V>, PathsPtr<V>);
+
+/// Convert a filter with a run and a paths pointer to a native filter.
+fn paths<V>((name, arity, (run, paths)): Filter<RunPaths<V>>) -> Filter<Native<V>> {
+    (name, arity, Native::new(run).with_paths(paths))
+}
+
+type RunUpdatePaths<V> = (RunPtr<V>, UpdatePtr<V>, PathsPtr<V>);
+
+/// Convert a filter with a run, an update, and a paths pointer to a native filter.
+fn upd<V>((name, arity, (run, update, paths)): Filter<RunUpdatePaths<V>>) -> Filter<Native<V>> {
+    let native = Native::new(run).with_update(update);
+    (name, arity, native.with_paths(paths))
 }
 
 /// Sort array by the given function.
@@ -296,7 +314,6 @@ pub fn v(n: usize) -> Box<[Bind]> {
 #[allow(clippy::unit_arg)]
 fn base_run<V: ValT, F: FilterT<V = V>>() -> Box<[Filter<RunPtr<V, F>>]> {
     let f = || [Bind::Fun(())].into();
-    let vf = [Bind::Var(()), Bind::Fun(())].into();
     Box::new([
         ("inputs", v(0), |_, cv| {
             Box::new(
@@ -304,6 +321,8 @@ fn base_run<V: ValT, F: FilterT<V = V>>() -> Box<[Filter<RunPtr<V, F>>]> {
                     .map(|r| r.map_err(|e| Exn::from(Error::str(e)))),
             )
         }),
+        ("nan", v(0), |_, _| ow!(Ok(V::from(f64::NAN)))),
+        ("infinite", v(0), |_, _| ow!(Ok(V::from(f64::INFINITY)))),
         ("floor", v(0), |_, cv| ow!(cv.1.round(f64::floor))),
         ("round", v(0), |_, cv| ow!(cv.1.round(f64::round))),
         ("ceil", v(0), |_, cv| ow!(cv.1.round(f64::ceil))),
@@ -346,17 +365,10 @@ fn base_run<V: ValT, F: FilterT<V = V>>() -> Box<[Filter<RunPtr<V, F>>]> {
             let f = move |a| cmp_by(a, |v| f.run(lut, (fc.clone(), v)), |my, y| y >= my);
             once_or_empty(|| cv.1.into_vec().map_err(Exn::from).and_then(f).transpose())
         }),
-        ("first", f(), |lut, mut cv| {
-            let (f, fc) = cv.0.pop_fun();
-            Box::new(f.run(lut, (fc, cv.1)).take(1))
-        }),
-        ("limit", vf, |lut, mut cv| {
+        ("path", f(), |lut, mut cv| {
             let (f, fc) = cv.0.pop_fun();
-            let n = cv.0.pop_var();
-            let pos = |n: isize| n.try_into().unwrap_or(0usize);
-            then(n.try_as_isize().map_err(Exn::from), |n| {
-                Box::new(f.run(lut, (fc, cv.1)).take(pos(n)))
-            })
+            let paths = f.paths(lut, (fc, (cv.1, Vec::new())));
+            Box::new(paths.map(|y| y.map(|(_, path)| path.into_iter().collect())))
         }),
         ("range", v(3), |_, mut cv| {
             let by = cv.0.pop_var();
@@ -397,6 +409,47 @@ fn base_run<V: ValT, F: FilterT<V = V>>() -> Box<[Filter<RunPtr<V, F>>]> {
     ])
 }
 
+fn base_paths<V: ValT>() -> Box<[Filter<RunPaths<V>>]> {
+    let f = || [Bind::Fun(())].into();
+    let vf = || [Bind::Var(()), Bind::Fun(())].into();
+    Box::new([
+        (
+            "first",
+            f(),
+            (
+                |lut, mut cv| {
+                    let (f, fc) = cv.0.pop_fun();
+                    Box::new(f.run(lut, (fc, cv.1)).take(1))
+                },
+                |lut, mut cv| {
+                    let (f, fc) = cv.0.pop_fun();
+                    Box::new(f.paths(lut, (fc, cv.1)).take(1))
+                },
+            ),
+        ),
+        (
+            "limit",
+            vf(),
+            (
+                |lut, mut cv| {
+                    let (f, fc) = cv.0.pop_fun();
+                    let n = cv.0.pop_var();
+                    then(n.try_as_isize().map_err(Exn::from), |n| {
+                        Box::new(f.run(lut, (fc, cv.1)).take(n.try_into().unwrap_or(0)))
+                    })
+                },
+                |lut, mut cv| {
+                    let (f, fc) = cv.0.pop_fun();
+                    let n = cv.0.pop_var();
+                    then(n.try_as_isize().map_err(Exn::from), |n| {
+                        Box::new(f.paths(lut, (fc, cv.1)).take(n.try_into().unwrap_or(0)))
+                    })
+                },
+            ),
+        ),
+    ])
+}
+
 #[cfg(feature = "std")]
 fn now<V: From<String>>() -> Result<f64, Error<V>> {
     use std::time::{SystemTime, UNIX_EPOCH};
@@ -406,24 +459,29 @@ fn now<V: From<String>>() -> Result<f64, Error<V>> {
         .map_err(Error::str)
 }
 
+/// Return a snapshot of the process environment taken when this is first called.
+#[cfg(feature = "std")]
+fn env_snapshot<V: ValT>() -> ValR<V> {
+    use std::sync::Mutex;
+    static SNAPSHOT: Mutex<Option<Vec<(String, String)>>> = Mutex::new(None);
+    let mut snapshot = SNAPSHOT.lock().unwrap();
+    let vars = snapshot.get_or_insert_with(|| std::env::vars().collect());
+    let vars = vars.iter().cloned();
+    V::from_map(vars.map(|(k, v)| (V::from(k), V::from(v))))
+}
+
 #[cfg(feature = "std")]
 fn std<V: ValT>() -> Box<[Filter<RunPtr<V>>]> {
-    use std::env::vars;
     Box::new([
-        ("env", v(0), |_, _| {
-            ow!(V::from_map(vars().map(|(k, v)| (V::from(k), V::from(v)))))
+        ("env", v(0), |_, cv| {
+            ow!(cv.0.env().map_or_else(env_snapshot, |env| Ok(env.get())))
         }),
         ("now", v(0), |_, _| ow!(now().map(V::from))),
-        ("halt", v(0), |_, _| once_with(|| std::process::exit(0))),
+        ("halt", v(0), |_, _| ow!(Err(Error::halt(0, None)))),
         ("halt_error", v(1), |_, mut cv| {
             once_with(move || {
                 let exit_code = cv.0.pop_var().try_as_isize()?;
-                if let Some(s) = cv.1.as_str() {
-                    std::print!("{}", s);
-                } else {
-                    std::println!("{}", cv.1);
-                }
-                std::process::exit(exit_code as i32)
+                Err(Exn::from(Error::halt(exit_code, Some(cv.1))))
             })
         }),
     ])
@@ -435,6 +493,86 @@ fn replace(s: &str, patterns: &[&str], replacements: &[&str]) -> String {
     ac.replace_all(s, replacements)
 }
 
+/// Decoder of URL-safe base64, ignoring padding.
+#[cfg(feature = "format")]
+const BASE64URL: base64::engine::GeneralPurpose = {
+    use base64::engine::{DecodePaddingMode, GeneralPurpose, GeneralPurposeConfig};
+    let config = GeneralPurposeConfig::new()
+        .with_encode_padding(false)
+        .with_decode_padding_mode(DecodePaddingMode::Indifferent);
+    GeneralPurpose::new(&base64::alphabet::URL_SAFE, config)
+};
+
+/// Alphabet of the standard base64 encoding defined in RFC 4648.
+#[cfg(feature = "format")]
+const BASE64_STD: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Alphabet of the URL-safe base64 encoding defined in RFC 4648.
+#[cfg(feature = "format")]
+const BASE64_URL: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+/// Encode base64, writing every chunk directly into the output string.
+///
+/// This allocates the output only once, which matters for large inputs.
+#[cfg(feature = "format")]
+fn encode_base64(bytes: &[u8], alphabet: &[u8; 64], pad: bool) -> String {
+    let mut out = String::with_capacity((bytes.len() + 2) / 3 * 4);
+    for chunk in bytes.chunks(3) {
+        let mut buf = [0; 3];
+        buf[..chunk.len()].copy_from_slice(chunk);
+        let n = buf.iter().fold(0u32, |acc, b| acc << 8 | u32::from(*b));
+        // number of characters that encode the bits of the chunk, rest is padding
+        let chars = (chunk.len() * 8 + 5) / 6;
+        for i in 0..4 {
+            if i < chars {
+                out.push(alphabet[(n >> (18 - 6 * i)) as usize & 63].into());
+            } else if pad {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+/// Alphabet of the base32 encoding defined in RFC 4648.
+#[cfg(feature = "format")]
+const BASE32: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+#[cfg(feature = "format")]
+fn encode_base32(bytes: &[u8]) -> String {
+    let mut out = String::new();
+    for chunk in bytes.chunks(5) {
+        let mut buf = [0; 5];
+        buf[..chunk.len()].copy_from_slice(chunk);
+        let n = buf.iter().fold(0u64, |acc, b| acc << 8 | u64::from(*b));
+        // number of characters that encode the bits of the chunk, rest is padding
+        let chars = (chunk.len() * 8 + 4) / 5;
+        for i in 0..8 {
+            let c = BASE32[(n >> (35 - 5 * i)) as usize & 31];
+            out.push(if i < chars { c.into() } else { '=' });
+        }
+    }
+    out
+}
+
+/// Decode base32, ignoring padding, or return the first invalid character.
+#[cfg(feature = "format")]
+fn decode_base32(s: &str) -> Result<Vec<u8>, char> {
+    let mut out = Vec::new();
+    let (mut n, mut bits) = (0u32, 0);
+    for c in s.trim_end_matches('=').chars() {
+        let digit = BASE32.iter().position(|b| char::from(*b) == c).ok_or(c)?;
+        n = n << 5 | digit as u32;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((n >> bits) as u8);
+            n &= (1 << bits) - 1;
+        }
+    }
+    Ok(out)
+}
+
 #[cfg(feature = "format")]
 fn format<V: ValT>() -> Box<[Filter<RunPtr<V>>]> {
     Box::new([
@@ -453,8 +591,8 @@ fn format<V: ValT>() -> Box<[Filter<RunPtr<V>>]> {
             ow!(Ok(encode(cv.1.try_as_str()?).into_owned().into()))
         }),
         ("encode_base64", v(0), |_, cv| {
-            use base64::{engine::general_purpose::STANDARD, Engine};
-            ow!(Ok(STANDARD.encode(cv.1.try_as_str()?).into()))
+            let encode = |s: &str| encode_base64(s.as_bytes(), BASE64_STD, true);
+            ow!(Ok(encode(cv.1.try_as_str()?).into()))
         }),
         ("decode_base64", v(0), |_, cv| {
             use base64::{engine::general_purpose::STANDARD, Engine};
@@ -464,6 +602,29 @@ fn format<V: ValT>() -> Box<[Filter<RunPtr<V>>]> {
                 Ok(from_utf8(&d).map_err(Error::str)?.to_owned().into())
             })
         }),
+        ("encode_base64url", v(0), |_, cv| {
+            let encode = |s: &str| encode_base64(s.as_bytes(), BASE64_URL, false);
+            ow!(Ok(encode(cv.1.try_as_str()?).into()))
+        }),
+        ("decode_base64url", v(0), |_, cv| {
+            use base64::Engine;
+            use core::str::from_utf8;
+            ow!({
+                let d = BASE64URL.decode(cv.1.try_as_str()?).map_err(Error::str)?;
+                Ok(from_utf8(&d).map_err(Error::str)?.to_owned().into())
+            })
+        }),
+        ("encode_base32", v(0), |_, cv| {
+            ow!(Ok(encode_base32(cv.1.try_as_str()?.as_bytes()).into()))
+        }),
+        ("decode_base32", v(0), |_, cv| {
+            use core::str::from_utf8;
+            ow!({
+                let invalid = |c| Error::str(format_args!("invalid base32 character: {c}"));
+                let d = decode_base32(cv.1.try_as_str()?).map_err(invalid)?;
+                Ok(from_utf8(&d).map_err(Error::str)?.to_owned().into())
+            })
+        }),
     ])
 }
 
@@ -573,15 +734,27 @@ fn time<V: ValT>() -> Box<[Filter<RunPtr<V>>]> {
     ])
 }
 
-fn error<V: Clone, F>() -> Filter<(RunPtr<V, F>, UpdatePtr<V, F>)> {
-    (
-        "error",
-        v(1),
+fn error<V: Clone>() -> [Filter<RunUpdatePaths<V>>; 2] {
+    [
         (
-            |_, mut cv| ow!(Err(Error::new(cv.0.pop_var()))),
-            |_, mut cv, _| ow!(Err(Error::new(cv.0.pop_var()))),
+            "error",
+            v(0),
+            (
+                |_, cv| ow!(Err(Error::new(cv.1))),
+                |_, cv, _| ow!(Err(Error::new(cv.1))),
+                |_, (_, (v, _))| ow!(Err(Error::new(v))),
+            ),
         ),
-    )
+        (
+            "error",
+            v(1),
+            (
+                |_, mut cv| ow!(Err(Error::new(cv.0.pop_var()))),
+                |_, mut cv, _| ow!(Err(Error::new(cv.0.pop_var()))),
+                |_, mut cv| ow!(Err(Error::new(cv.0.pop_var()))),
+            ),
+        ),
+    ]
 }
 
 #[cfg(feature = "log")]
@@ -591,13 +764,14 @@ fn with_debug<T: core::fmt::Display>(x: T) -> T {
 }
 
 #[cfg(feature = "log")]
-fn debug<V: core::fmt::Display>() -> Filter<(RunPtr<V>, UpdatePtr<V>)> {
+fn debug<V: core::fmt::Display>() -> Filter<RunUpdatePaths<V>> {
     (
         "debug",
         v(0),
         (
             |_, cv| ow!(Ok(with_debug(cv.1))),
             |_, cv, f| f(with_debug(cv.1)),
+            |_, (_, (v, path))| ow!(Ok((with_debug(v), path))),
         ),
     )
 }
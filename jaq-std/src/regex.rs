@@ -145,9 +145,21 @@ pub fn regex<'a>(s: &'a str, re: &'a Regex, flags: Flags, sm: (bool, bool)) -> V
     let mut bc = ByteChar::new(s);
     let mut out = Vec::new();
 
-    for c in re.captures_iter(s) {
+    // byte offset from which we search for the next match
+    let mut start = 0;
+    while let Some(c) = re.captures_at(s, start).filter(|_| start < s.len()) {
         let whole = c.get(0).unwrap();
-        if whole.start() >= s.len() || (flags.ignore_empty() && whole.as_str().is_empty()) {
+        // like jq, after an empty match, we search again from the next character,
+        // whereas after a non-empty match, we search again from its end
+        // (unlike `captures_iter`, this allows an empty match right after a non-empty one)
+        start = match s[whole.end()..].chars().next() {
+            Some(c) if whole.is_empty() => whole.end() + c.len_utf8(),
+            _ => whole.end(),
+        };
+        if whole.start() >= s.len() {
+            break;
+        }
+        if flags.ignore_empty() && whole.is_empty() {
             continue;
         }
         let match_names = c.iter().zip(re.capture_names());
@@ -1,18 +1,31 @@
 use jaq_json::{Error, Val, ValR};
 use serde_json::Value;
 
-fn yields(x: Val, code: &str, ys: impl Iterator<Item = ValR>) {
+type Filter = jaq_core::Filter<jaq_core::Native<Val>>;
+
+/// Compile a filter with the standard library,
+/// yielding the names of the erroneous symbols on failure.
+pub fn try_compile(code: &str) -> Result<Filter, Vec<String>> {
     use jaq_core::load::{Arena, File, Loader};
 
     let arena = Arena::default();
     let loader = Loader::new(jaq_std::defs());
     let path = "".into();
     let modules = loader.load(&arena, File { path, code }).unwrap();
-    let filter = jaq_core::Compiler::default()
-        .with_funs(jaq_std::funs())
-        .compile(modules)
-        .unwrap();
-    filter.yields(x, ys)
+    let compiler = jaq_core::Compiler::default().with_funs(jaq_std::funs());
+    compiler.compile(modules).map_err(|errs| {
+        let errs = errs.into_iter().flat_map(|(_file, errs)| errs);
+        errs.map(|(name, _)| name.to_string()).collect()
+    })
+}
+
+/// Compile a filter with the standard library.
+pub fn compile(code: &str) -> Filter {
+    try_compile(code).unwrap()
+}
+
+fn yields(x: Val, code: &str, ys: impl Iterator<Item = ValR>) {
+    compile(code).yields(x, ys)
 }
 
 pub fn fail(x: Value, f: &str, err: Error) {
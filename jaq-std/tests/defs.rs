@@ -2,7 +2,8 @@
 
 pub mod common;
 
-use common::{give, gives};
+use common::{fail, give, gives};
+use jaq_json::Error;
 use serde_json::json;
 
 #[test]
@@ -25,6 +26,35 @@ fn any() {
     give(json!({"a": false, "b": true}), "any", json!(true));
 }
 
+yields!(
+    del_seq,
+    "{a: 1, b: 2, c: 3} | del(.a) | del(.b)",
+    json!({"c": 3})
+);
+yields!(del_absent_key, "{a: 1} | del(.b)", json!({"a": 1}));
+yields!(del_absent_index, "[1, 2] | del(.[5], .[-5])", [1, 2]);
+yields!(
+    update_absent_index_error,
+    r#"[1] | try (.[5] |= error("x")) catch ."#,
+    "x"
+);
+yields!(
+    del_select,
+    "[{i: 1, dead: false}, {i: 2, dead: true}, {i: 3, dead: true}, {i: 4}] | del(.[] | select(.dead))",
+    json!([{"i": 1, "dead": false}, {"i": 4}])
+);
+
+// `f // empty` drops the outputs of `f` that are null or false
+#[test]
+fn empty_alt() {
+    gives(json!(null), "null // empty", []);
+    gives(json!(null), "false // empty", []);
+    gives(json!({}), ".x // empty", []);
+    gives(json!({"x": 5}), ".x // empty", [json!(5)]);
+    give(json!(null), "5 // empty", json!(5));
+    gives(json!(null), "(1, null, 2) // empty", [json!(1), json!(2)]);
+}
+
 // aliases for fromdateiso8601 and todateiso8601
 yields!(fromdate, r#""1970-01-02T00:00:00Z" | fromdate"#, 86400);
 yields!(
@@ -86,6 +116,94 @@ yields!(flatten_obj, "{a: 1} | flatten", json!([{"a": 1}]));
 // jq gives an error here
 yields!(flatten_num, "0 | flatten", [0]);
 
+#[test]
+fn getpath() {
+    let x = json!({"a": [1, {"b": 2}]});
+    give(x.clone(), r#"getpath(["a", 1, "b"])"#, json!(2));
+    give(x.clone(), r#"getpath(["a", -1])"#, json!({"b": 2}));
+    give(x.clone(), "getpath([])", x);
+}
+
+#[test]
+fn getpath_missing() {
+    let f = r#"getpath(["x", "y"]) // 0"#;
+    give(json!({}), f, json!(0));
+    give(json!({"x": {}}), f, json!(0));
+    give(json!({"x": {"y": 1}}), f, json!(1));
+    let f = r#"getpath(["x", "y", "z"])"#;
+    give(json!({"x": null}), f, json!(null));
+}
+
+yields!(
+    getpath_many,
+    r#"{"a": 1, "b": 2} | [getpath(["a"], ["b"])]"#,
+    [1, 2]
+);
+
+// `getpath` is a path expression, even when the path does not exist
+#[test]
+fn getpath_path() {
+    let f = r#"path(getpath(["a", "b"]))"#;
+    give(json!({}), f, json!(["a", "b"]));
+    give(json!(null), f, json!(["a", "b"]));
+    give(json!({"a": {"b": 1}}), f, json!(["a", "b"]));
+    give(json!(null), "path(getpath([]))", json!([]));
+}
+
+#[test]
+fn getpath_mismatch() {
+    let err = Error::str(r#"cannot index array ([1]) with string ("a")"#);
+    fail(json!([1]), r#"getpath(["a"])"#, err);
+    let err = Error::str(r#"cannot index object ({"a":1}) with number (0)"#);
+    fail(json!({"a": 1}), "getpath([0])", err);
+    let err = Error::str(r#"cannot index array ([1]) with string ("b")"#);
+    fail(json!({"a": [1]}), r#"getpath(["a", "b"])"#, err);
+}
+
+#[test]
+fn setpath() {
+    let x = json!({"a": [1, {"b": 2}]});
+    let y = json!({"a": [1, {"b": 3}]});
+    give(x.clone(), r#"setpath(["a", 1, "b"]; 3)"#, y);
+    let y = json!({"a": [1, {"b": 2, "c": 3}]});
+    give(x.clone(), r#"setpath(["a", -1, "c"]; 3)"#, y);
+    give(x, "setpath([]; 0)", json!(0));
+}
+
+// like in jq, setting a missing path creates it
+#[test]
+fn setpath_missing() {
+    give(json!(null), r#"setpath(["a"]; 1)"#, json!({"a": 1}));
+    give(
+        json!({}),
+        r#"setpath(["a", "b"]; 1)"#,
+        json!({"a": {"b": 1}}),
+    );
+    let x = json!({"a": {"c": 0}});
+    give(
+        x,
+        r#"setpath(["a", "b"]; 1)"#,
+        json!({"a": {"c": 0, "b": 1}}),
+    );
+    give(json!(null), "del(.a)", json!(null));
+    give(json!(null), "del(.[0])", json!(null));
+}
+
+#[test]
+fn setpath_str() {
+    let err = Error::str(r#"cannot index string ("abc") with number (0)"#);
+    fail(json!("abc"), r#"setpath([0]; "x")"#, err);
+    let err = Error::str(r#"cannot index string ("abc") with string ("b")"#);
+    fail(json!({"a": "abc"}), r#"setpath(["a", "b"]; "x")"#, err);
+}
+
+yields!(input_eof, "try input catch .", "No more inputs");
+yields!(
+    input_loop,
+    "[def loop: (input | ., loop)? // empty; loop]",
+    json!([])
+);
+
 yields!(isfinite_true, "all((0, 1, nan); isfinite)", true);
 yields!(
     isfinite_false,
@@ -173,8 +291,26 @@ fn nth() {
     give(json!(10), &format!("nth(.; {})", fib), json!(55));
 }
 
+yields!(not_null, "null | not", true);
+yields!(not_false, "false | not", true);
+yields!(not_zero, "0 | not", false);
+yields!(not_truthy, r#"[true, "", [] | not]"#, [false, false, false]);
+yields!(not_path, r#"[{a: 1}, {} | .a | not]"#, [false, true]);
+yields!(
+    range_break,
+    "[label $out | range(10) | if . == 3 then break $out else . end]",
+    [0, 1, 2]
+);
 yields!(range_many, "[range(-1, 1; 0, 2)]", json!([-1, -1, 0, 1, 1]));
 
+// these would not terminate if `range` built its outputs eagerly
+yields!(range_lazy_first, "first(range(1e12))", 0);
+yields!(
+    range_lazy_select,
+    "[limit(3; range(1e12) | select(. % 7 == 0))]",
+    [0, 7, 14]
+);
+
 #[test]
 fn range_reverse() {
     give(json!(null), "[range(1, 2)]", json!([0, 0, 1]));
@@ -195,6 +331,13 @@ yields!(limit_inf_sumr, "[limit(3; 0 + recurse(.+1))]", [0, 1, 2]);
 
 yields!(limit_inf_path, "[limit(2; [1] | .[repeat(0)])]", [1, 1]);
 
+yields!(
+    recurse_key,
+    r#"{a: {a: 1, b: 2}} | [[..a?], [.. | .a?], [..a?.a?]]"#,
+    json!([[{"a": 1, "b": 2}, 1], [{"a": 1, "b": 2}, 1], [1]])
+);
+yields!(recurse_pipe, "[[1, [2]] | .. | numbers]", [1, 2]);
+
 #[test]
 fn recurse() {
     let x = json!({"a":0,"b":[1]});
@@ -211,15 +354,15 @@ fn recurse() {
     let y = json!([[[1], 2], 3, [4, [5]]]);
     give(x.clone(), "(.. | scalars) |= .+1", y);
 
+    // like in jq, the paths are determined on the original input,
+    // so the added values are not updated
     let f = ".. |= if . < [] then .+1 else . + [42] end";
-    let y = json!([[[1, 43], 2, 43], 3, [4, [5, 43], 43], 43]);
-    // jq gives: `[[[1, 42], 2, 42], 3, [4, [5, 42], 42], 42]`
+    let y = json!([[[1, 42], 2, 42], 3, [4, [5, 42], 42], 42]);
     give(x.clone(), f, y);
 
     let f = ".. |= if . < [] then .+1 else [42] + . end";
-    let y = json!([43, [43, [43, 1], 2], 3, [43, 4, [43, 5]]]);
-    // jq fails here with: "Cannot index number with number"
-    give(x.clone(), f, y);
+    let err = Error::str("cannot index number (43) with number (0)");
+    fail(x.clone(), f, err);
 }
 
 yields!(
@@ -227,11 +370,22 @@ yields!(
     "[1 | recurse(if . < 3 then .+1 else empty end)]",
     [1, 2, 3]
 );
+// this runs in constant memory, because `range` does not buffer its outputs
+yields!(
+    reduce_range,
+    "reduce range(1000000) as $x (0; . + $x)",
+    499999500000_i64
+);
 yields!(
     reduce_recurse,
     "reduce recurse(if . == 1000 then empty else .+1 end) as $x (0; . + $x)",
     500500
 );
+yields!(
+    recurse_deep,
+    "reduce range(100000) as $x (0; [.]) | [.. | numbers]",
+    [0]
+);
 
 const RECURSE_FLATTEN: &str = "def flatten($d):
   [ { d: $d, x: . } |
@@ -315,6 +469,17 @@ yields!(
     [-1.929, -1.1, -1.0, -1.0, 0.0, 1.31072, 1.0, 1.0, 1.1, 1.929]
 );
 
+yields!(
+    split_multibyte,
+    r#""a→b→→c" | split("→")"#,
+    ["a", "b", "", "c"]
+);
+yields!(split_ends, r#"",a,b," | split(",")"#, ["", "a", "b", ""]);
+yields!(split_sep_only, r#""," | split(",")"#, ["", ""]);
+// like in jq, splitting the empty string yields no strings at all
+yields!(split_empty, r#""" | split(",")"#, json!([]));
+yields!(split_chars, r#""aő→" | split("")"#, ["a", "ő", "→"]);
+
 yields!(tostring_str, r#""\n" | tostring"#, "\n");
 yields!(tostring_arr_str, r#"["\n"] | tostring"#, "[\"\\n\"]");
 
@@ -333,12 +498,34 @@ yields!(sub, r#""XYxyXYxy" | sub("x";"Q")"#, "XYQyXYxy");
 yields!(gsub, r#""XYxyXYxy" | gsub("x";"Q")"#, "XYQyXYQy");
 yields!(isub, r#""XYxyXYxy" | sub("x";"Q";"i")"#, "QYxyXYxy");
 yields!(gisub, r#""XYxyXYxy" | gsub("x";"Q";"i")"#, "QYQyQYQy");
+// text around matches is kept verbatim, also if it contains multi-byte characters
+yields!(sub_multibyte, r#""€a€b€" | sub("a"; "ä")"#, "€ä€b€");
+yields!(
+    gsub_multibyte,
+    r#""日本a語b" | gsub("[ab]"; "€")"#,
+    "日本€語€"
+);
+yields!(
+    gsub_multibyte_capture,
+    r#""äöü-x-éè" | gsub("-(?<c>.)-"; "<\(.c)>")"#,
+    "äöü<x>éè"
+);
 // swap adjacent occurrences of upper- and lower-case characters
 yields!(
     gsub_swap,
     r#""XYxyXYxy" | gsub("(?<upper>[A-Z])(?<lower>[a-z])"; .lower + .upper)"#,
     "XxYyXxYy"
 );
+// after a match of the empty string, matching continues with the next character
+yields!(gsub_empty, r#""abc" | gsub("x*"; "-")"#, "-a-b-c");
+yields!(gsub_empty_re, r#""aé" | gsub(""; "-")"#, "-a-é");
+yields!(gsub_empty_mixed, r#""baab" | gsub("a*"; "-")"#, "-b--b");
+yields!(sub_empty, r#""abc" | sub("x*"; "-")"#, "-abc");
+yields!(
+    match_empty_offsets,
+    r#""aé" | [match("x*"; "g") | .offset]"#,
+    [0, 1]
+);
 // this diverges from jq, which yields ["XxYy", "!XxYy", "Xx!Yy", "!Xx!Yy"]
 yields!(
     gsub_many,
@@ -356,12 +543,97 @@ yields!(
     r#"[0, 0 == 0, {}.a, "hello \"quotes\" and, commas"] | @csv"#,
     r#"0,true,,"hello ""quotes"" and, commas""#
 );
+yields!(
+    format_jaq_csv_quoted,
+    r#"[0, 0 == 0, {}.a, "hello \"quotes\" and, commas"] | @jaq_csv_quoted"#,
+    r#""0","true","","hello ""quotes"" and, commas""#
+);
 yields!(
     format_tsv,
     r#"[0, 0 == 0, {}.a, "hello \"quotes\" and \n\r\t\\ escapes"] | @tsv"#,
     "0\ttrue\t\thello \"quotes\" and \\n\\r\\t\\\\ escapes"
 );
 
+// only the five characters `<>&'"` are escaped, like in jq;
+// this is not sufficient for unquoted attribute values, where e.g. `=` and `` ` `` matter
+yields!(
+    format_html,
+    r#""<a href=\"x\" title='y'>`=&</a>" | @html"#,
+    "&lt;a href=&quot;x&quot; title=&apos;y&apos;&gt;`=&amp;&lt;/a&gt;"
+);
+yields!(
+    format_html_str,
+    r#"[1, "<"] | @html "<b>\(.)</b>""#,
+    "<b>[1,&quot;&lt;&quot;]</b>"
+);
+
+yields!(format_base64, r#""hello" | @base64"#, "aGVsbG8=");
+yields!(
+    format_base64_str,
+    r#""hello" | @base64 "<\(.)>, \(1)""#,
+    "<aGVsbG8=>, MQ=="
+);
+yields!(
+    format_base64d,
+    r#"{x: "aGk="} | .x | @base64d | . + "!""#,
+    "hi!"
+);
+yields!(
+    format_base64_roundtrip,
+    r#""hello" | @base64 | @base64d"#,
+    "hello"
+);
+
+yields!(
+    format_base64_long,
+    r#"([limit(1000; repeat("abcd"))] | add) as $s | [$s, $s[1:]] | map(@base64 | @base64d) == [$s, $s[1:]]"#,
+    true
+);
+
+// unlike `@base64`, `@base64url` uses `-` and `_` instead of `+` and `/` and omits padding
+yields!(
+    format_base64url,
+    r#"["", "f", "fo", "foo", "foob", "?>>"] | [map(@base64), map(@base64url)]"#,
+    json!([
+        ["", "Zg==", "Zm8=", "Zm9v", "Zm9vYg==", "Pz4+"],
+        ["", "Zg", "Zm8", "Zm9v", "Zm9vYg", "Pz4-"]
+    ])
+);
+yields!(
+    format_base64url_roundtrip,
+    r#"["", "f", "fo", "foo", "foob", "?>>"] | map(@base64url | @base64urld)"#,
+    ["", "f", "fo", "foo", "foob", "?>>"]
+);
+// padding is accepted when decoding
+yields!(format_base64urld_pad, r#""Zg==" | @base64urld"#, "f");
+
+yields!(
+    format_base32,
+    r#"["", "f", "fo", "foo", "foob", "fooba", "foobar"] | map(@base32)"#,
+    [
+        "",
+        "MY======",
+        "MZXQ====",
+        "MZXW6===",
+        "MZXW6YQ=",
+        "MZXW6YTB",
+        "MZXW6YTBOI======"
+    ]
+);
+yields!(
+    format_base32d,
+    r#"["MZXW6YQ=", "MZXW6YQ", "MZXW6YTBOI======"] | map(@base32d)"#,
+    ["foob", "foob", "foobar"]
+);
+yields!(format_base32d_invalid, r#"["MZ!" | @base32d?]"#, json!([]));
+
+/// There is no decoding counterpart to formats like `@uri`.
+#[test]
+fn format_decode_undefined() {
+    let errs = common::try_compile("@urid").err().unwrap();
+    assert_eq!(errs, ["@urid"]);
+}
+
 yields!(
     format_sh,
     r#"[0, 0 == 0, {}.a, "O'Hara!", ["Here", "there"] | @sh]"#,
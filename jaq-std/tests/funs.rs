@@ -2,7 +2,7 @@
 
 pub mod common;
 
-use common::{give, gives};
+use common::{fail, give, gives};
 use serde_json::json;
 
 yields!(repeat, "def r(f): f, r(f); [limit(3; r(1, 2))]", [1, 2, 1]);
@@ -15,19 +15,145 @@ yields!(
     0
 );
 
-yields!(nested_rec, "def f: def g: 0, g; g; def h: h; first(f)", 0);
+/// Ensure that `reduce inputs` does not hold on to inputs that it has consumed.
+#[test]
+fn lazy_reduce_inputs() {
+    use jaq_core::{Ctx, RcIter};
+    use jaq_json::Val;
+    use std::{cell::RefCell, rc::Rc, rc::Weak};
+
+    let code = "reduce inputs as $x (0; . + ($x | utf8bytelength))";
+    let filter = common::compile(code);
+
+    let n = 100_000;
+    let last: RefCell<Option<Weak<String>>> = RefCell::new(None);
+    let inputs = (0..n).map(|_| {
+        // by the time an input is requested, the previous input is no longer alive
+        let prev = last.borrow_mut().take();
+        assert!(prev.and_then(|w| w.upgrade()).is_none());
+        let s = Rc::new("x".repeat(100));
+        *last.borrow_mut() = Some(Rc::downgrade(&s));
+        Ok(Val::Str(s))
+    });
+    let inputs = RcIter::new(Box::new(inputs) as Box<dyn Iterator<Item = _>>);
+    let mut out = filter.run((Ctx::new([], &inputs), Val::Null));
+    assert_eq!(out.next(), Some(Ok(Val::Int(n * 100))));
+    assert_eq!(out.next(), None);
+}
 
-yields!(
-    rec_two_var_args,
-    "def f($a; $b): [$a, $b], f($a+1; $b+1); [limit(3; f(0; 1))]",
-    [[0, 1], [1, 2], [2, 3]]
-);
+/// Ensure that `input` consumes the inputs that the filter is run on.
+#[test]
+fn run_with_inputs() {
+    use jaq_core::RcIter;
+    use jaq_json::Val;
 
-yields!(
-    rec_update,
-    "def upto($x): .[$x], (if $x > 0 then upto($x-1) else {}[] as $x | . end); [1, 2, 3, 4] | upto(1) |= .+1",
-    [2, 3, 3, 4]
-);
+    let code = "[., input]";
+    let filter = common::compile(code);
+
+    let inputs = [json!(1), json!(2)].map(|x| Ok(Val::from(x)));
+    let inputs = RcIter::new(Box::new(inputs.into_iter()) as Box<dyn Iterator<Item = _>>);
+    let out: Vec<_> = filter.run_with_inputs([], &inputs).collect();
+    assert_eq!(out, [Ok(Val::from(json!([1, 2])))]);
+}
+
+/// Ensure that running a filter in parallel yields the same outputs as running it sequentially.
+#[test]
+fn run_parallel() {
+    use jaq_core::RcIter;
+    use jaq_json::Val;
+
+    let code = "[range(.)] | map(. * .) | add, (.[1:] | add)";
+    let filter = common::compile(code);
+
+    let xs: Vec<_> = (0..100).map(|i| json!(i)).collect();
+    let from_val = |y: jaq_core::ValR<Val>| y.map(serde_json::Value::from).unwrap();
+    let seq = xs.iter().map(|x| {
+        let inputs = RcIter::new(core::iter::empty());
+        let ys = filter.run((jaq_core::Ctx::new([], &inputs), Val::from(x.clone())));
+        ys.map(from_val).collect::<Vec<_>>()
+    });
+    let seq: Vec<_> = seq.collect();
+    for threads in [1, 3, 8, 200] {
+        let par = filter.run_parallel(xs.clone(), threads, Val::from, from_val);
+        assert_eq!(par, seq);
+    }
+}
+
+/// Compiling many filters against precompiled definitions
+/// yields the same filters as compiling the definitions every time.
+///
+/// The precompiled definitions are not compiled again:
+/// the filters are compiled with an empty prelude module,
+/// which on its own does not define the filters that they use.
+#[test]
+fn precompiled_defs() {
+    use jaq_core::load::{Arena, File, Loader};
+    use jaq_core::Compiler;
+    use jaq_json::Val;
+
+    let codes: Vec<_> = (0..100).map(|i| format!("[range({i})] | add")).collect();
+    let arena = Arena::default();
+    let file = |code| File {
+        code,
+        path: "".into(),
+    };
+    let compiler = || Compiler::default().with_funs(jaq_std::funs::<Val>());
+
+    let mut mods = Loader::new(jaq_std::defs())
+        .load(&arena, file("."))
+        .unwrap();
+    mods.pop();
+    let prelude = compiler().with_mods(mods).unwrap();
+
+    for (i, code) in codes.iter().enumerate() {
+        let f1 = common::compile(code);
+
+        let mods = || Loader::new([]).load(&arena, file(code)).unwrap();
+        assert!(compiler().compile(mods()).is_err());
+        let f2 = prelude.clone().compile(mods()).unwrap();
+
+        let y = Val::from(json!(if i == 0 { None } else { Some(i * (i - 1) / 2) }));
+        f1.yields(Val::Null, core::iter::once(Ok(y.clone())));
+        f2.yields(Val::Null, core::iter::once(Ok(y)));
+    }
+}
+
+/// `env` yields either a fixed or the current environment, depending on the context.
+#[test]
+fn env_snapshot_live() {
+    use core::cell::RefCell;
+    use jaq_core::{Ctx, Env, RcIter};
+    use jaq_json::Val;
+
+    let code = "env.X";
+    let filter = common::compile(code);
+
+    let vars = RefCell::new(json!({"X": "1"}));
+    let current = || Val::from(vars.borrow().clone());
+    let snapshot = Env::Snapshot(current());
+    let live = Env::Live(&current);
+    let run = |env| {
+        let inputs = RcIter::new(core::iter::empty());
+        let ctx = Ctx::new([], &inputs).with_env(env);
+        filter.run((ctx, Val::Null)).collect::<Vec<_>>()
+    };
+
+    assert_eq!(run(&snapshot), [Ok(Val::from(json!("1")))]);
+    assert_eq!(run(&live), [Ok(Val::from(json!("1")))]);
+    *vars.borrow_mut() = json!({"X": "2"});
+    assert_eq!(run(&snapshot), [Ok(Val::from(json!("1")))]);
+    assert_eq!(run(&live), [Ok(Val::from(json!("2")))]);
+}
+
+/// `halt` and `halt_error` yield errors that cannot be caught.
+#[test]
+fn halt() {
+    use jaq_json::Error;
+    fail(json!(null), "try halt catch 1", Error::halt(0, None));
+    fail(json!(null), "halt // 1", Error::halt(0, None));
+    let err = Error::halt(1, Some(json!("x").into()));
+    fail(json!("x"), "try halt_error(1) catch 1", err);
+}
 
 #[test]
 fn ascii() {
@@ -52,6 +178,17 @@ yields!(
     "1970-01-02T00:00:00.123456Z"
 );
 
+// `catch` receives the value passed to `error`
+yields!(error_catch_arr, "try error([1, 2]) catch .", [1, 2]);
+yields!(error_catch_obj, "try error({a: 1}) catch .a", 1);
+yields!(error_catch_null, "try error(null) catch .", json!(null));
+// errors raised by jaq itself are caught as message strings
+yields!(
+    error_catch_msg,
+    "try (1 + {}) catch .",
+    "cannot calculate number (1) + object ({})"
+);
+
 #[test]
 fn explode_implode() {
     give(json!("❤ の"), "explode", json!([10084, 32, 12398]));
@@ -64,6 +201,11 @@ fn explode_implode() {
 }
 
 yields!(first_empty, "[first({}[])]", json!([]));
+yields!(
+    first_nested,
+    "[first(first(range(5))), first(range(5) | first(range(.; 5)))]",
+    [0, 0]
+);
 yields!(first_some, "first(1, 2, 3)", 1);
 
 yields!(
@@ -112,6 +254,10 @@ fn limit() {
     give(json!(null), "[limit(-1; 0, 1)]", json!([]));
 }
 
+yields!(limit_zero_lazy, "[limit(0; error)]", json!([]));
+yields!(limit_neg_range, "[limit(-1; range(3))]", json!([]));
+yields!(limit_exceeding, "[limit(10; range(3))]", [0, 1, 2]);
+
 yields!(
     math_0_argument_scalar_filters,
     "[-2.2, -1.1, 0, 1.1, 2.2 | sin as $s | cos as $c | $s * $s + $c * $c]",
@@ -136,14 +282,105 @@ yields!(
     [10.0, 11.0, 12.0, 13.0, 7.0, 8.0, 8.0, 9.0]
 );
 
+#[test]
+fn path() {
+    let x = json!({"a": [{"b": 1}, 2], "c": null});
+    give(x.clone(), "path(.a[0].b)", json!(["a", 0, "b"]));
+    give(
+        x.clone(),
+        "[path(..)]",
+        json!([[], ["a"], ["a", 0], ["a", 0, "b"], ["a", 1], ["c"]]),
+    );
+    give(
+        x.clone(),
+        "[path(.a[] | select(. == 2))]",
+        json!([["a", 1]]),
+    );
+    give(
+        x.clone(),
+        "[path(.c // .a, first(.a, .c))]",
+        json!([["a"], ["a"]]),
+    );
+    give(
+        x.clone(),
+        "[path(.x?, .a[]?[]?)]",
+        json!([["x"], ["a", 0, "b"]]),
+    );
+    give(
+        x.clone(),
+        "[path(if .c then .c else .a end)]",
+        json!([["a"]]),
+    );
+    give(
+        x,
+        "def f: .a; [path(f[1], limit(1; .c, .a))]",
+        json!([["a", 1], ["c"]]),
+    );
+}
+
+// paths yielded by `path` can be used by `getpath` and `setpath`
+#[test]
+fn path_getpath() {
+    let x = json!({"a": [{"b": 1}, 2]});
+    let f = ". as $v | path(.a[0].b) as $p | [($v | getpath($p)), .a[0].b]";
+    give(x.clone(), f, json!([1, 1]));
+    let f = "path(.a[0].b) as $p | setpath($p; 3) == (.a[0].b = 3)";
+    give(x, f, json!(true));
+}
+
+#[test]
+fn path_slice() {
+    let x = json!({"a": [1, 2, 3]});
+    give(x.clone(), "path(.a[1:])", json!(["a", {"start": 1}]));
+    give(x.clone(), "path(.a[:-1][0])", json!(["a", {"end": -1}, 0]));
+    give(
+        x.clone(),
+        r#"getpath(["a", {"start": 1, "end": null}])"#,
+        json!([2, 3]),
+    );
+    let f = "path(.a[1:2]) as $p | setpath($p; [4, 5])";
+    give(x.clone(), f, json!({"a": [1, 4, 5, 3]}));
+    give(json!("abc"), r#"getpath([{"end": 2}])"#, json!("ab"));
+
+    give(
+        x.clone(),
+        "[path(.a[:], .a[])]",
+        json!([["a", {}], ["a", 0], ["a", 1], ["a", 2]]),
+    );
+    give(x.clone(), r#"getpath(["a", {}])"#, json!([1, 2, 3]));
+    give(x, "(.a[:], .a[:]) |= .[1:]", json!({"a": [3]}));
+}
+
+#[test]
+fn path_invalid() {
+    let err = "invalid path expression";
+    give(json!(0), "try path(1) catch .", json!(err));
+    give(json!([0]), "try path(.[0] + 1) catch .", json!(err));
+    give(
+        json!([0]),
+        "try path(try error(0) catch .) catch .",
+        json!(err),
+    );
+    give(json!([0]), "try path(.[0] | . + 1 | .) catch .", json!(err));
+    // values without path are only an error if they are output
+    give(json!(0), "[path(1 | empty)]", json!([]));
+    give(
+        json!(0),
+        "[path(1 | select(false), (2 | empty))]",
+        json!([]),
+    );
+    give(json!(0), "try path(1 | (empty, .)) catch .", json!(err));
+    give(json!(0), r#"try path(error("x")) catch ."#, json!("x"));
+}
+
 yields!(range_pp, "[range(0; 6;  2)]", [0, 2, 4]);
 yields!(range_pn, "[range(0; 6; -2)]", json!([]));
 yields!(range_np, "[range(0; -6; 2)]", json!([]));
 yields!(range_nn, "[range(0; -6; -2)]", [0, -2, -4]);
 yields!(range_zz, "[range(0; 0; 0)]", json!([]));
 yields!(range_fp, "[range(0.0; 2; 0.5)]", [0.0, 0.5, 1.0, 1.5]);
-yields!(range_ip, "[limit(3; range(0; 1/0; 1))]", [0, 1, 2]);
-yields!(range_in, "[limit(3; range(0; -1/0; -1))]", [0, -1, -2]);
+yields!(range_ip, "[limit(3; range(0; infinite; 1))]", [0, 1, 2]);
+yields!(range_in, "[limit(3; range(0; -infinite; -1))]", [0, -1, -2]);
 // here, we diverge from jq, which just returns the empty list
 yields!(range_pz, "[limit(3; range(0; 6; 0))]", json!([0, 0, 0]));
 yields!(range_nz, "[limit(3; range(0; -6; 0))]", json!([0, 0, 0]));
@@ -164,7 +401,7 @@ fn regex() {
           "string": s
         })
     };
-    let d1 = json!([c(00, "2012-03-14"), c(00, "2012"), c(05, "03"), c(08, "14")]);
+    let d1 = json!([c(0, "2012-03-14"), c(0, "2012"), c(5, "03"), c(8, "14")]);
     let d2 = json!([c(12, "2013-01-01"), c(12, "2013"), c(17, "01"), c(20, "01")]);
     let d3 = json!([c(27, "2014-07-05"), c(27, "2014"), c(32, "07"), c(35, "05")]);
 
@@ -199,6 +436,22 @@ fn round() {
     */
 }
 
+#[test]
+fn sort_by() {
+    let v = json!([{"a": 2, "b": 1}, {"a": 1, "b": 3}, {"a": 1, "b": 2}, {"a": 1}]);
+    let ab = json!([{"a": 1}, {"a": 1, "b": 2}, {"a": 1, "b": 3}, {"a": 2, "b": 1}]);
+    give(v.clone(), "sort_by(.a, .b)", ab);
+    let ba = json!([{"a": 1}, {"a": 2, "b": 1}, {"a": 1, "b": 2}, {"a": 1, "b": 3}]);
+    give(v, "sort_by(.b, .a)", ba);
+
+    // keys are compared like arrays, so shorter keys come first
+    give(
+        json!([[2, 1], [1, 0], [1]]),
+        "sort_by(.[])",
+        json!([[1], [1, 0], [2, 1]]),
+    );
+}
+
 #[test]
 fn startswith() {
     give(json!("foobar"), r#"startswith("")"#, json!(true));
@@ -221,6 +474,8 @@ fn ltrimstr() {
     give(json!("foobar"), r#"ltrimstr("foo")"#, json!("bar"));
     give(json!("foobar"), r#"ltrimstr("bar")"#, json!("foobar"));
     give(json!("اَلْعَرَبِيَّةُ"), r#"ltrimstr("ا")"#, json!("َلْعَرَبِيَّةُ"));
+    give(json!("abc"), r#"ltrimstr("abc")"#, json!(""));
+    give(json!("aabc"), r#"ltrimstr("a")"#, json!("abc"));
 }
 
 #[test]
@@ -229,4 +484,12 @@ fn rtrimstr() {
     give(json!("foobar"), r#"rtrimstr("bar")"#, json!("foo"));
     give(json!("foobar"), r#"rtrimstr("foo")"#, json!("foobar"));
     give(json!("اَلْعَرَبِيَّةُ"), r#"rtrimstr("ا")"#, json!("اَلْعَرَبِيَّةُ"));
+    give(json!("abc"), r#"rtrimstr("abc")"#, json!(""));
+    give(json!("abcc"), r#"rtrimstr("c")"#, json!("abc"));
 }
+
+yields!(
+    shadow_builtin,
+    "def not: 0; [true | not, (false | not)]",
+    [0, 0]
+);
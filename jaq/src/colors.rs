@@ -0,0 +1,102 @@
+//! Colors for printing JSON values.
+use core::fmt::{self, Display, Formatter};
+
+/// ANSI SGR parameters, such as `1;31`, for the parts of JSON values.
+///
+/// An empty parameter means that the part is printed without color.
+pub struct Colors {
+    pub null: String,
+    pub false_: String,
+    pub true_: String,
+    pub num: String,
+    pub str: String,
+    pub arr: String,
+    pub obj: String,
+    pub key: String,
+}
+
+impl Default for Colors {
+    fn default() -> Self {
+        let [null, false_, true_, num, str, arr, obj, key] =
+            ["", "", "", "", "32", "1", "1", "1"].map(String::from);
+        Self {
+            null,
+            false_,
+            true_,
+            num,
+            str,
+            arr,
+            obj,
+            key,
+        }
+    }
+}
+
+impl Colors {
+    /// Colors that print every part without color.
+    pub fn none() -> Self {
+        let [null, false_, true_, num, str, arr, obj, key] = [""; 8].map(String::from);
+        Self {
+            null,
+            false_,
+            true_,
+            num,
+            str,
+            arr,
+            obj,
+            key,
+        }
+    }
+
+    fn fields_mut(&mut self) -> [&mut String; 8] {
+        [
+            &mut self.null,
+            &mut self.false_,
+            &mut self.true_,
+            &mut self.num,
+            &mut self.str,
+            &mut self.arr,
+            &mut self.obj,
+            &mut self.key,
+        ]
+    }
+
+    /// Parse colors from a specification like `1;30:0;31:0;32:0;33:0;34:1;35:1;36:1;37`.
+    ///
+    /// Like in jq, the specification consists of up to eight colon-separated fields for
+    /// null, false, true, numbers, strings, arrays, objects, and object keys.
+    /// Fields that are omitted keep their default color.
+    /// Return `None` if there are too many fields or
+    /// if a field contains anything else than digits and `;`.
+    pub fn parse(spec: &str) -> Option<Self> {
+        let mut colors = Self::default();
+        let mut fields = colors.fields_mut().into_iter();
+        for sgr in spec.split(':') {
+            let valid = sgr.chars().all(|c| c.is_ascii_digit() || c == ';');
+            *fields.next().filter(|_| valid)? = sgr.to_string();
+        }
+        drop(fields);
+        Some(colors)
+    }
+
+    /// Read colors from the `JQ_COLORS` environment variable.
+    ///
+    /// If the variable is not set or malformed, return the default colors.
+    pub fn from_env() -> Self {
+        let spec = std::env::var("JQ_COLORS").ok();
+        spec.and_then(|spec| Self::parse(&spec)).unwrap_or_default()
+    }
+}
+
+/// Display a value with given SGR parameters if colors are enabled.
+pub struct Paint<'a, T>(pub &'a str, pub T);
+
+impl<T: Display> Display for Paint<'_, T> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        if self.0.is_empty() || !yansi::is_enabled() {
+            self.1.fmt(f)
+        } else {
+            write!(f, "\x1b[{}m{}\x1b[0m", self.0, self.1)
+        }
+    }
+}
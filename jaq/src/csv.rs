@@ -0,0 +1,99 @@
+//! Reading of CSV and TSV input.
+use std::io::{self, BufRead};
+
+/// Format of delimiter-separated values.
+#[derive(Copy, Clone)]
+pub enum Format {
+    /// Comma-separated values with character separating fields
+    ///
+    /// Fields may be enclosed in double quotes, in which case they may
+    /// contain separators, newlines, and double quotes (written as `""`).
+    Csv(char),
+    /// Tab-separated values
+    ///
+    /// Fields may contain the escape sequences `\t`, `\n`, `\r`, and `\\`.
+    Tsv,
+}
+
+/// Return an iterator over the rows of the input, where each row is a vector of fields.
+pub fn rows<'a>(
+    fmt: Format,
+    mut read: impl BufRead + 'a,
+) -> impl Iterator<Item = io::Result<Vec<String>>> + 'a {
+    let mut line = String::new();
+    core::iter::from_fn(move || {
+        line.clear();
+        match read.read_line(&mut line) {
+            Ok(0) => None,
+            Ok(_) => Some(match fmt {
+                Format::Csv(sep) => csv_row(sep, &mut line, &mut read),
+                Format::Tsv => Ok(tsv_row(&line)),
+            }),
+            Err(e) => Some(Err(e)),
+        }
+    })
+}
+
+fn trim_newline(line: &str) -> &str {
+    let line = line.strip_suffix('\n').unwrap_or(line);
+    line.strip_suffix('\r').unwrap_or(line)
+}
+
+/// Parse a CSV row that starts with `line`, reading more lines if a quoted field spans them.
+fn csv_row(sep: char, line: &mut String, read: &mut impl BufRead) -> io::Result<Vec<String>> {
+    let mut row = Vec::new();
+    let mut field = String::new();
+    // position in `line` up to which we have already processed
+    let mut pos = 0;
+    let mut quoted = false;
+    loop {
+        let mut chars = line[pos..].chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '"' if quoted && chars.peek() == Some(&'"') => {
+                    chars.next();
+                    field.push('"')
+                }
+                '"' if quoted => quoted = false,
+                '"' if field.is_empty() => quoted = true,
+                c if quoted => field.push(c),
+                c if c == sep => row.push(core::mem::take(&mut field)),
+                '\r' | '\n' => (),
+                c => field.push(c),
+            }
+        }
+        if !quoted {
+            row.push(field);
+            return Ok(row);
+        }
+        // the quoted field continues on the next line
+        pos = line.len();
+        if read.read_line(line)? == 0 {
+            let e = "unterminated quoted field in CSV input";
+            return Err(io::Error::new(io::ErrorKind::InvalidData, e));
+        }
+    }
+}
+
+fn tsv_row(line: &str) -> Vec<String> {
+    let unescape = |field: &str| {
+        let mut s = String::with_capacity(field.len());
+        let mut chars = field.chars();
+        while let Some(c) = chars.next() {
+            match (c, chars.clone().next()) {
+                ('\\', Some(e @ ('t' | 'n' | 'r' | '\\'))) => {
+                    chars.next();
+                    s.push(match e {
+                        't' => '\t',
+                        'n' => '\n',
+                        'r' => '\r',
+                        _ => '\\',
+                    })
+                }
+                (c, _) => s.push(c),
+            }
+        }
+        s
+    };
+    trim_newline(line).split('\t').map(unescape).collect()
+}
@@ -5,6 +5,12 @@ use jaq_json::Val;
 use std::io::{self, BufRead, Write};
 use std::path::{Path, PathBuf};
 use std::process::{ExitCode, Termination};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+mod colors;
+#[cfg(feature = "csv")]
+mod csv;
+mod repl;
 
 type Filter = jaq_core::Filter<Native<Val>>;
 
@@ -12,6 +18,9 @@ type Filter = jaq_core::Filter<Native<Val>>;
 #[global_allocator]
 static GLOBAL: mimalloc::MiMalloc = mimalloc::MiMalloc;
 
+/// Number of lines read from the current input file, yielded by `input_line_number`.
+static LINE: AtomicUsize = AtomicUsize::new(0);
+
 /// Just Another Query Tool
 #[derive(Parser)]
 #[command(version)]
@@ -55,6 +64,31 @@ struct Cli {
     #[arg(short = 'R', long)]
     raw_input: bool,
 
+    /// Read CSV rows of the input as arrays of strings
+    ///
+    /// Fields may be enclosed in double quotes,
+    /// in which case they may contain separators, newlines, and
+    /// double quotes (written as `""`).
+    /// When the option `--slurp` is used additionally,
+    /// then all rows are read into a single array.
+    #[cfg(feature = "csv")]
+    #[arg(long, conflicts_with = "raw_input")]
+    csv_input: bool,
+
+    /// Read TSV rows of the input as arrays of strings
+    ///
+    /// Fields may contain the escape sequences `\t`, `\n`, `\r`, and `\\`.
+    /// When the option `--slurp` is used additionally,
+    /// then all rows are read into a single array.
+    #[cfg(feature = "csv")]
+    #[arg(long, conflicts_with_all = ["raw_input", "csv_input"])]
+    tsv_input: bool,
+
+    /// Separate fields of CSV input by character c
+    #[cfg(feature = "csv")]
+    #[arg(long, value_name = "c", default_value_t = ',')]
+    csv_separator: char,
+
     /// Print JSON compactly, omitting whitespace
     #[arg(short, long)]
     compact_output: bool,
@@ -67,20 +101,89 @@ struct Cli {
     #[arg(long)]
     tab: bool,
 
+    /// Use string s for indentation
+    ///
+    /// The string may consist only of whitespace characters.
+    /// This overrides `--indent` and `--tab`.
+    #[arg(long, value_name = "s", value_parser = whitespace)]
+    indent_str: Option<String>,
+
+    /// Print arrays and objects compactly on one line if they take at most n characters
+    ///
+    /// This has no effect with `--compact-output`.
+    #[arg(long, value_name = "n")]
+    inline_width: Option<usize>,
+
+    /// Escape line and paragraph separators (U+2028, U+2029) in strings
+    ///
+    /// This makes output valid as JavaScript string literal,
+    /// because older JavaScript engines do not allow these characters
+    /// to occur unescaped in strings.
+    #[arg(long)]
+    escape_js: bool,
+
     /// Do not print a newline after each value
     ///
     /// Unlike jq, this does not enable `--raw-output`.
     #[arg(short, long)]
     join_output: bool,
 
+    /// Do not print a newline after the last value
+    ///
+    /// Newlines are still printed between values.
+    #[arg(long, conflicts_with = "join_output")]
+    no_trailing_newline: bool,
+
+    /// Flush output after every n values
+    ///
+    /// By default, output is flushed after every value,
+    /// so that readers see it as soon as possible.
+    /// Larger values improve throughput at the cost of latency.
+    /// If n is 0, output is only flushed when the output buffer is full
+    /// or when all values have been printed.
+    #[arg(long, value_name = "n", default_value_t = 1)]
+    flush_every: usize,
+
+    /// Read and write JSON text sequences (RFC 7464)
+    ///
+    /// Every output value is preceded by an ASCII record separator (RS).
+    /// Input is split into records at RS characters.
+    /// Records that are invalid or truncated, such as a final record `1`
+    /// that is not followed by whitespace, are skipped with a warning.
+    #[arg(long)]
+    seq: bool,
+
+    /// Print runtime errors as values to the standard output and continue
+    ///
+    /// Every error is printed like other output values, as an object
+    /// `{"error": e, "line": n}`, where `e` is the value of the error
+    /// (as it would be caught by `try ... catch .`) and
+    /// `n` is the input line number where the error occurred.
+    /// Errors when reading input are still fatal.
+    #[arg(long)]
+    stream_errors: bool,
+
+    /// Replace invalid UTF-8 in the input by U+FFFD instead of failing
+    ///
+    /// Unless the option `--raw-input` is used,
+    /// the whole input is read before processing it.
+    #[arg(long)]
+    lossy_utf8: bool,
+
     /// Color output
     ///
     /// When this is set to `auto`, colors are enabled if
     /// output is written to a terminal and
     /// the `NO_COLOR` environment variable is not set.
+    ///
+    /// The colors can be set like in jq via the `JQ_COLORS` environment variable.
     #[arg(long, value_name = "WHEN", default_value = "auto")]
     color: ColorWhen,
 
+    // colors from the environment
+    #[arg(skip = colors::Colors::from_env())]
+    colors: colors::Colors,
+
     /// Read filter from a file
     ///
     /// In this case, all arguments are interpreted as input files.
@@ -97,6 +200,13 @@ struct Cli {
     #[arg(long, value_names = &["a", "v"])]
     arg: Vec<String>,
 
+    /// Set variable `$<a>` to JSON value `<v>`
+    ///
+    /// If `<v>` is not a single valid JSON value,
+    /// jaq fails before running the filter.
+    #[arg(long, value_names = &["a", "v"])]
+    argjson: Vec<String>,
+
     /// Set variable `$<a>` to string containing the contents of file `f`
     #[arg(long, value_names = &["a", "f"])]
     rawfile: Vec<String>,
@@ -109,10 +219,40 @@ struct Cli {
     #[arg(long, value_name = "FILE")]
     run_tests: Option<PathBuf>,
 
+    /// Run filters read line by line from standard input (REPL)
+    ///
+    /// In this case, all arguments are interpreted as input files.
+    /// Every line is run on the values in the input files (or `null` if none are given).
+    /// A line `:load FILE` replaces these values by the values in `FILE`, and
+    /// a line starting with `|` is run on the outputs of the previous line.
+    #[arg(long, conflicts_with_all = ["from_file", "in_place", "run_tests"])]
+    repl: bool,
+
+    /// Print the filter formatted canonically instead of running it
+    ///
+    /// Comments in the filter are not preserved.
+    #[arg(long)]
+    fmt: bool,
+
+    /// Check that the filter is valid instead of running it
+    ///
+    /// This parses and compiles the filter, including its modules,
+    /// and prints all errors found on the way.
+    #[arg(long, conflicts_with = "fmt")]
+    check: bool,
+
     /// Filter to execute, followed by list of input files
     args: Vec<String>,
 }
 
+fn whitespace(s: &str) -> Result<String, &'static str> {
+    if s.chars().all(char::is_whitespace) {
+        Ok(s.to_string())
+    } else {
+        Err("string must consist only of whitespace")
+    }
+}
+
 #[derive(Clone, ValueEnum)]
 enum ColorWhen {
     Always,
@@ -167,6 +307,10 @@ fn real_main(cli: &Cli) -> Result<ExitCode, Error> {
 
     let (vars, mut ctx): (Vec<String>, Vec<Val>) = binds(cli)?.into_iter().unzip();
 
+    if cli.repl {
+        return repl::run(cli, &vars, ctx, &cli.args);
+    }
+
     let mut args = cli.args.iter();
     let file = match &cli.from_file {
         Some(path) => Some((
@@ -177,6 +321,22 @@ fn real_main(cli: &Cli) -> Result<ExitCode, Error> {
     };
     let files: Vec<_> = args.collect();
 
+    if cli.fmt {
+        let (path, code) = file.unwrap_or_else(|| ("<inline>".into(), ".".into()));
+        let formatted = load::format(&code).map_err(|e| {
+            let code = &*code;
+            Error::Report(load_errors(Vec::from([(load::File { code, path }, e)])))
+        })?;
+        println!("{formatted}");
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    if cli.check {
+        let (path, code) = file.unwrap_or_else(|| ("<inline>".into(), ".".into()));
+        parse(&path, &code, &vars, &cli.search_paths).map_err(Error::Report)?;
+        return Ok(ExitCode::SUCCESS);
+    }
+
     let (vals, filter) = match file {
         None => (Vec::new(), Filter::default()),
         Some((path, code)) => {
@@ -186,14 +346,17 @@ fn real_main(cli: &Cli) -> Result<ExitCode, Error> {
     ctx.extend(vals);
     //println!("Filter: {:?}", filter);
 
+    let mut printer = Printer::new(cli.flush_every);
     let last = if files.is_empty() {
         let inputs = read_buffered(cli, io::stdin().lock());
-        with_stdout(|out| run(cli, &filter, ctx, inputs, |v| print(out, cli, &v)))?
+        with_stdout(|out| run(cli, &filter, ctx, inputs, |v| printer.print(out, cli, &v)))?
     } else {
         let mut last = None;
         for file in files {
             let path = Path::new(file);
             let file = load_file(path).map_err(|e| Error::Io(Some(file.to_string()), e))?;
+            // like jq, count lines separately for every input file
+            LINE.store(0, Ordering::Relaxed);
             let inputs = read_slice(cli, &file);
             if cli.in_place {
                 // create a temporary file where output is written to
@@ -202,9 +365,13 @@ fn real_main(cli: &Cli) -> Result<ExitCode, Error> {
                     .prefix("jaq")
                     .tempfile_in(location)?;
 
-                last = run(cli, &filter, ctx.clone(), inputs, |output| {
-                    print(tmp.as_file_mut(), cli, &output)
+                let mut out = io::BufWriter::new(tmp.as_file_mut());
+                let mut printer = Printer::new(cli.flush_every);
+                last = run(cli, &filter, ctx.clone(), inputs, |v| {
+                    printer.print(&mut out, cli, &v)
                 })?;
+                out.flush()?;
+                drop(out);
 
                 // replace the input file with the temporary file
                 let perms = std::fs::metadata(path)?.permissions();
@@ -212,7 +379,9 @@ fn real_main(cli: &Cli) -> Result<ExitCode, Error> {
                 std::fs::set_permissions(path, perms)?;
             } else {
                 last = with_stdout(|out| {
-                    run(cli, &filter, ctx.clone(), inputs, |v| print(out, cli, &v))
+                    run(cli, &filter, ctx.clone(), inputs, |v| {
+                        printer.print(out, cli, &v)
+                    })
                 })?;
             }
         }
@@ -231,11 +400,11 @@ fn real_main(cli: &Cli) -> Result<ExitCode, Error> {
 
 fn bind<F>(var_val: &mut Vec<(String, Val)>, args: &[String], f: F) -> Result<(), Error>
 where
-    F: Fn(&str) -> Result<Val, Error>,
+    F: Fn(&str, &str) -> Result<Val, Error>,
 {
     for arg_val in args.chunks(2) {
         if let [arg, val] = arg_val {
-            var_val.push((arg.clone(), f(val)?));
+            var_val.push((arg.clone(), f(arg, val)?));
         }
     }
     Ok(())
@@ -244,14 +413,20 @@ where
 fn binds(cli: &Cli) -> Result<Vec<(String, Val)>, Error> {
     let mut var_val = Vec::new();
 
-    bind(&mut var_val, &cli.arg, |v| {
+    bind(&mut var_val, &cli.arg, |_, v| {
         Ok(Val::Str(v.to_string().into()))
     })?;
-    bind(&mut var_val, &cli.rawfile, |path| {
+    bind(&mut var_val, &cli.argjson, |arg, v| {
+        use hifijson::token::Lex;
+        let mut lexer = hifijson::SliceLexer::new(v.as_bytes());
+        let prefix = || Some(format!("--argjson {arg}"));
+        (lexer.exactly_one(Val::parse)).map_err(|e| Error::Io(prefix(), invalid_data(e)))
+    })?;
+    bind(&mut var_val, &cli.rawfile, |_, path| {
         let s = std::fs::read_to_string(path).map_err(|e| Error::Io(Some(path.to_string()), e));
         Ok(Val::Str(s?.into()))
     })?;
-    bind(&mut var_val, &cli.slurpfile, |path| {
+    bind(&mut var_val, &cli.slurpfile, |_, path| {
         json_array(path).map_err(|e| Error::Io(Some(path.to_string()), e))
     })?;
 
@@ -276,10 +451,9 @@ fn parse(
     vars: &[String],
     paths: &[PathBuf],
 ) -> Result<(Vec<Val>, Filter), Vec<FileReports>> {
-    use compile::Compiler;
-    use load::{import, Arena, File, Loader};
+    use load::{Arena, File, Loader};
 
-    let vars: Vec<_> = vars.iter().map(|v| format!("${v}")).collect();
+    let vars = global_vars(vars);
     let arena = Arena::default();
     let loader = Loader::new(jaq_std::defs().chain(jaq_json::defs())).with_std_read(paths);
     let path = path.into();
@@ -287,19 +461,46 @@ fn parse(
         .load(&arena, File { path, code })
         .map_err(load_errors)?;
 
+    let vals = import_vals(&modules, paths)?;
+    let filter = compiler(&vars).compile(modules).map_err(compile_errors)?;
+    Ok((vals, filter))
+}
+
+/// Return the names of global variables, starting with `$`.
+fn global_vars(vars: &[String]) -> Vec<String> {
+    vars.iter().map(|v| format!("${v}")).collect()
+}
+
+/// Return a compiler for filters using the given global variables.
+fn compiler(vars: &[String]) -> compile::Compiler<&str, Native<Val>> {
+    compile::Compiler::default()
+        .with_funs(jaq_std::funs().chain(jaq_json::funs()).chain(funs()))
+        .with_global_vars(vars.iter().map(|v| &**v))
+}
+
+/// Return the native filters that only make sense in the jaq binary.
+fn funs() -> impl Iterator<Item = jaq_std::Filter<Native<Val>>> {
+    let line: jaq_std::Filter<jaq_core::RunPtr<Val>> =
+        ("input_line_number", Box::new([]), |_, _| {
+            let line = LINE.load(Ordering::Relaxed);
+            Box::new(core::iter::once(Ok(Val::from(line as isize))))
+        });
+    [jaq_std::run(line)].into_iter()
+}
+
+/// Load the JSON data imported by the given modules.
+fn import_vals(
+    modules: &load::Modules<&str>,
+    paths: &[PathBuf],
+) -> Result<Vec<Val>, Vec<FileReports>> {
     let mut vals = Vec::new();
-    import(&modules, |p| {
+    load::import(modules, |p| {
         let path = p.find(paths, "json")?;
         vals.push(json_array(path).map_err(|e| e.to_string())?);
         Ok(())
     })
     .map_err(load_errors)?;
-
-    let compiler = Compiler::default()
-        .with_funs(jaq_std::funs().chain(jaq_json::funs()))
-        .with_global_vars(vars.iter().map(|v| &**v));
-    let filter = compiler.compile(modules).map_err(compile_errors)?;
-    Ok((vals, filter))
+    Ok(vals)
 }
 
 fn load_errors(errs: load::Errors<&str>) -> Vec<FileReports> {
@@ -339,20 +540,141 @@ fn invalid_data(e: impl std::error::Error + Send + Sync + 'static) -> std::io::E
     io::Error::new(io::ErrorKind::InvalidData, e)
 }
 
+/// UTF-8 byte order mark, which is ignored at the start of JSON input.
+const BOM: &[u8] = b"\xEF\xBB\xBF";
+
 fn json_slice(slice: &[u8]) -> impl Iterator<Item = io::Result<Val>> + '_ {
+    let slice = slice.strip_prefix(BOM).unwrap_or(slice);
     let mut lexer = hifijson::SliceLexer::new(slice);
+    // input after the last line break that was counted
+    let mut uncounted = slice;
     core::iter::from_fn(move || {
         use hifijson::token::Lex;
-        Some(Val::parse(lexer.ws_token()?, &mut lexer).map_err(invalid_data))
+        let v = Val::parse(lexer.ws_token()?, &mut lexer).map_err(invalid_data);
+        // like jq, count lines up to and including a line break after the value
+        let rest = lexer.as_slice();
+        let rest = match rest.iter().position(|c| !matches!(c, b' ' | b'\t' | b'\r')) {
+            Some(i) if rest[i] == b'\n' => &rest[i + 1..],
+            _ => rest,
+        };
+        if rest.len() < uncounted.len() {
+            let read = &uncounted[..uncounted.len() - rest.len()];
+            let lines = read.iter().filter(|c| **c == b'\n').count();
+            LINE.fetch_add(lines, Ordering::Relaxed);
+            uncounted = rest;
+        }
+        Some(v)
     })
 }
 
 fn json_read<'a>(read: impl BufRead + 'a) -> impl Iterator<Item = io::Result<Val>> + 'a {
-    let mut lexer = hifijson::IterLexer::new(read.bytes());
+    use std::{cell::RefCell, rc::Rc};
+    // the reader together with the last byte that was passed to the lexer
+    let shared = Rc::new(RefCell::new((read, None)));
+    let mut lexer = None;
     core::iter::from_fn(move || {
         use hifijson::token::Lex;
-        let v = Val::parse(lexer.ws_token()?, &mut lexer);
-        Some(v.map_err(|e| core::mem::take(&mut lexer.error).unwrap_or_else(|| invalid_data(e))))
+        // strip BOM only once we actually need input,
+        // because reading may block (e.g. when reading from a terminal)
+        let lexer = lexer.get_or_insert_with(|| {
+            let read = &mut shared.borrow_mut().0;
+            // if this fails, then the error is reported when reading again
+            if read.fill_buf().map_or(false, |buf| buf.starts_with(BOM)) {
+                read.consume(BOM.len())
+            }
+            let shared = shared.clone();
+            hifijson::IterLexer::new(core::iter::from_fn(move || {
+                let (read, last) = &mut *shared.borrow_mut();
+                let c = read_byte(read)?;
+                if let Ok(c) = c {
+                    if c == b'\n' {
+                        LINE.fetch_add(1, Ordering::Relaxed);
+                    }
+                    *last = Some(c);
+                }
+                Some(c)
+            }))
+        });
+        let v = Val::parse(lexer.ws_token()?, lexer);
+        let v = v.map_err(|e| core::mem::take(&mut lexer.error).unwrap_or_else(|| invalid_data(e)));
+
+        // like jq, count lines up to and including a line break after the value;
+        // the lexer reads the byte after a number, but not after other values
+        let (read, last) = &mut *shared.borrow_mut();
+        let skip = match (&v, last) {
+            (Ok(Val::Int(_) | Val::Float(_) | Val::Num(_)), last) => {
+                matches!(last, Some(b' ' | b'\t' | b'\r'))
+            }
+            (Ok(_), _) => true,
+            (Err(_), _) => false,
+        };
+        if skip {
+            skip_line_end(read);
+        }
+        Some(v)
+    })
+}
+
+/// Read a single byte, retrying if reading was interrupted.
+fn read_byte(read: &mut impl BufRead) -> Option<io::Result<u8>> {
+    loop {
+        match read.fill_buf() {
+            Ok(buf) => {
+                let c = *buf.first()?;
+                read.consume(1);
+                return Some(Ok(c));
+            }
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Some(Err(e)),
+        }
+    }
+}
+
+/// Consume whitespace up to and including the next line break, counting it.
+///
+/// Reading errors are ignored here, because they are reported when reading again.
+fn skip_line_end(read: &mut impl BufRead) {
+    while let Ok(buf) = read.fill_buf() {
+        match buf.first() {
+            Some(b' ' | b'\t' | b'\r') => read.consume(1),
+            Some(b'\n') => {
+                read.consume(1);
+                LINE.fetch_add(1, Ordering::Relaxed);
+                return;
+            }
+            _ => return,
+        }
+    }
+}
+
+/// ASCII record separator, which starts every record of a JSON text sequence.
+const RS: u8 = 0x1E;
+
+/// Read JSON text sequences, skipping invalid records with a warning.
+fn json_seq<'a>(mut read: impl BufRead + 'a) -> impl Iterator<Item = io::Result<Val>> + 'a {
+    let mut record = Vec::new();
+    let mut vals = Vec::new().into_iter();
+    core::iter::from_fn(move || loop {
+        if let Some(v) = vals.next() {
+            return Some(Ok(v));
+        }
+        record.clear();
+        match read.read_until(RS, &mut record) {
+            Ok(0) => return None,
+            Ok(_) => (),
+            Err(e) => return Some(Err(e)),
+        }
+        let record = record.strip_suffix(&[RS]).unwrap_or(&record);
+        // a number, `true`, `false`, or `null` at the end of a record
+        // may have been truncated if no whitespace follows it
+        if record.last().map_or(false, u8::is_ascii_alphanumeric) {
+            eprintln!("Warning: ignoring possibly truncated record");
+            continue;
+        }
+        match json_slice(record).collect::<io::Result<Vec<_>>>() {
+            Ok(v) => vals = v.into_iter(),
+            Err(e) => eprintln!("Warning: ignoring invalid record: {e}"),
+        }
     })
 }
 
@@ -360,39 +682,115 @@ fn json_array(path: impl AsRef<Path>) -> io::Result<Val> {
     json_slice(&load_file(path.as_ref())?).collect()
 }
 
-fn read_buffered<'a, R>(cli: &Cli, read: R) -> Box<dyn Iterator<Item = io::Result<Val>> + 'a>
+#[cfg(feature = "csv")]
+impl Cli {
+    fn csv_format(&self) -> Option<csv::Format> {
+        if self.csv_input {
+            Some(csv::Format::Csv(self.csv_separator))
+        } else if self.tsv_input {
+            Some(csv::Format::Tsv)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(feature = "csv")]
+fn csv_input<'a>(
+    slurp: bool,
+    fmt: csv::Format,
+    read: impl BufRead + 'a,
+) -> impl Iterator<Item = io::Result<Val>> + 'a {
+    let rows = csv::rows(fmt, read).map(|r| r.map(|row| row.into_iter().map(Val::from).collect()));
+    collect_if(slurp, rows)
+}
+
+fn read_buffered<'a, R>(cli: &Cli, mut read: R) -> Box<dyn Iterator<Item = io::Result<Val>> + 'a>
 where
     R: BufRead + 'a,
 {
+    #[cfg(feature = "csv")]
+    if let Some(fmt) = cli.csv_format() {
+        return Box::new(csv_input(cli.slurp, fmt, read));
+    }
     if cli.raw_input {
-        Box::new(raw_input(cli.slurp, read).map(|r| r.map(Val::from)))
+        Box::new(raw_input(cli.slurp, cli.lossy_utf8, read).map(|r| r.map(Val::from)))
+    } else if cli.lossy_utf8 {
+        let mut buf = Vec::new();
+        match read.read_to_end(&mut buf) {
+            Ok(_) => read_owned(cli, lossy_utf8(buf).into_bytes()),
+            Err(e) => Box::new(core::iter::once(Err(e))),
+        }
+    } else if cli.seq {
+        Box::new(collect_if(cli.slurp, json_seq(read)))
+    } else {
+        Box::new(collect_if(cli.slurp, json_read(read)))
+    }
+}
+
+/// Read JSON from an owned buffer.
+fn read_owned(cli: &Cli, buf: Vec<u8>) -> Box<dyn Iterator<Item = io::Result<Val>>> {
+    let read = io::Cursor::new(buf);
+    if cli.seq {
+        Box::new(collect_if(cli.slurp, json_seq(read)))
     } else {
         Box::new(collect_if(cli.slurp, json_read(read)))
     }
 }
 
 fn read_slice<'a>(cli: &Cli, slice: &'a [u8]) -> Box<dyn Iterator<Item = io::Result<Val>> + 'a> {
+    #[cfg(feature = "csv")]
+    if let Some(fmt) = cli.csv_format() {
+        return Box::new(csv_input(cli.slurp, fmt, io::BufReader::new(slice)));
+    }
     if cli.raw_input {
         let read = io::BufReader::new(slice);
-        Box::new(raw_input(cli.slurp, read).map(|r| r.map(Val::from)))
+        Box::new(raw_input(cli.slurp, cli.lossy_utf8, read).map(|r| r.map(Val::from)))
+    } else if cli.lossy_utf8 && core::str::from_utf8(slice).is_err() {
+        read_owned(cli, lossy_utf8(slice.to_vec()).into_bytes())
+    } else if cli.seq {
+        Box::new(collect_if(cli.slurp, json_seq(slice)))
     } else {
         Box::new(collect_if(cli.slurp, json_slice(slice)))
     }
 }
 
-fn raw_input<'a, R>(slurp: bool, mut read: R) -> impl Iterator<Item = io::Result<String>> + 'a
+fn raw_input<'a, R>(
+    slurp: bool,
+    lossy: bool,
+    mut read: R,
+) -> impl Iterator<Item = io::Result<String>> + 'a
 where
     R: BufRead + 'a,
 {
-    if slurp {
+    if slurp && lossy {
+        let mut buf = Vec::new();
+        let s = read.read_to_end(&mut buf).map(|_| lossy_utf8(buf));
+        Box::new(std::iter::once(s))
+    } else if slurp {
         let mut buf = String::new();
         let s = read.read_to_string(&mut buf).map(|_| buf);
         Box::new(std::iter::once(s))
+    } else if lossy {
+        // like `lines()`, remove a trailing "\n" or "\r\n"
+        let lines = read.split(b'\n').map(move |line| {
+            let mut line = line?;
+            if line.last() == Some(&b'\r') {
+                line.pop();
+            }
+            Ok(lossy_utf8(line))
+        });
+        Box::new(lines)
     } else {
         Box::new(read.lines()) as Box<dyn Iterator<Item = _>>
     }
 }
 
+/// Replace invalid UTF-8 sequences in `buf` by U+FFFD.
+fn lossy_utf8(buf: Vec<u8>) -> String {
+    String::from_utf8(buf).unwrap_or_else(|e| String::from_utf8_lossy(e.as_bytes()).into_owned())
+}
+
 fn collect_if<'a, T: FromIterator<T> + 'a, E: 'a>(
     slurp: bool,
     iter: impl Iterator<Item = Result<T, E>> + 'a,
@@ -451,7 +849,19 @@ impl Termination for Error {
                 5
             }
             Self::Jaq(e) => {
-                eprintln!("Error: {e}");
+                if let Some((exit_code, msg)) = e.as_halt() {
+                    match msg {
+                        // strings are printed verbatim, other values as JSON
+                        Some(Val::Str(s)) => eprint!("{s}"),
+                        Some(v) => eprintln!("{v}"),
+                        None => (),
+                    }
+                    return ExitCode::from(exit_code as u8);
+                }
+                match e.prelude_def() {
+                    Some(def) => eprintln!("Error: {e} (in builtin `{def}`)"),
+                    None => eprintln!("Error: {e}"),
+                }
                 5
             }
         };
@@ -492,14 +902,26 @@ fn run(
         //println!("Got {:?}", input);
         for output in filter.run((ctx.clone(), input)) {
             use jaq_core::ValT;
-            let output = output.map_err(Error::Jaq)?;
-            last = Some(output.as_bool());
+            let output = match output {
+                Err(e) if cli.stream_errors && e.as_halt().is_none() => stream_error(e),
+                output => {
+                    let output = output.map_err(Error::Jaq)?;
+                    last = Some(output.as_bool());
+                    output
+                }
+            };
             f(output)?;
         }
     }
     Ok(last)
 }
 
+/// Convert an error to a value that is printed by `--stream-errors`.
+fn stream_error(e: jaq_core::Error<Val>) -> Val {
+    let line = LINE.load(Ordering::Relaxed) as isize;
+    Val::object([("error", e.into_val()), ("line", Val::from(line))])
+}
+
 struct FormatterFn<F>(F);
 
 impl<F: Fn(&mut Formatter) -> fmt::Result> Display for FormatterFn<F> {
@@ -508,12 +930,16 @@ impl<F: Fn(&mut Formatter) -> fmt::Result> Display for FormatterFn<F> {
     }
 }
 
-struct PpOpts {
+#[derive(Clone)]
+struct PpOpts<'a> {
     compact: bool,
     indent: String,
+    escape_js: bool,
+    colors: &'a colors::Colors,
+    inline_width: Option<usize>,
 }
 
-impl PpOpts {
+impl PpOpts<'_> {
     fn indent(&self, f: &mut Formatter, level: usize) -> fmt::Result {
         if !self.compact {
             write!(f, "{}", self.indent.repeat(level))?;
@@ -527,6 +953,54 @@ impl PpOpts {
         }
         Ok(())
     }
+
+    /// Return options to print a value compactly if it fits into `inline_width`.
+    fn inline(&self, v: &Val) -> Option<Self> {
+        let width = self.inline_width.filter(|_| !self.compact)?;
+        let empty = match v {
+            Val::Arr(a) => a.is_empty(),
+            Val::Obj(o) => o.is_empty(),
+            _ => return None,
+        };
+        let opts = Self {
+            compact: true,
+            indent: String::new(),
+            escape_js: self.escape_js,
+            colors: self.colors,
+            inline_width: None,
+        };
+        if !empty {
+            let colors = &colors::Colors::none();
+            let colorless = PpOpts {
+                colors,
+                ..opts.clone()
+            };
+            let f = FormatterFn(|f: &mut Formatter| fmt_val(f, &colorless, 0, v));
+            // this stops printing as soon as the width is exceeded
+            fmt::write(&mut Width(width), format_args!("{f}")).ok()?;
+        }
+        Some(opts)
+    }
+
+    fn fmt_str(&self, f: &mut Formatter, s: &str) -> fmt::Result {
+        let js = |c| c == '\u{2028}' || c == '\u{2029}';
+        if !(self.escape_js && s.contains(js)) {
+            return jaq_json::fmt_str(f, s);
+        }
+        let s = FormatterFn(|f: &mut Formatter| jaq_json::fmt_str(f, s)).to_string();
+        let s = s.replace('\u{2028}', "\\u2028");
+        write!(f, "{}", s.replace('\u{2029}', "\\u2029"))
+    }
+}
+
+/// Remaining number of characters that may be written, failing when it is exceeded.
+struct Width(usize);
+
+impl fmt::Write for Width {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.0 = self.0.checked_sub(s.chars().count()).ok_or(fmt::Error)?;
+        Ok(())
+    }
 }
 
 fn fmt_seq<T, I, F>(fmt: &mut Formatter, opts: &PpOpts, level: usize, xs: I, f: F) -> fmt::Result
@@ -548,64 +1022,118 @@ where
 }
 
 fn fmt_val(f: &mut Formatter, opts: &PpOpts, level: usize, v: &Val) -> fmt::Result {
-    use yansi::Paint;
+    use colors::Paint;
+    let c = opts.colors;
+    if let Some(inline) = opts.inline(v) {
+        return fmt_val(f, &inline, level, v);
+    }
     match v {
-        Val::Null | Val::Bool(_) | Val::Int(_) | Val::Float(_) | Val::Num(_) => v.fmt(f),
-        Val::Str(_) => write!(f, "{}", v.green()),
+        Val::Null => Paint(&c.null, v).fmt(f),
+        Val::Bool(false) => Paint(&c.false_, v).fmt(f),
+        Val::Bool(true) => Paint(&c.true_, v).fmt(f),
+        Val::Int(_) | Val::Float(_) | Val::Num(_) => Paint(&c.num, v).fmt(f),
+        Val::Str(s) => {
+            let s = FormatterFn(|f: &mut Formatter| opts.fmt_str(f, s));
+            Paint(&c.str, s).fmt(f)
+        }
         Val::Arr(a) => {
-            '['.bold().fmt(f)?;
+            Paint(&c.arr, '[').fmt(f)?;
             if !a.is_empty() {
                 fmt_seq(f, opts, level, &**a, |f, x| fmt_val(f, opts, level + 1, x))?;
             }
-            ']'.bold().fmt(f)
+            Paint(&c.arr, ']').fmt(f)
         }
         Val::Obj(o) => {
-            '{'.bold().fmt(f)?;
+            Paint(&c.obj, '{').fmt(f)?;
             if !o.is_empty() {
                 fmt_seq(f, opts, level, &**o, |f, (k, val)| {
-                    write!(f, "{:?}:", k.bold())?;
+                    let k = FormatterFn(|f: &mut Formatter| opts.fmt_str(f, k));
+                    write!(f, "{}:", Paint(&c.key, k))?;
                     if !opts.compact {
                         write!(f, " ")?;
                     }
                     fmt_val(f, opts, level + 1, val)
                 })?;
             }
-            '}'.bold().fmt(f)
+            Paint(&c.obj, '}').fmt(f)
         }
     }
 }
 
-fn print(writer: &mut impl Write, cli: &Cli, val: &Val) -> io::Result<()> {
-    let f = |f: &mut Formatter| fmt_val_root(f, cli, val);
-    write!(writer, "{}", FormatterFn(f))
+/// Printer of values to one output.
+struct Printer {
+    /// Number of values printed so far,
+    /// used by `--no-trailing-newline` and `--flush-every`.
+    printed: usize,
+    /// Flush the output after every `flush_every` values, or never if it is 0.
+    flush_every: usize,
+}
+
+impl Printer {
+    fn new(flush_every: usize) -> Self {
+        Self {
+            printed: 0,
+            flush_every,
+        }
+    }
+
+    /// Write a value to a buffered writer, then flush it if the flush cadence says so.
+    ///
+    /// The value is written piece by piece while it is traversed,
+    /// so printing a large value does not require constructing its string representation.
+    /// Flushing makes the value visible to readers as soon as it has been printed.
+    fn print(&mut self, writer: &mut impl Write, cli: &Cli, val: &Val) -> io::Result<()> {
+        self.printed += 1;
+        // print the newline *before* every value but the first
+        if cli.no_trailing_newline && self.printed > 1 {
+            writeln!(writer)?;
+        }
+        let f = |f: &mut Formatter| fmt_val_root(f, cli, val);
+        write!(writer, "{}", FormatterFn(f))?;
+        if self.flush_every > 0 && self.printed % self.flush_every == 0 {
+            writer.flush()?;
+        }
+        Ok(())
+    }
 }
 
 fn fmt_val_root(f: &mut Formatter, cli: &Cli, val: &Val) -> fmt::Result {
+    if cli.seq {
+        write!(f, "{}", RS as char)?;
+    }
     match val {
         Val::Str(s) if cli.raw_output => write!(f, "{s}")?,
         _ => {
             let opts = PpOpts {
                 compact: cli.compact_output,
-                indent: if cli.tab {
+                indent: if let Some(indent) = &cli.indent_str {
+                    indent.clone()
+                } else if cli.tab {
                     String::from("\t")
                 } else {
                     " ".repeat(cli.indent)
                 },
+                escape_js: cli.escape_js,
+                colors: &cli.colors,
+                inline_width: cli.inline_width,
             };
             fmt_val(f, &opts, 0, val)?;
         }
     };
-    if !cli.join_output {
+    if !cli.join_output && !cli.no_trailing_newline {
         writeln!(f)?;
     }
     Ok(())
 }
 
-fn with_stdout<T>(f: impl FnOnce(&mut io::StdoutLock) -> Result<T, Error>) -> Result<T, Error> {
-    let mut stdout = io::stdout().lock();
-    let y = f(&mut stdout)?;
+type Stdout = io::BufWriter<io::StdoutLock<'static>>;
+
+fn with_stdout<T>(f: impl FnOnce(&mut Stdout) -> Result<T, Error>) -> Result<T, Error> {
+    let mut stdout = io::BufWriter::new(io::stdout().lock());
+    // flush also on errors, such as when `halt` stops the filter
+    let y = f(&mut stdout);
     stdout.flush()?;
-    Ok(y)
+    y
 }
 
 type StringColors = Vec<(String, Option<Color>)>;
@@ -687,7 +1215,11 @@ fn report_parse(code: &str, (expected, found): load::parse::Error<&str>) -> Repo
 
 fn report_compile(code: &str, (found, undefined): compile::Error<&str>) -> Report {
     let found_range = load::span(code, found);
-    let message = format!("undefined {}", undefined.as_str());
+    let message = match undefined {
+        compile::Undefined::Path => format!("expected {}", undefined.as_str()),
+        compile::Undefined::Duplicate(_) => format!("duplicate {}", undefined.as_str()),
+        _ => format!("undefined {}", undefined.as_str()),
+    };
     let found = [(message.clone(), None)].into();
 
     Report {
@@ -762,3 +1294,31 @@ fn run_tests(file: std::fs::File) -> ExitCode {
         ExitCode::SUCCESS
     }
 }
+
+#[test]
+fn flush_every() {
+    /// Writer that discards its input and counts how often it was flushed.
+    struct Flushes(usize);
+    impl Write for Flushes {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            Ok(buf.len())
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            self.0 += 1;
+            Ok(())
+        }
+    }
+
+    let cli = Cli::parse_from(["jaq"]);
+    let flushes = |n| {
+        let mut printer = Printer::new(n);
+        let mut out = Flushes(0);
+        for i in 0..7 {
+            printer.print(&mut out, &cli, &Val::from(i)).unwrap();
+        }
+        out.0
+    };
+    assert_eq!(flushes(1), 7);
+    assert_eq!(flushes(3), 2);
+    assert_eq!(flushes(0), 0);
+}
@@ -0,0 +1,122 @@
+//! Interactive read-eval-print loop (REPL).
+use super::{compile_errors, load_errors, Cli, Error};
+use jaq_core::{compile, load, Ctx, Native, RcIter};
+use jaq_json::Val;
+use std::io::{self, BufRead};
+use std::path::Path;
+use std::process::{ExitCode, Termination};
+
+type Compiler<'s> = compile::Compiler<&'s str, Native<Val>>;
+
+/// Run expressions read line by line from standard input.
+///
+/// Expressions are run on the current input values, which are
+/// initially the values in the given files (or `null` if no files are given).
+/// A line `:load FILE` replaces the current input values by the values in `FILE`, and
+/// an expression starting with `|` is run on the outputs of the previous expression.
+///
+/// Errors are printed, but do not stop the REPL.
+pub fn run(cli: &Cli, vars: &[String], ctx: Vec<Val>, files: &[String]) -> Result<ExitCode, Error> {
+    use load::{Arena, File, Loader};
+
+    let vars = super::global_vars(vars);
+    let arena = Arena::default();
+    // compile the definitions of the standard library only once
+    let loader = Loader::new(jaq_std::defs().chain(jaq_json::defs()));
+    let main = File {
+        path: "<repl>".into(),
+        code: ".",
+    };
+    let mut mods = loader
+        .load(&arena, main)
+        .map_err(|e| Error::Report(load_errors(e)))?;
+    mods.pop();
+    let prelude = super::compiler(&vars).with_mods(mods);
+    let prelude = prelude.map_err(|e| Error::Report(compile_errors(e)))?;
+
+    let mut inputs = if files.is_empty() {
+        Vec::from([Val::Null])
+    } else {
+        let vals = files.iter().map(|path| read(cli, path));
+        vals.collect::<Result<Vec<_>, _>>()?.concat()
+    };
+    let mut prev = Vec::new();
+
+    let prompt = atty::is(atty::Stream::Stdin);
+    let mut stdin = io::stdin().lock();
+    let mut line = String::new();
+    loop {
+        if prompt {
+            eprint!("> ");
+        }
+        line.clear();
+        if stdin.read_line(&mut line)? == 0 {
+            break;
+        }
+        let line = line.trim();
+        let result = if let Some(path) = line.strip_prefix(":load ") {
+            read(cli, path.trim()).map(|vals| inputs = vals)
+        } else if line.is_empty() {
+            Ok(())
+        } else {
+            let (code, xs) = match line.strip_prefix('|') {
+                Some(code) => (code, &prev),
+                None => (line, &inputs),
+            };
+            let ctx = ctx.clone();
+            eval(cli, prelude.clone(), ctx, code, xs).map(|ys| prev = ys)
+        };
+        if let Err(e) = result {
+            // `halt` and `halt_error` end the REPL
+            let halt = matches!(&e, Error::Jaq(e) if e.as_halt().is_some());
+            let exit = e.report();
+            if halt {
+                return Ok(exit);
+            }
+        }
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Read all input values from a file.
+fn read(cli: &Cli, path: &str) -> Result<Vec<Val>, Error> {
+    let file = super::load_file(Path::new(path)).map_err(|e| Error::Io(Some(path.into()), e))?;
+    let vals = super::read_slice(cli, &file).collect::<io::Result<_>>();
+    vals.map_err(|e| Error::Io(Some(path.into()), e))
+}
+
+/// Compile an expression and run it on the given values, printing and returning its outputs.
+fn eval(
+    cli: &Cli,
+    prelude: Compiler,
+    mut ctx: Vec<Val>,
+    code: &str,
+    xs: &[Val],
+) -> Result<Vec<Val>, Error> {
+    use load::{Arena, File, Loader};
+
+    let arena = Arena::default();
+    let loader = Loader::new([]).with_std_read(&cli.search_paths);
+    let path = "<repl>".into();
+    let mods = loader.load(&arena, File { path, code });
+    let mods = mods.map_err(|e| Error::Report(load_errors(e)))?;
+    let vals = super::import_vals(&mods, &cli.search_paths).map_err(Error::Report)?;
+    ctx.extend(vals);
+    let filter = prelude.compile(mods);
+    let filter = filter.map_err(|e| Error::Report(compile_errors(e)))?;
+
+    let inputs = RcIter::new(Box::new(core::iter::empty()) as Box<dyn Iterator<Item = _>>);
+    let mut ys = Vec::new();
+    let mut printer = super::Printer::new(cli.flush_every);
+    super::with_stdout(|out| {
+        for x in xs {
+            for y in filter.run((Ctx::new(ctx.clone(), &inputs), x.clone())) {
+                let y = y.map_err(Error::Jaq)?;
+                printer.print(out, cli, &y)?;
+                ys.push(y);
+            }
+        }
+        Ok(())
+    })?;
+    Ok(ys)
+}
@@ -1,3 +1,6 @@
+// expected outputs may contain characters that jq does not escape, such as zero-width spaces
+#![allow(clippy::invisible_characters)]
+
 use std::{env, io, process, str};
 
 fn golden_test(args: &[&str], input: &str, out_ex: &str) -> io::Result<()> {
@@ -8,7 +11,11 @@ fn golden_test(args: &[&str], input: &str, out_ex: &str) -> io::Result<()> {
         .spawn()?;
 
     use io::Write;
-    child.stdin.take().unwrap().write_all(input.as_bytes())?;
+    // the child may exit without reading its input, such as with `--fmt`
+    match child.stdin.take().unwrap().write_all(input.as_bytes()) {
+        Err(e) if e.kind() != io::ErrorKind::BrokenPipe => return Err(e),
+        _ => (),
+    }
     let output = child.wait_with_output()?;
     assert!(output.status.success());
 
@@ -36,6 +43,13 @@ test!(no_args, &[], "[0, 1]", "[\n  0,\n  1\n]");
 test!(one, &["1"], "0", "1");
 test!(sparse, &["."], "[2,3]", "[\n  2,\n  3\n]");
 
+test!(
+    non_finite,
+    &["-c", "[nan, infinite, -infinite]"],
+    "0",
+    "[null,null,null]"
+);
+
 test!(
     arg,
     &["--arg", "x", "y", "--arg", "a", "b", "$x + $a"],
@@ -43,6 +57,357 @@ test!(
     "\"yb\""
 );
 
+test!(
+    argjson,
+    &["-c", "--argjson", "x", "{\"a\": [1, 2]}", "$x, $ARGS.named"],
+    "0",
+    r#"{"a":[1,2]}
+{"x":{"a":[1,2]}}"#
+);
+
+#[test]
+fn argjson_invalid() -> io::Result<()> {
+    let output = process::Command::new(env!("CARGO_BIN_EXE_jaq"))
+        .args(["-n", "--argjson", "x", "[1, 2", "$x"])
+        .output()?;
+    assert_eq!(output.status.code(), Some(2));
+    assert!(output.stdout.is_empty());
+    let err = str::from_utf8(&output.stderr).expect("invalid UTF-8 in error");
+    assert!(err.starts_with("Error: --argjson x: "));
+    Ok(())
+}
+
+#[test]
+fn env_copy() -> io::Result<()> {
+    let filter = "$ENV as $e | ($e | .FOO = \"x\" | .FOO), $e.FOO, $ENV.FOO, env.FOO";
+    let output = process::Command::new(env!("CARGO_BIN_EXE_jaq"))
+        .args(["-nc", filter])
+        .env("FOO", "a")
+        .output()?;
+    assert!(output.status.success());
+    // modifying `$ENV` yields a modified copy and leaves `$ENV` and `env` unchanged
+    let out = String::from_utf8(output.stdout).expect("invalid UTF-8 in output");
+    assert_eq!(out.replace('\r', ""), "\"x\"\n\"a\"\n\"a\"\n\"a\"\n");
+    Ok(())
+}
+
+fn checked(filter: &str) -> io::Result<(Option<i32>, String)> {
+    let output = process::Command::new(env!("CARGO_BIN_EXE_jaq"))
+        .args(["--check", filter])
+        .output()?;
+    assert!(output.stdout.is_empty());
+    let err = String::from_utf8(output.stderr).expect("invalid UTF-8 in error");
+    Ok((output.status.code(), err))
+}
+
+#[test]
+fn check() -> io::Result<()> {
+    assert_eq!(checked("def f: 1; f | .a |= 2")?, (Some(0), String::new()));
+    // all errors are reported, not only the first one
+    let (code, err) = checked("foo, 1 |= 2")?;
+    assert_eq!(code, Some(3));
+    assert_eq!(err.matches("Error: ").count(), 2);
+    // global variables such as `$ENV` are always bound
+    let (code, err) = checked("$ENV, $__loc__, $x")?;
+    assert_eq!(code, Some(3));
+    assert_eq!(err.matches("Error: ").count(), 1);
+    assert!(err.starts_with("Error: undefined variable"));
+    Ok(())
+}
+
+/// Run jaq on input that may be invalid UTF-8, returning its output if it succeeded.
+fn run_bytes(args: &[&str], input: &[u8]) -> io::Result<Option<String>> {
+    use io::Write;
+    let mut child = process::Command::new(env!("CARGO_BIN_EXE_jaq"))
+        .args(args)
+        .stdin(process::Stdio::piped())
+        .stdout(process::Stdio::piped())
+        .stderr(process::Stdio::null())
+        .spawn()?;
+    child.stdin.take().unwrap().write_all(input)?;
+    let output = child.wait_with_output()?;
+    let out = String::from_utf8(output.stdout).expect("invalid UTF-8 in output");
+    Ok(output.status.success().then_some(out.replace('\r', "")))
+}
+
+#[test]
+fn lossy_utf8() -> io::Result<()> {
+    let json = b"\"a\xffb\" \"c\"";
+    assert_eq!(run_bytes(&["-c", "."], json)?, None);
+    let out = run_bytes(&["-c", "--lossy-utf8", "."], json)?;
+    assert_eq!(out.as_deref(), Some("\"a\u{FFFD}b\"\n\"c\"\n"));
+
+    let raw = b"a\xffb\r\nc\n";
+    assert_eq!(run_bytes(&["-R", "."], raw)?, None);
+    let out = run_bytes(&["-c", "-R", "--lossy-utf8", "."], raw)?;
+    assert_eq!(out.as_deref(), Some("\"a\u{FFFD}b\"\n\"c\"\n"));
+    let out = run_bytes(&["-c", "-Rs", "--lossy-utf8", "."], raw)?;
+    assert_eq!(out.as_deref(), Some("\"a\u{FFFD}b\\r\\nc\\n\"\n"));
+    Ok(())
+}
+
+#[test]
+fn concat_numbers() -> io::Result<()> {
+    let run = |input: &str| run_bytes(&["-c", "."], input.as_bytes());
+    assert_eq!(run("1 2")?.as_deref(), Some("1\n2\n"));
+    assert_eq!(run("1.5 2")?.as_deref(), Some("1.5\n2\n"));
+    assert_eq!(run("123")?.as_deref(), Some("123\n"));
+    assert_eq!(run("[1]2")?.as_deref(), Some("[1]\n2\n"));
+    // numbers are read maximally, failing only on malformed syntax
+    assert_eq!(run("1e ")?, None);
+    assert_eq!(run("1.5.5")?, None);
+    Ok(())
+}
+
+#[test]
+fn stream_errors() -> io::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let file = dir.path().join("in.json");
+    std::fs::write(&file, "1\n\"a\"\n{}\n3\n")?;
+    let run = |args: &[&str]| {
+        let mut cmd = process::Command::new(env!("CARGO_BIN_EXE_jaq"));
+        let out = cmd.args(args).arg(&file).output()?;
+        let stdout = String::from_utf8(out.stdout).expect("invalid UTF-8 in output");
+        io::Result::Ok((out.status.code(), stdout.replace('\r', "")))
+    };
+
+    // without `--stream-errors`, the first error stops jaq
+    let (code, out) = run(&["-c", ". + 1"])?;
+    assert_eq!((code, out.as_str()), (Some(5), "2\n"));
+
+    let (code, out) = run(&["-c", "--stream-errors", ". + 1"])?;
+    let lines = [
+        r#"2"#,
+        r#"{"error":"cannot calculate string (\"a\") + number (1)","line":2}"#,
+        r#"{"error":"cannot calculate object ({}) + number (1)","line":3}"#,
+        r#"4"#,
+    ];
+    assert_eq!(
+        (code, out.lines().collect::<Vec<_>>()),
+        (Some(0), lines.into())
+    );
+
+    let (_, out) = run(&["-c", "--stream-errors", "select(. == 3) | error({x: .})"])?;
+    assert_eq!(out, "{\"error\":{\"x\":3},\"line\":4}\n");
+    Ok(())
+}
+
+#[test]
+fn input_line_number() -> io::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let (a, b) = (dir.path().join("a.json"), dir.path().join("b.json"));
+    std::fs::write(&a, "1\n\"x\"\n")?;
+    std::fs::write(&b, "[\n2]\n\n3")?;
+    let output = process::Command::new(env!("CARGO_BIN_EXE_jaq"))
+        .args([
+            "-c".as_ref(),
+            "[., input_line_number]".as_ref(),
+            a.as_os_str(),
+            b.as_os_str(),
+        ])
+        .output()?;
+    assert!(output.status.success());
+    let out = String::from_utf8(output.stdout).expect("invalid UTF-8 in output");
+    // line numbers start again from zero for every file
+    let lines = ["[1,1]", "[\"x\",2]", "[[2],2]", "[3,3]"];
+    assert_eq!(out.replace('\r', "").lines().collect::<Vec<_>>(), lines);
+    Ok(())
+}
+
+// standard input counts lines in the same way as files
+#[test]
+fn input_line_number_stdin() -> io::Result<()> {
+    let input = b"1\n\"x\"\n[\n2]\n\n3";
+    let out = run_bytes(&["-c", "[., input_line_number]"], input)?;
+    let lines = ["[1,1]", "[\"x\",2]", "[[2],4]", "[3,5]"];
+    let out = out.expect("jaq failed");
+    assert_eq!(out.lines().collect::<Vec<_>>(), lines);
+    Ok(())
+}
+
+#[test]
+fn trailing_newline() -> io::Result<()> {
+    let out = run_bytes(&["-c", ".[]"], b"[1, [2]]")?;
+    assert_eq!(out.as_deref(), Some("1\n[2]\n"));
+    let out = run_bytes(&["-c", "--no-trailing-newline", ".[]"], b"[1, [2]]")?;
+    assert_eq!(out.as_deref(), Some("1\n[2]"));
+    let out = run_bytes(&["--no-trailing-newline", ".[]"], b"[]")?;
+    assert_eq!(out.as_deref(), Some(""));
+    Ok(())
+}
+
+// flushing less often does not change the output
+#[test]
+fn flush_every() -> io::Result<()> {
+    for n in ["0", "2"] {
+        let out = run_bytes(&["-c", "--flush-every", n, ".[]"], b"[1, [2], 3]")?;
+        assert_eq!(out.as_deref(), Some("1\n[2]\n3\n"));
+    }
+    let out = run_bytes(
+        &["--flush-every", "2", "--no-trailing-newline", ".[]"],
+        b"[1, 2, 3]",
+    )?;
+    assert_eq!(out.as_deref(), Some("1\n2\n3"));
+    Ok(())
+}
+
+// output that has not been flushed yet is printed before halting
+#[test]
+fn flush_every_halt() -> io::Result<()> {
+    let out = run_bytes(&["-n", "--flush-every", "0", "1, halt"], b"")?;
+    assert_eq!(out.as_deref(), Some("1\n"));
+    let out = run_bytes(&["-n", "--flush-every", "2", "1, 2, 3, halt"], b"")?;
+    assert_eq!(out.as_deref(), Some("1\n2\n3\n"));
+    let output = process::Command::new(env!("CARGO_BIN_EXE_jaq"))
+        .args(["-n", "--flush-every", "0", r#"1, ("oops" | halt_error(1))"#])
+        .output()?;
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        (&*output.stdout, &*output.stderr),
+        (&b"1\n"[..], &b"oops"[..])
+    );
+    Ok(())
+}
+
+fn colored(jq_colors: Option<&str>) -> io::Result<String> {
+    let mut cmd = process::Command::new(env!("CARGO_BIN_EXE_jaq"));
+    cmd.args([
+        "-nc",
+        "--color",
+        "always",
+        r#"[null, false, true, 1, "a", {"k": {}}]"#,
+    ]);
+    match jq_colors {
+        Some(spec) => cmd.env("JQ_COLORS", spec),
+        None => cmd.env_remove("JQ_COLORS"),
+    };
+    let output = cmd.output()?;
+    assert!(output.status.success());
+    Ok(String::from_utf8(output.stdout).expect("invalid UTF-8 in output"))
+}
+
+/// Color `s` with the SGR parameters `c`.
+fn sgr(c: &str, s: &str) -> String {
+    format!("\x1b[{c}m{s}\x1b[0m")
+}
+
+#[test]
+fn jq_colors() -> io::Result<()> {
+    let spec = "1;30:0;31:0;32:0;33:0;34:1;35:1;36:1;37";
+    let [null, f, t, n, s, a, o, k] = [
+        "1;30", "0;31", "0;32", "0;33", "0;34", "1;35", "1;36", "1;37",
+    ];
+    let k = sgr(k, "\"k\"");
+    let obj = [
+        sgr(o, "{"),
+        k,
+        ":".into(),
+        sgr(o, "{"),
+        sgr(o, "}"),
+        sgr(o, "}"),
+    ];
+    let f = sgr(f, "false");
+    let vals = [
+        sgr(null, "null"),
+        f,
+        sgr(t, "true"),
+        sgr(n, "1"),
+        sgr(s, "\"a\""),
+    ];
+    let vals = vals.join(",") + "," + &obj.concat();
+    let out = sgr(a, "[") + &vals + &sgr(a, "]") + "\n";
+    assert_eq!(colored(Some(spec))?, out);
+    Ok(())
+}
+
+#[test]
+fn jq_colors_partial() -> io::Result<()> {
+    // omitted fields keep their default colors
+    let default = colored(None)?;
+    let out = default.replacen("null", &sgr("0;31", "null"), 1);
+    let out = out.replacen("false", &sgr("0;32", "false"), 1);
+    assert_eq!(colored(Some("0;31:0;32"))?, out);
+    Ok(())
+}
+
+#[test]
+fn jq_colors_malformed() -> io::Result<()> {
+    let default = colored(None)?;
+    assert_eq!(colored(Some("0;31:red"))?, default);
+    assert_eq!(colored(Some("0:0:0:0:0:0:0:0:0"))?, default);
+    Ok(())
+}
+
+fn halted(filter: &str) -> io::Result<(Option<i32>, String)> {
+    let output = process::Command::new(env!("CARGO_BIN_EXE_jaq"))
+        .args(["-n", filter])
+        .output()?;
+    assert!(output.stdout.is_empty());
+    let err = String::from_utf8(output.stderr).expect("invalid UTF-8 in error");
+    Ok((output.status.code(), err))
+}
+
+#[test]
+fn halt_error_str() -> io::Result<()> {
+    let (code, err) = halted(r#""oops\n" | halt_error"#)?;
+    assert_eq!((code, &*err), (Some(5), "oops\n"));
+    let (code, err) = halted(r#""oops" | halt_error(1)"#)?;
+    assert_eq!((code, &*err), (Some(1), "oops"));
+    Ok(())
+}
+
+#[test]
+fn halt_error_json() -> io::Result<()> {
+    let (code, err) = halted("{code: 1} | halt_error(2)")?;
+    assert_eq!((code, &*err), (Some(2), "{\"code\":1}\n"));
+    Ok(())
+}
+
+#[test]
+fn halt() -> io::Result<()> {
+    assert_eq!(halted("halt, 1")?, (Some(0), String::new()));
+    Ok(())
+}
+
+// errors that occur in definitions of the standard library name the definition,
+// unless they occur in filter arguments given by the user
+#[test]
+fn error_in_builtin() -> io::Result<()> {
+    let (code, err) = halted("1 | map(.)")?;
+    let msg = "Error: cannot use 1 as iterable (array or object) (in builtin `map/1`)\n";
+    assert_eq!((code, &*err), (Some(5), msg));
+    let (_, err) = halted(r#"[1] | map(error("x"))"#)?;
+    assert_eq!(err, "Error: \"x\"\n");
+    let (_, err) = halted(r#""x" | error"#)?;
+    assert_eq!(err, "Error: \"x\"\n");
+    Ok(())
+}
+
+/// Pretty-print a large array and consume the output while it is produced.
+#[test]
+fn pretty_large() -> io::Result<()> {
+    use io::BufRead;
+    let n = 1_000_000;
+    let mut child = process::Command::new(env!("CARGO_BIN_EXE_jaq"))
+        .args(["-n", &format!("[range({n})]")])
+        .stdout(process::Stdio::piped())
+        .spawn()?;
+    let stdout = io::BufReader::new(child.stdout.take().unwrap());
+    let mut lines = 0;
+    for (i, line) in stdout.lines().enumerate() {
+        let line = line?;
+        match i {
+            0 => assert_eq!(line, "["),
+            i if i <= n => assert_eq!(line.trim_end_matches(','), format!("  {}", i - 1)),
+            _ => assert_eq!(line, "]"),
+        }
+        lines += 1;
+    }
+    assert!(child.wait()?.success());
+    assert_eq!(lines, n + 2);
+    Ok(())
+}
+
 test!(
     compact,
     &["-c", "."],
@@ -52,6 +417,58 @@ test!(
 {"a":1,"b":["c"]}"#
 );
 
+test!(
+    escape_js,
+    &["-c", "--escape-js", "., tojson"],
+    "{\"a\u{2028}\": \"b\\u2029\"}",
+    r#"{"a\u2028":"b\u2029"}
+"{\"a\u2028\":\"b\u2029\"}""#
+);
+
+test!(
+    input_eof,
+    &["-nc", "[limit(2; repeat(input))], try input catch ."],
+    "1 2",
+    r#"[1,2]
+"No more inputs""#
+);
+
+test!(
+    indent_str,
+    &["--indent-str", "   ", "."],
+    "[{\"a\": 1}]",
+    "[\n   {\n      \"a\": 1\n   }\n]"
+);
+
+// `[1,2,3]` takes 7 characters, whereas `[1,2,3,4,5]` takes 11
+test!(
+    inline_width,
+    &["--inline-width", "10", "."],
+    "{\"a\": [1, 2, 3], \"b\": [1, 2, 3, 4, 5], \"c\": [[]]}",
+    r#"{
+  "a": [1,2,3],
+  "b": [
+    1,
+    2,
+    3,
+    4,
+    5
+  ],
+  "c": [[]]
+}"#
+);
+
+test!(
+    fmt,
+    &[
+        "--fmt",
+        "def f:  .+1 ;  # increment\n.[]|(f,-(-1))?|{a:(1,2),\"b c\"}",
+    ],
+    "0",
+    r#"def f: . + 1;
+.[] | (f, - -1)? | {a: (1, 2), "b c"}"#
+);
+
 test!(
     inputs,
     &["-c", r#"{".": .}, {input: input}"#],
@@ -62,6 +479,18 @@ test!(
 {"input":3}"#
 );
 
+// `,` evaluates its left side first, so `input` is read only after it
+test!(input_after, &["-c", "., input"], "1 2", "1\n2");
+test!(input_before, &["-c", "input, ."], "1 2", "2\n1");
+
+// errors, such as the undefined filter `foo`, do not stop the REPL
+test!(
+    repl,
+    &["--repl", "-c"],
+    "1 + 1\n:load tests/data.json\n[., 0]\n| .[0] * 10\nfoo\n\n. + 1",
+    "2\n[1,0]\n[2,0]\n10\n20\n2\n3"
+);
+
 test!(
     null_input,
     &["-nc", r#"{".": .}, {inputs: [inputs]}"#],
@@ -70,6 +499,56 @@ test!(
 {"inputs":[0,1,2,3]}"#
 );
 
+// `limit` and `first` read no more inputs than they yield
+test!(
+    limit_inputs,
+    &["-nc", "[limit(2; inputs)], [inputs]"],
+    "1 2 3 4",
+    "[1,2]\n[3,4]"
+);
+test!(
+    first_inputs,
+    &["-nc", "first(inputs), [inputs]"],
+    "1 2 3 4",
+    "1\n[2,3,4]"
+);
+
+// numbers embedded via `tojson` are written like numbers at the top level,
+// both when they pass through unchanged and when they are calculated
+// (unlike jq, jaq writes integral floats with `.0`, see "Numbers" in the README)
+test!(
+    tojson_nums,
+    &[
+        "-rc",
+        "., tojson, @json, (map(. * 1), [3.0 * 2, 10 / 2, 1 / 3, 1e1000 * 1] | ., tojson)",
+    ],
+    "[1, 1.0, 1.50, 1e3, 100000000000000000001, 2.5e-7]",
+    "[1,1.0,1.50,1e3,100000000000000000001,2.5e-7]
+[1,1.0,1.50,1e3,100000000000000000001,2.5e-7]
+[1,1.0,1.50,1e3,100000000000000000001,2.5e-7]
+[1,1.0,1.5,1000.0,1e20,2.5e-7]
+[1,1.0,1.5,1000.0,1e20,2.5e-7]
+[6.0,5.0,0.3333333333333333,null]
+[6.0,5.0,0.3333333333333333,null]"
+);
+
+test!(bom, &["-c", "."], "\u{feff}{\"a\": 1}", r#"{"a":1}"#);
+
+test!(
+    blank_lines,
+    &["-c", "."],
+    "\n\n{\"a\": 1}\n\n\t \r\n2\r\n\n 3\n\n",
+    "{\"a\":1}\n2\n3"
+);
+
+// invalid and truncated records are skipped
+test!(
+    seq,
+    &["-c", "--seq", "."],
+    "\u{1e}{\"a\": 1}\n\u{1e}[1, 2\n\u{1e}2\n\u{1e}3",
+    "\u{1e}{\"a\":1}\n\u{1e}2"
+);
+
 const ONE23: &str = "One\nTwo\nThree\n";
 
 test!(raw_input_slurp, &["-Rs"], ONE23, r#""One\nTwo\nThree\n""#);
@@ -83,6 +562,45 @@ test!(
 "Three""#
 );
 
+test!(
+    raw_input_no_newline,
+    &["-R"],
+    "One\nTwo",
+    r#""One"
+"Two""#
+);
+
+test!(raw_input_empty_line, &["-R"], "One\n\n", "\"One\"\n\"\"");
+
+test!(raw_input_empty, &["-R"], "", "");
+
+test!(raw_input_empty_slurp, &["-Rs"], "", r#""""#);
+
+const CSV: &str = "a,b\n\"x, \"\"y\"\"\",\"multi\nline\"\n1,\n";
+
+test!(
+    csv_input,
+    &["-c", "--csv-input", "."],
+    CSV,
+    r#"["a","b"]
+["x, \"y\"","multi\nline"]
+["1",""]"#
+);
+
+test!(
+    csv_input_slurp,
+    &["-c", "--csv-input", "--csv-separator", ";", "-s", "."],
+    "a;b\nc;d",
+    r#"[["a","b"],["c","d"]]"#
+);
+
+test!(
+    tsv_input,
+    &["-c", "--tsv-input", "."],
+    "a\\tb\tc\n",
+    r#"["a\tb","c"]"#
+);
+
 test!(
     fmt_str,
     &[],
@@ -90,6 +608,51 @@ test!(
     r#""\u0000​\r\t\n asdf""#
 );
 
+// `-r` only affects top-level strings, not the formatting of other values
+test!(
+    raw_output_pretty,
+    &["-r", ".[]"],
+    r#"["a", {"b": ["c"]}, "d"]"#,
+    r#"a
+{
+  "b": [
+    "c"
+  ]
+}
+d"#
+);
+
+test!(
+    raw_output_compact,
+    &["-rc", ".[]"],
+    r#"["a", {"b": ["c"]}, "d"]"#,
+    r#"a
+{"b":["c"]}
+d"#
+);
+
+const ROW: &str = r#"["a b", "c,\"d", 1]"#;
+
+test!(
+    raw_output_formats,
+    &["-r", "@csv, @tsv, @sh, @base64, @uri"],
+    ROW,
+    r#""a b","c,""d",1
+a b	c,"d	1
+'a b' 'c,"d' 1
+WyJhIGIiLCJjLFwiZCIsMV0=
+%5B%22a%20b%22%2C%22c%2C%5C%22d%22%2C1%5D"#
+);
+
+test!(
+    formats,
+    &["@csv, @tsv, @sh"],
+    ROW,
+    r#""\"a b\",\"c,\"\"d\",1"
+"a b\tc,\"d\t1"
+"'a b' 'c,\"d' 1""#
+);
+
 test!(
     mods,
     &["-c", "-L", "tests", r#"include "a"; [a, data]"#],
@@ -0,0 +1,105 @@
+//! Comparison of jaq's output with the output of jq.
+//!
+//! The tests in this file are only run if the environment variable `JAQ_COMPARE_JQ`
+//! is set to the path of a jq binary, e.g. `JAQ_COMPARE_JQ=jq cargo test --test jq`.
+
+use std::{env, io::Write, process, str};
+
+/// Pairs of filters and inputs on which jaq and jq should behave the same.
+const CASES: &[(&str, &str)] = &[
+    // arithmetic
+    ("1 + 2 * 3", "null"),
+    ("10 / 4", "null"),
+    ("7 % 3, -7 % 3", "null"),
+    ("1 - -1, - -1", "null"),
+    (".a + .b", r#"{"a": 1, "b": 2}"#),
+    ("[1, 2] + [3], [1, 2, 1] - [1]", "null"),
+    (r#""ab" * 0, "a" + "b""#, "null"),
+    ("{a: 1} + {b: 2}, {a: {b: 1}} * {a: {c: 2}}", "null"),
+    ("[.[] | -. ]", "[1, -2, 0.5]"),
+    ("1 < 2, [] < {}, null < false, \"a\" < \"b\"", "null"),
+    ("[1, 2] == [1, 2], {a: 1} != {a: 1}", "null"),
+    // paths
+    (".a.b", r#"{"a": {"b": 1}}"#),
+    (".[0], .[-1], .[1:], .[:-1]", "[1, 2, 3]"),
+    (".[2:4], .[:1]", r#""abcdef""#),
+    (r#".["a b"], ."c""#, r#"{"a b": 1, "c": 2}"#),
+    (".a?, .[0]?", "1"),
+    ("[..]", "[[1], {\"a\": 2}]"),
+    (".a.b |= . + 1", r#"{"a": {"b": 1}}"#),
+    (".[] += 1", "[1, 2]"),
+    ("del(.a, .c)", r#"{"a": 1, "b": 2, "c": 3}"#),
+    ("[paths]", r#"{"a": [1, {"b": 2}]}"#),
+    ("to_entries", r#"{"a": 1, "b": 2}"#),
+    // generators
+    ("[range(5)], [range(1; 10; 3)]", "null"),
+    ("[.[] | select(. > 1)]", "[1, 2, 3]"),
+    ("[limit(3; repeat(1))]", "null"),
+    ("first(range(10)), [first(empty)]", "null"),
+    ("reduce .[] as $x (0; . + $x)", "[1, 2, 3]"),
+    ("[foreach .[] as $x (0; . + $x)]", "[1, 2, 3]"),
+    ("[recurse(if . < 3 then . + 1 else empty end)]", "0"),
+    ("label $f | range(10) | ., break $f", "null"),
+    (
+        "try error(\"x\") catch ., [.[] | try tonumber catch -1]",
+        r#"["1", "a"]"#,
+    ),
+    ("[.[] // 0], (null // false // 1)", "[null, false, 2]"),
+    ("if . then 1 elif . == false then 2 else 3 end", "false"),
+    // strings
+    ("ascii_downcase, ascii_upcase", r#""aBc""#),
+    ("length, utf8bytelength", r#""äb""#),
+    ("split(\", \"), join(\"-\")?", r#""a, b, c""#),
+    (
+        r#"ltrimstr("a"), rtrimstr("c"), startswith("ab"), endswith("c")"#,
+        r#""abc""#,
+    ),
+    (r#""x\(. + 1)y", tostring, tojson"#, "1"),
+    ("explode, (explode | implode)", r#""aä""#),
+    ("@base64, (@base64 | @base64d), @uri, @html", r#""<a b&c>""#),
+    ("@csv, @tsv", r#"[1, "a\"b", null]"#),
+    (
+        "test(\"a.c\"), [match(\"b+\"; \"g\") | .offset]",
+        r#""abcbb""#,
+    ),
+    ("sub(\"b\"; \"x\"), gsub(\"b\"; \"x\")", r#""abcb""#),
+    ("tostring, (tostring | fromjson)", r#"{"a": [1, "b"]}"#),
+    ("indices(\"b\"), index(\"b\"), rindex(\"b\")", r#""abcb""#),
+];
+
+/// Run a binary with the given filter and input, returning its output if it succeeded.
+fn run(bin: &str, filter: &str, input: &str) -> Option<String> {
+    let mut child = process::Command::new(bin)
+        .args(["-c", filter])
+        .stdin(process::Stdio::piped())
+        .stdout(process::Stdio::piped())
+        .stderr(process::Stdio::null())
+        .spawn()
+        .unwrap_or_else(|e| panic!("could not run {bin}: {e}"));
+    let mut stdin = child.stdin.take().unwrap();
+    stdin.write_all(input.as_bytes()).unwrap();
+    drop(stdin);
+    let output = child.wait_with_output().unwrap();
+    let stdout = str::from_utf8(&output.stdout).expect("invalid UTF-8 in output");
+    // remove '\r' from output for compatibility with Windows
+    let stdout = stdout.replace('\r', "");
+    output.status.success().then_some(stdout)
+}
+
+#[test]
+fn compare() {
+    let jq = match env::var("JAQ_COMPARE_JQ") {
+        Ok(jq) => jq,
+        Err(_) => return,
+    };
+    let jaq = env!("CARGO_BIN_EXE_jaq");
+    let diverging = CASES.iter().filter(|(filter, input)| {
+        let (jaq_out, jq_out) = (run(jaq, filter, input), run(&jq, filter, input));
+        if jaq_out != jq_out {
+            println!("filter:  {filter}\ninput:   {input}");
+            println!("jaq:     {jaq_out:?}\njq:      {jq_out:?}\n---");
+        }
+        jaq_out != jq_out
+    });
+    assert_eq!(diverging.count(), 0);
+}